@@ -0,0 +1,1133 @@
+#![allow(dead_code)]
+use crate::inst::Instruction;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+static ORIGIN: usize = 512;
+static MAX_SIZE: usize = 4096;
+static MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnexpectedEndOfLine,
+    UnterminatedString,
+    InvalidNumber(String),
+    UnknownMnemonic(String),
+    InvalidOperands(String),
+    UndefinedSymbol(String),
+    DuplicateSymbol(String),
+    RecursiveSymbol(String),
+    ValueOutOfRange { value: i64, min: i64, max: i64 },
+    DivisionByZero,
+    Overflow,
+    AddressOutOfRange(i64),
+    OverlappingOutput(usize),
+    IncludeFailed { path: String, reason: String },
+    IncludeTooDeep,
+    InvalidSymbolFile,
+    InvalidSourceMap,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: ", self.file, self.line, self.column)?;
+        match &self.kind {
+            ErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{c}'"),
+            ErrorKind::UnexpectedToken(t) => write!(f, "unexpected '{t}'"),
+            ErrorKind::UnexpectedEndOfLine => write!(f, "unexpected end of line"),
+            ErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            ErrorKind::InvalidNumber(n) => write!(f, "invalid number '{n}'"),
+            ErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic '{m}'"),
+            ErrorKind::InvalidOperands(m) => write!(f, "invalid operands for '{m}'"),
+            ErrorKind::UndefinedSymbol(s) => write!(f, "undefined symbol '{s}'"),
+            ErrorKind::DuplicateSymbol(s) => write!(f, "symbol '{s}' is already defined"),
+            ErrorKind::RecursiveSymbol(s) => write!(f, "symbol '{s}' is defined in terms of itself"),
+            ErrorKind::ValueOutOfRange { value, min, max } => {
+                write!(f, "value {value} is out of range ({min}..={max})")
+            }
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::AddressOutOfRange(a) => write!(f, "address {a:#x} is outside of program memory"),
+            ErrorKind::OverlappingOutput(a) => write!(f, "address {a:#05x} is written more than once"),
+            ErrorKind::IncludeFailed { path, reason } => write!(f, "cannot include '{path}': {reason}"),
+            ErrorKind::IncludeTooDeep => write!(f, "includes nested too deeply"),
+            ErrorKind::InvalidSymbolFile => write!(f, "invalid symbol file entry"),
+            ErrorKind::InvalidSourceMap => write!(f, "invalid source map entry"),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Loc {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Punct(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Lexeme {
+    token: Token,
+    column: usize,
+}
+
+static PUNCTUATION: [&str; 18] = [
+    "<<", ">>", ",", ":", "[", "]", "(", ")", "+", "-", "*", "/", "%", "&", "|", "^", "~", "=",
+];
+
+fn tokenize(line: &str) -> Result<Vec<Lexeme>, (usize, ErrorKind)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Lexeme {
+                token: Token::Ident(chars[start..i].iter().collect()),
+                column,
+            });
+        } else if c.is_ascii_digit() || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = parse_number(&text).ok_or((column, ErrorKind::InvalidNumber(text.clone())))?;
+            tokens.push(Lexeme {
+                token: Token::Number(value),
+                column,
+            });
+        } else if c == '"' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err((column, ErrorKind::UnterminatedString));
+            }
+            tokens.push(Lexeme {
+                token: Token::Str(chars[start..i].iter().collect()),
+                column,
+            });
+            i += 1;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match PUNCTUATION.iter().find(|p| rest.starts_with(**p)) {
+                Some(p) => {
+                    tokens.push(Lexeme {
+                        token: Token::Punct(p),
+                        column,
+                    });
+                    i += p.len();
+                }
+                None => return Err((column, ErrorKind::UnexpectedCharacter(c))),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.replace('_', "");
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x").or(lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Symbol(String, Loc),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>, Loc),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Bcd,
+    Value(Expr, Loc),
+}
+
+#[derive(Debug, Clone)]
+enum StatementKind {
+    Instruction { mnemonic: String, operands: Vec<Operand> },
+    Bytes(Vec<(Expr, Loc)>),
+    Words(Vec<(Expr, Loc)>),
+}
+
+#[derive(Debug, Clone)]
+struct Statement {
+    loc: Loc,
+    addr: usize,
+    kind: StatementKind,
+}
+
+impl StatementKind {
+    fn size(&self) -> usize {
+        match self {
+            StatementKind::Instruction { .. } => 2,
+            StatementKind::Bytes(bs) => bs.len(),
+            StatementKind::Words(ws) => ws.len() * 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Label,
+    Constant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u16,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    pub symbols: Vec<Symbol>,
+}
+
+impl Symbols {
+    pub fn parse(file: &str, text: &str) -> Result<Self, AsmError> {
+        let mut symbols = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = || AsmError {
+                file: file.to_string(),
+                line: n + 1,
+                column: 1,
+                kind: ErrorKind::InvalidSymbolFile,
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(err());
+            }
+            let kind = match fields[0] {
+                "label" => SymbolKind::Label,
+                "const" => SymbolKind::Constant,
+                _ => return Err(err()),
+            };
+            let value = parse_number(fields[1])
+                .filter(|v| (0..=0xffff).contains(v))
+                .ok_or_else(err)?;
+            symbols.push(Symbol {
+                name: fields[2].to_string(),
+                value: value as u16,
+                kind,
+            });
+        }
+        Ok(Self { symbols })
+    }
+
+    pub fn load(p: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(p)?;
+        Ok(Self::parse(&p.display().to_string(), &text)?)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    pub fn label_at(&self, addr: u16) -> Option<&str> {
+        self.labels().find(|s| s.value == addr).map(|s| s.name.as_str())
+    }
+
+    // closest label at or below addr, along with the offset from it
+    pub fn label_before(&self, addr: u16) -> Option<(&str, u16)> {
+        self.labels()
+            .filter(|s| s.value <= addr)
+            .max_by_key(|s| s.value)
+            .map(|s| (s.name.as_str(), addr - s.value))
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter().filter(|s| s.kind == SymbolKind::Label)
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for s in self.symbols.iter() {
+            let kind = match s.kind {
+                SymbolKind::Label => "label",
+                SymbolKind::Constant => "const",
+            };
+            writeln!(f, "{} {:#06x} {}", kind, s.value, s.name)?;
+        }
+        Ok(())
+    }
+}
+
+// the source line each instruction was assembled from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub lines: Vec<SourceLine>,
+}
+
+impl SourceMap {
+    pub fn parse(file: &str, text: &str) -> Result<Self, AsmError> {
+        let mut lines = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = || AsmError {
+                file: file.to_string(),
+                line: n + 1,
+                column: 1,
+                kind: ErrorKind::InvalidSourceMap,
+            };
+            // file names may contain spaces and colons, line numbers don't
+            let (addr, location) = line.split_once(' ').ok_or_else(err)?;
+            let (source, number) = location.rsplit_once(':').ok_or_else(err)?;
+            let addr = parse_number(addr)
+                .filter(|v| (0..MAX_SIZE as i64).contains(v))
+                .ok_or_else(err)?;
+            lines.push(SourceLine {
+                addr: addr as u16,
+                file: source.to_string(),
+                line: number.parse().map_err(|_| err())?,
+            });
+        }
+        Ok(Self { lines })
+    }
+
+    pub fn load(p: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(p)?;
+        Ok(Self::parse(&p.display().to_string(), &text)?)
+    }
+
+    pub fn lookup(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.iter().find(|l| l.addr == addr)
+    }
+
+    // the first instruction at or after `line` of `file`, along with the line it is on
+    pub fn address_of(&self, file: &Path, line: usize) -> Option<(u16, usize)> {
+        self.lines
+            .iter()
+            .filter(|l| same_file(Path::new(&l.file), file) && l.line >= line)
+            .min_by_key(|l| (l.line, l.addr))
+            .map(|l| (l.addr, l.line))
+    }
+}
+
+// the map holds paths as given to the assembler, which may be relative to where it ran
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.ends_with(b) || b.ends_with(a),
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for l in self.lines.iter() {
+            writeln!(f, "{:#06x} {}:{}", l.addr, l.file, l.line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub origin: usize,
+    pub bytes: Vec<u8>,
+    pub symbols: Symbols,
+    pub lines: SourceMap,
+}
+
+enum Definition {
+    Label(usize),
+    Constant(Expr),
+}
+
+struct Assembler {
+    files: Vec<String>,
+    statements: Vec<Statement>,
+    definitions: HashMap<String, (Definition, Loc)>,
+    order: Vec<String>,
+    addr: usize,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            files: Vec::new(),
+            statements: Vec::new(),
+            definitions: HashMap::new(),
+            order: Vec::new(),
+            addr: ORIGIN,
+        }
+    }
+
+    fn error(&self, loc: &Loc, kind: ErrorKind) -> AsmError {
+        AsmError {
+            file: self.files[loc.file].clone(),
+            line: loc.line,
+            column: loc.column,
+            kind,
+        }
+    }
+
+    fn located(&self, loc: &Loc, (column, kind): (usize, ErrorKind)) -> AsmError {
+        self.error(&Loc { column, ..loc.clone() }, kind)
+    }
+
+    fn add_file(&mut self, name: &str, source: &str, dir: &Path, depth: usize) -> Result<(), AsmError> {
+        let file = self.files.len();
+        self.files.push(name.to_string());
+        for (n, line) in source.lines().enumerate() {
+            let loc = Loc {
+                file,
+                line: n + 1,
+                column: 1,
+            };
+            let tokens = tokenize(line).map_err(|e| self.located(&loc, e))?;
+            let mut parser = LineParser {
+                tokens: &tokens,
+                pos: 0,
+                loc: loc.clone(),
+            };
+            if let Some((path, column)) = self.add_line(&mut parser)? {
+                self.include(&dir.join(path), depth, &Loc { column, ..loc })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, def: Definition, loc: Loc) -> Result<(), AsmError> {
+        if self.definitions.contains_key(name) || parse_register(name).is_some() {
+            return Err(self.error(&loc, ErrorKind::DuplicateSymbol(name.to_string())));
+        }
+        self.definitions.insert(name.to_string(), (def, loc));
+        self.order.push(name.to_string());
+        Ok(())
+    }
+
+    // returns the path and column of an include directive, which the caller resolves
+    fn add_line(&mut self, p: &mut LineParser) -> Result<Option<(String, usize)>, AsmError> {
+        // `name:` defines a label, `name = expr` and `name equ expr` define constants
+        if let (Some(Token::Ident(name)), Some(Token::Punct(":"))) = (p.peek(0), p.peek(1)) {
+            let loc = p.loc_at(0);
+            p.pos += 2;
+            self.define(&name, Definition::Label(self.addr), loc)?;
+        }
+        let Some(Token::Ident(word)) = p.peek(0) else {
+            return match p.peek(0) {
+                None => Ok(None),
+                Some(_) => Err(self.located(&p.loc, p.unexpected())),
+            };
+        };
+        let is_assignment = matches!(p.peek(1), Some(Token::Punct("=")))
+            || matches!(p.peek(1), Some(Token::Ident(ref s)) if s.eq_ignore_ascii_case("equ"));
+        if is_assignment {
+            let loc = p.loc_at(0);
+            p.pos += 2;
+            let expr = p
+                .expr()
+                .and_then(|e| p.end().map(|_| e))
+                .map_err(|e| self.located(&loc, e))?;
+            self.define(&word, Definition::Constant(expr), loc)?;
+            return Ok(None);
+        }
+
+        let loc = p.loc_at(0);
+        p.pos += 1;
+        let kind = match word.to_ascii_lowercase().as_str() {
+            "org" => {
+                let expr = p
+                    .expr()
+                    .and_then(|e| p.end().map(|_| e))
+                    .map_err(|e| self.located(&loc, e))?;
+                let addr = self.evaluate(&expr, &mut Vec::new())?;
+                if !(ORIGIN as i64..MAX_SIZE as i64).contains(&addr) {
+                    return Err(self.error(&loc, ErrorKind::AddressOutOfRange(addr)));
+                }
+                self.addr = addr as usize;
+                return Ok(None);
+            }
+            "include" => {
+                let column = p.column();
+                let Some(Token::Str(path)) = p.peek(0) else {
+                    return Err(self.located(&loc, p.unexpected()));
+                };
+                p.pos += 1;
+                p.end().map_err(|e| self.located(&loc, e))?;
+                return Ok(Some((path, column)));
+            }
+            "db" => StatementKind::Bytes(p.data(true).map_err(|e| self.located(&loc, e))?),
+            "dw" => StatementKind::Words(p.data(false).map_err(|e| self.located(&loc, e))?),
+            _ => StatementKind::Instruction {
+                mnemonic: word.to_ascii_lowercase(),
+                operands: p.operands().map_err(|e| self.located(&loc, e))?,
+            },
+        };
+        let size = kind.size();
+        self.statements.push(Statement {
+            loc,
+            addr: self.addr,
+            kind,
+        });
+        self.addr += size;
+        Ok(None)
+    }
+
+    fn include(&mut self, path: &Path, depth: usize, loc: &Loc) -> Result<(), AsmError> {
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(self.error(loc, ErrorKind::IncludeTooDeep));
+        }
+        let source = fs::read_to_string(path).map_err(|e| {
+            self.error(
+                loc,
+                ErrorKind::IncludeFailed {
+                    path: path.display().to_string(),
+                    reason: e.to_string(),
+                },
+            )
+        })?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.add_file(&path.display().to_string(), &source, &dir, depth + 1)
+    }
+
+    fn evaluate(&self, expr: &Expr, visiting: &mut Vec<String>) -> Result<i64, AsmError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Symbol(name, loc) => match self.definitions.get(name) {
+                Some((Definition::Label(addr), _)) => Ok(*addr as i64),
+                Some((Definition::Constant(e), _)) => {
+                    if visiting.contains(name) {
+                        return Err(self.error(loc, ErrorKind::RecursiveSymbol(name.clone())));
+                    }
+                    visiting.push(name.clone());
+                    let v = self.evaluate(e, visiting);
+                    visiting.pop();
+                    v
+                }
+                None => Err(self.error(loc, ErrorKind::UndefinedSymbol(name.clone()))),
+            },
+            Expr::Unary(op, e) => {
+                let v = self.evaluate(e, visiting)?;
+                Ok(match *op {
+                    "-" => v.wrapping_neg(),
+                    _ => !v,
+                })
+            }
+            Expr::Binary(op, a, b, loc) => {
+                let a_v = self.evaluate(a, visiting)?;
+                let b_v = self.evaluate(b, visiting)?;
+                match *op {
+                    "+" => Ok(a_v.wrapping_add(b_v)),
+                    "-" => Ok(a_v.wrapping_sub(b_v)),
+                    "*" => Ok(a_v.wrapping_mul(b_v)),
+                    "/" | "%" if b_v == 0 => Err(self.error(loc, ErrorKind::DivisionByZero)),
+                    // the only quotient that doesn't fit is of the smallest number by -1
+                    "/" => a_v.checked_div(b_v).ok_or_else(|| self.error(loc, ErrorKind::Overflow)),
+                    "%" => a_v.checked_rem(b_v).ok_or_else(|| self.error(loc, ErrorKind::Overflow)),
+                    "&" => Ok(a_v & b_v),
+                    "|" => Ok(a_v | b_v),
+                    "^" => Ok(a_v ^ b_v),
+                    "<<" => Ok(a_v.wrapping_shl(b_v as u32)),
+                    _ => Ok(a_v.wrapping_shr(b_v as u32)),
+                }
+            }
+        }
+    }
+
+    fn value(&self, expr: &Expr, loc: &Loc, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.evaluate(expr, &mut Vec::new())?;
+        if value < min || value > max {
+            return Err(self.error(loc, ErrorKind::ValueOutOfRange { value, min, max }));
+        }
+        Ok(value)
+    }
+
+    fn operand_value(&self, op: &Operand, min: i64, max: i64) -> Option<Result<i64, AsmError>> {
+        match op {
+            Operand::Value(expr, loc) => Some(self.value(expr, loc, min, max)),
+            _ => None,
+        }
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand], loc: &Loc) -> Result<Instruction, AsmError> {
+        use Operand::*;
+        let addr = |op: &Operand| self.operand_value(op, 0, 0xfff).map(|r| r.map(|v| v as u16));
+        let byte = |op: &Operand| self.operand_value(op, -128, 0xff).map(|r| r.map(|v| v as u8));
+        let nibble = |op: &Operand| self.operand_value(op, 0, 0xf).map(|r| r.map(|v| v as u8));
+
+        let inst = match (mnemonic, operands) {
+            ("cls", []) => Instruction::ClearDisplay,
+            ("ret", []) => Instruction::Return,
+            ("sys", [a]) if addr(a).is_some() => Instruction::Call {
+                addr: addr(a).unwrap()?,
+            },
+            ("jp", [Register(0), a]) if addr(a).is_some() => Instruction::GotoPlusV0 {
+                addr: addr(a).unwrap()?,
+            },
+            ("jp", [a]) if addr(a).is_some() => Instruction::Goto {
+                addr: addr(a).unwrap()?,
+            },
+            ("call", [a]) if addr(a).is_some() => Instruction::CallSubroutine {
+                addr: addr(a).unwrap()?,
+            },
+            ("se", [Register(x), Register(y)]) => Instruction::SkipIfRegistersEqual {
+                register_1: *x,
+                register_2: *y,
+            },
+            ("se", [Register(x), b]) if byte(b).is_some() => Instruction::SkipIfRegisterEquals {
+                register: *x,
+                value: byte(b).unwrap()?,
+            },
+            ("sne", [Register(x), Register(y)]) => Instruction::SkipIfRegistersNotEqual {
+                register_1: *x,
+                register_2: *y,
+            },
+            ("sne", [Register(x), b]) if byte(b).is_some() => Instruction::SkipIfRegisterNotEquals {
+                register: *x,
+                value: byte(b).unwrap()?,
+            },
+            ("ld", [Register(x), Register(y)]) => Instruction::CopyRegister {
+                src_register: *y,
+                dst_register: *x,
+            },
+            ("ld", [Register(x), DelayTimer]) => Instruction::GetDelayTimer { register: *x },
+            ("ld", [Register(x), Key]) => Instruction::GetKey { register: *x },
+            ("ld", [Register(x), IndirectI]) => Instruction::LoadRegisters { end_register: *x },
+            ("ld", [Register(x), b]) if byte(b).is_some() => Instruction::SetRegister {
+                register: *x,
+                value: byte(b).unwrap()?,
+            },
+            ("ld", [I, a]) if addr(a).is_some() => Instruction::SetI {
+                addr: addr(a).unwrap()?,
+            },
+            ("ld", [DelayTimer, Register(x)]) => Instruction::SetDelayTimer { register: *x },
+            ("ld", [SoundTimer, Register(x)]) => Instruction::SetSoundTimer { register: *x },
+            ("ld", [Font, Register(x)]) => Instruction::SetIToFontSprite { register: *x },
+            ("ld", [Bcd, Register(x)]) => Instruction::StoreBcd { register: *x },
+            ("ld", [IndirectI, Register(x)]) => Instruction::DumpRegisters { end_register: *x },
+            ("add", [I, Register(x)]) => Instruction::AddToI { register: *x },
+            ("add", [Register(x), Register(y)]) => Instruction::AddRegisters {
+                value_register: *x,
+                operand_register: *y,
+            },
+            ("add", [Register(x), b]) if byte(b).is_some() => Instruction::AddToRegister {
+                register: *x,
+                value: byte(b).unwrap()?,
+            },
+            ("or" | "and" | "xor" | "sub" | "subn", [Register(x), Register(y)]) => {
+                let (value_register, operand_register) = (*x, *y);
+                match mnemonic {
+                    "or" => Instruction::ApplyBitwiseOr {
+                        value_register,
+                        operand_register,
+                    },
+                    "and" => Instruction::ApplyBitwiseAnd {
+                        value_register,
+                        operand_register,
+                    },
+                    "xor" => Instruction::ApplyBitwiseXor {
+                        value_register,
+                        operand_register,
+                    },
+                    "sub" => Instruction::SubtractRegisters {
+                        value_register,
+                        operand_register,
+                    },
+                    _ => Instruction::SubtractRegistersReversed {
+                        value_register,
+                        operand_register,
+                    },
+                }
+            }
+            ("shr" | "shl", [Register(x)]) | ("shr" | "shl", [Register(x), Register(_)]) => {
+                // the single operand form shifts Vx in place
+                let operand_register = match operands {
+                    [_, Register(y)] => *y,
+                    _ => *x,
+                };
+                if mnemonic == "shr" {
+                    Instruction::ShiftRight {
+                        value_register: *x,
+                        operand_register,
+                    }
+                } else {
+                    Instruction::ShiftLeft {
+                        value_register: *x,
+                        operand_register,
+                    }
+                }
+            }
+            ("rnd", [Register(x), b]) if byte(b).is_some() => Instruction::SetRegisterRandomBitwiseAnd {
+                register: *x,
+                and_operand: byte(b).unwrap()?,
+            },
+            ("drw", [Register(x), Register(y), n]) if nibble(n).is_some() => Instruction::Draw {
+                reg_x: *x,
+                reg_y: *y,
+                sprite_height: nibble(n).unwrap()?,
+            },
+            ("skp", [Register(x)]) => Instruction::SkipIfKeyPressed { register: *x },
+            ("sknp", [Register(x)]) => Instruction::SkipIfKeyNotPressed { register: *x },
+            (
+                "cls" | "ret" | "sys" | "jp" | "call" | "se" | "sne" | "ld" | "add" | "or" | "and" | "xor" | "sub"
+                | "subn" | "shr" | "shl" | "rnd" | "drw" | "skp" | "sknp",
+                _,
+            ) => return Err(self.error(loc, ErrorKind::InvalidOperands(mnemonic.to_string()))),
+            _ => return Err(self.error(loc, ErrorKind::UnknownMnemonic(mnemonic.to_string()))),
+        };
+        Ok(inst)
+    }
+
+    fn link(&self) -> Result<Program, AsmError> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut written = [false; MAX_SIZE];
+        let mut lines = Vec::new();
+        for stmt in self.statements.iter() {
+            let out: Vec<u8> = match &stmt.kind {
+                StatementKind::Instruction { mnemonic, operands } => {
+                    lines.push(SourceLine {
+                        addr: stmt.addr as u16,
+                        file: self.files[stmt.loc.file].clone(),
+                        line: stmt.loc.line,
+                    });
+                    self.instruction(mnemonic, operands, &stmt.loc)?.encode().to_vec()
+                }
+                StatementKind::Bytes(bs) => bs
+                    .iter()
+                    .map(|(e, loc)| self.value(e, loc, -128, 0xff).map(|v| v as u8))
+                    .collect::<Result<_, _>>()?,
+                StatementKind::Words(ws) => {
+                    let mut out = Vec::new();
+                    for (e, loc) in ws.iter() {
+                        let v = self.value(e, loc, -0x8000, 0xffff)? as u16;
+                        out.extend_from_slice(&v.to_be_bytes());
+                    }
+                    out
+                }
+            };
+            for (offset, b) in out.iter().enumerate() {
+                let addr = stmt.addr + offset;
+                if addr >= MAX_SIZE {
+                    return Err(self.error(&stmt.loc, ErrorKind::AddressOutOfRange(addr as i64)));
+                }
+                if written[addr] {
+                    return Err(self.error(&stmt.loc, ErrorKind::OverlappingOutput(addr)));
+                }
+                written[addr] = true;
+                if bytes.len() <= addr - ORIGIN {
+                    bytes.resize(addr - ORIGIN + 1, 0);
+                }
+                bytes[addr - ORIGIN] = *b;
+            }
+        }
+
+        let mut symbols = Vec::new();
+        for name in self.order.iter() {
+            let (def, loc) = &self.definitions[name];
+            let (value, kind) = match def {
+                Definition::Label(addr) => (*addr as i64, SymbolKind::Label),
+                Definition::Constant(e) => (self.evaluate(e, &mut Vec::new())?, SymbolKind::Constant),
+            };
+            if kind == SymbolKind::Label && value >= MAX_SIZE as i64 {
+                return Err(self.error(loc, ErrorKind::AddressOutOfRange(value)));
+            }
+            symbols.push(Symbol {
+                name: name.clone(),
+                value: value as u16,
+                kind,
+            });
+        }
+
+        Ok(Program {
+            origin: ORIGIN,
+            bytes,
+            symbols: Symbols { symbols },
+            lines: SourceMap { lines },
+        })
+    }
+}
+
+fn token_text(t: &Token) -> String {
+    match t {
+        Token::Ident(s) => s.clone(),
+        Token::Number(n) => n.to_string(),
+        Token::Str(s) => format!("\"{s}\""),
+        Token::Punct(p) => p.to_string(),
+    }
+}
+
+fn parse_register(name: &str) -> Option<u8> {
+    let lower = name.to_ascii_lowercase();
+    let digit = lower.strip_prefix('v')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+struct LineParser<'a> {
+    tokens: &'a [Lexeme],
+    pos: usize,
+    loc: Loc,
+}
+
+// binary operators by precedence, loosest first
+static BINARY_OPERATORS: [&[&str]; 5] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"]];
+static MULTIPLICATIVE_OPERATORS: [&str; 3] = ["*", "/", "%"];
+
+impl LineParser<'_> {
+    fn peek(&self, offset: usize) -> Option<Token> {
+        self.tokens.get(self.pos + offset).map(|l| l.token.clone())
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.peek(0);
+        self.pos += 1;
+        t
+    }
+
+    fn column(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some(l) => l.column,
+            None => self
+                .tokens
+                .last()
+                .map(|l| l.column + token_text(&l.token).len())
+                .unwrap_or(1),
+        }
+    }
+
+    fn loc_at(&self, offset: usize) -> Loc {
+        Loc {
+            column: self.tokens.get(self.pos + offset).map(|l| l.column).unwrap_or(1),
+            ..self.loc.clone()
+        }
+    }
+
+    fn unexpected(&self) -> (usize, ErrorKind) {
+        match self.peek(0) {
+            Some(t) => (self.column(), ErrorKind::UnexpectedToken(token_text(&t))),
+            None => (self.column(), ErrorKind::UnexpectedEndOfLine),
+        }
+    }
+
+    fn end(&self) -> Result<(), (usize, ErrorKind)> {
+        match self.peek(0) {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn expect(&mut self, p: &str) -> Result<(), (usize, ErrorKind)> {
+        match self.peek(0) {
+            Some(Token::Punct(q)) if q == p => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn accept(&mut self, p: &str) -> bool {
+        let found = matches!(self.peek(0), Some(Token::Punct(q)) if q == p);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expr(&mut self) -> Result<Expr, (usize, ErrorKind)> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, (usize, ErrorKind)> {
+        if level == BINARY_OPERATORS.len() {
+            return self.multiplicative();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(Token::Punct(p)) = self.peek(0) {
+            if !BINARY_OPERATORS[level].contains(&p) {
+                break;
+            }
+            let loc = self.loc_at(0);
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(p, Box::new(lhs), Box::new(rhs), loc);
+        }
+        Ok(lhs)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, (usize, ErrorKind)> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Punct(p)) = self.peek(0) {
+            if !MULTIPLICATIVE_OPERATORS.contains(&p) {
+                break;
+            }
+            let loc = self.loc_at(0);
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(p, Box::new(lhs), Box::new(rhs), loc);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, (usize, ErrorKind)> {
+        let loc = self.loc_at(0);
+        match self.peek(0) {
+            Some(Token::Punct(p @ ("-" | "~"))) => {
+                self.pos += 1;
+                Ok(Expr::Unary(p, Box::new(self.unary()?)))
+            }
+            Some(Token::Punct("(")) => {
+                self.pos += 1;
+                let e = self.expr()?;
+                self.expect(")")?;
+                Ok(e)
+            }
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::Ident(name)) if parse_register(&name).is_none() => {
+                self.pos += 1;
+                Ok(Expr::Symbol(name, loc))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, (usize, ErrorKind)> {
+        if self.accept("[") {
+            match self.next() {
+                Some(Token::Ident(name)) if name.eq_ignore_ascii_case("i") => {}
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected());
+                }
+            }
+            self.expect("]")?;
+            return Ok(Operand::IndirectI);
+        }
+        if let Some(Token::Ident(name)) = self.peek(0) {
+            let reserved = match name.to_ascii_lowercase().as_str() {
+                "i" => Some(Operand::I),
+                "dt" => Some(Operand::DelayTimer),
+                "st" => Some(Operand::SoundTimer),
+                "k" => Some(Operand::Key),
+                "f" => Some(Operand::Font),
+                "b" => Some(Operand::Bcd),
+                _ => parse_register(&name).map(Operand::Register),
+            };
+            if let Some(op) = reserved {
+                self.pos += 1;
+                return Ok(op);
+            }
+        }
+        let loc = self.loc_at(0);
+        Ok(Operand::Value(self.expr()?, loc))
+    }
+
+    fn operands(&mut self) -> Result<Vec<Operand>, (usize, ErrorKind)> {
+        let mut operands = Vec::new();
+        if self.peek(0).is_none() {
+            return Ok(operands);
+        }
+        loop {
+            operands.push(self.operand()?);
+            if !self.accept(",") {
+                break;
+            }
+        }
+        self.end()?;
+        Ok(operands)
+    }
+
+    // comma separated expressions, db additionally accepts strings as a sequence of bytes
+    fn data(&mut self, allow_strings: bool) -> Result<Vec<(Expr, Loc)>, (usize, ErrorKind)> {
+        let mut values = Vec::new();
+        loop {
+            let loc = self.loc_at(0);
+            match self.peek(0) {
+                Some(Token::Str(s)) if allow_strings => {
+                    self.pos += 1;
+                    values.extend(s.bytes().map(|b| (Expr::Number(b as i64), loc.clone())));
+                }
+                _ => values.push((self.expr()?, loc)),
+            }
+            if !self.accept(",") {
+                break;
+            }
+        }
+        self.end()?;
+        Ok(values)
+    }
+}
+
+pub fn assemble_source(name: &str, source: &str, dir: &Path) -> Result<Program, AsmError> {
+    let mut asm = Assembler::new();
+    asm.add_file(name, source, dir, 0)?;
+    asm.link()
+}
+
+pub fn assemble(p: &Path) -> Result<Program, Box<dyn Error>> {
+    let source = fs::read_to_string(p)?;
+    let dir = p.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok(assemble_source(&p.display().to_string(), &source, &dir)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            WIDTH = 64
+            HEIGHT equ WIDTH / 2   ; constants may reference each other
+            start:
+                cls
+                ld i, sprite
+                ld v0, WIDTH - 8
+                ld v1, (HEIGHT - 5) / 2
+                drw v0, v1, 5
+                call sub
+                jp start
+            sub:
+                add v0, -1
+                se v0, v1
+                ld [i], v1
+                ret
+            sprite:
+                db 0xf0, 0x90, 0x90, 0x90, 0xf0
+                dw end
+            end:
+        ";
+        let program = assemble_source("test.s", source, Path::new(".")).unwrap();
+        assert_eq!(
+            program.bytes,
+            vec![
+                0x00, 0xe0, // cls
+                0xa2, 0x16, // ld i, sprite
+                0x60, 0x38, // ld v0, 56
+                0x61, 0x0d, // ld v1, 13
+                0xd0, 0x15, // drw v0, v1, 5
+                0x22, 0x0e, // call sub
+                0x12, 0x00, // jp start
+                0x70, 0xff, // add v0, -1
+                0x50, 0x10, // se v0, v1
+                0xf1, 0x55, // ld [i], v1
+                0x00, 0xee, // ret
+                0xf0, 0x90, 0x90, 0x90, 0xf0, // sprite
+                0x02, 0x1d, // dw end
+            ]
+        );
+        assert_eq!(program.symbols.lookup("sprite").unwrap().value, 0x216);
+        assert_eq!(program.symbols.lookup("HEIGHT").unwrap().value, 32);
+        assert_eq!(program.symbols.label_before(0x210), Some(("sub", 2)));
+
+        let reloaded = Symbols::parse("test.sym", &program.symbols.to_string()).unwrap();
+        assert_eq!(reloaded.symbols, program.symbols.symbols);
+
+        // data isn't mapped, labels map to the instruction after them
+        assert_eq!(program.lines.lines.len(), 11);
+        assert_eq!(program.lines.lookup(0x210).map(|l| l.line), Some(14));
+        assert_eq!(program.lines.address_of(Path::new("test.s"), 12), Some((0x20e, 13)));
+        let reloaded = SourceMap::parse("test.map", &program.lines.to_string()).unwrap();
+        assert_eq!(reloaded.lines, program.lines.lines);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |source: &str| assemble_source("test.s", source, Path::new(".")).unwrap_err();
+
+        let e = err("  cls\n  jp nowhere");
+        assert_eq!((e.line, e.column), (2, 6));
+        assert_eq!(e.kind, ErrorKind::UndefinedSymbol("nowhere".to_string()));
+
+        let e = err("ld v0, 256");
+        assert_eq!((e.line, e.column), (1, 8));
+        assert!(matches!(e.kind, ErrorKind::ValueOutOfRange { value: 256, .. }));
+
+        let e = err("a: cls\na: cls");
+        assert_eq!((e.line, e.column), (2, 1));
+        assert_eq!(e.kind, ErrorKind::DuplicateSymbol("a".to_string()));
+
+        let e = err("  mov v0, v1");
+        assert_eq!(e.kind, ErrorKind::UnknownMnemonic("mov".to_string()));
+        assert_eq!(e.to_string(), "test.s:1:3: unknown mnemonic 'mov'");
+
+        assert_eq!(
+            err("x = y\ny = x\nld v0, x").kind,
+            ErrorKind::RecursiveSymbol("x".to_string())
+        );
+        assert_eq!(err("cls\norg 0x200\ncls").kind, ErrorKind::OverlappingOutput(0x200));
+        assert_eq!(err("drw v0, v1").kind, ErrorKind::InvalidOperands("drw".to_string()));
+        assert_eq!(err("db 1 / (2 - 2)").kind, ErrorKind::DivisionByZero);
+        let min = "(0 - 9223372036854775807 - 1)";
+        assert_eq!(err(&format!("db {min} / -1")).kind, ErrorKind::Overflow);
+        assert_eq!(err(&format!("db {min} % -1")).kind, ErrorKind::Overflow);
+        // wraps around to itself, out of range of a byte rather than a panic
+        assert!(matches!(
+            err(&format!("db -{min}")).kind,
+            ErrorKind::ValueOutOfRange { .. }
+        ));
+    }
+}
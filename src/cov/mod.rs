@@ -0,0 +1,270 @@
+#![allow(dead_code)]
+use crate::asm::{SourceMap, Symbols};
+use crate::dbg::parse_number;
+use crate::decomp;
+use crate::inst::Instruction;
+use crate::mem::AccessKind;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+static ORIGIN: usize = 512;
+static DATA_PER_LINE: usize = 8;
+
+// what happened to each byte of a rom during a run
+pub struct Coverage {
+    pub rom: Vec<u8>,
+    // AccessKind bits for each byte of the rom
+    pub flags: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub executed: usize,
+    // read and never executed, sprites and loaded registers
+    pub data: usize,
+    pub written: usize,
+    pub untouched: usize,
+}
+
+enum Item {
+    Code(Instruction),
+    Data(usize),
+}
+
+fn marks(flags: u8) -> String {
+    [
+        (AccessKind::Execute, 'x'),
+        (AccessKind::Read, 'r'),
+        (AccessKind::Write, 'w'),
+    ]
+    .iter()
+    .filter(|(kind, _)| flags & kind.bit() != 0)
+    .map(|(_, c)| *c)
+    .collect()
+}
+
+impl Coverage {
+    // `flags` covers all of memory, as collected by mem::Memory
+    pub fn new(rom: &[u8], flags: &[u8]) -> Self {
+        Coverage {
+            rom: rom.to_vec(),
+            flags: flags[ORIGIN..ORIGIN + rom.len()].to_vec(),
+        }
+    }
+
+    fn is(&self, offset: usize, kind: AccessKind) -> bool {
+        self.flags[offset] & kind.bit() != 0
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for offset in 0..self.rom.len() {
+            if self.is(offset, AccessKind::Execute) {
+                summary.executed += 1;
+            } else if self.is(offset, AccessKind::Read) {
+                summary.data += 1;
+            }
+            if self.is(offset, AccessKind::Write) {
+                summary.written += 1;
+            }
+            if self.flags[offset] == 0 {
+                summary.untouched += 1;
+            }
+        }
+        summary
+    }
+
+    // instructions where control flow reaches statically or where execution went, data everywhere else.
+    // data runs break where the kind of access changes.
+    fn items(&self) -> Vec<(usize, Item)> {
+        let code = decomp::trace(&self.rom);
+        let mut items = Vec::new();
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let addr = ORIGIN + offset;
+            let inst = self
+                .rom
+                .get(offset..offset + 2)
+                .filter(|_| code[addr] || self.is(offset, AccessKind::Execute))
+                .and_then(|bs| Instruction::parse([bs[0], bs[1]]).ok());
+            if let Some(inst) = inst {
+                items.push((offset, Item::Code(inst)));
+                offset += 2;
+                continue;
+            }
+            match items.last_mut() {
+                Some((start, Item::Data(len))) if *len < DATA_PER_LINE && self.flags[*start] == self.flags[offset] => {
+                    *len += 1
+                }
+                _ => items.push((offset, Item::Data(1))),
+            }
+            offset += 1;
+        }
+        items
+    }
+
+    // the disassembly with how each line was accessed: x executed, r read, w written,
+    // and - for code that never ran
+    pub fn text(&self, symbols: &Symbols) -> String {
+        let mut out = String::new();
+        let s = self.summary();
+        let percent = |n: usize| n as f64 * 100.0 / self.rom.len().max(1) as f64;
+        writeln!(
+            out,
+            "{} bytes: executed {} ({:.1}%), read as data {} ({:.1}%), written {} ({:.1}%), untouched {} ({:.1}%)",
+            self.rom.len(),
+            s.executed,
+            percent(s.executed),
+            s.data,
+            percent(s.data),
+            s.written,
+            percent(s.written),
+            s.untouched,
+            percent(s.untouched)
+        )
+        .unwrap();
+        for (offset, item) in self.items() {
+            let addr = ORIGIN + offset;
+            if let Some(label) = symbols.label_at(addr as u16) {
+                writeln!(out, "{label}:").unwrap();
+            }
+            match item {
+                Item::Code(inst) => {
+                    let flags = self.flags[offset] | self.flags[offset + 1];
+                    let marks = if flags == 0 { "-".to_string() } else { marks(flags) };
+                    let bytes = &self.rom[offset..offset + 2];
+                    writeln!(out, "{marks:<3} {addr:#05x}: {:02x} {:02x}  {inst}", bytes[0], bytes[1]).unwrap();
+                }
+                Item::Data(len) => {
+                    let bytes: Vec<String> = self.rom[offset..offset + len]
+                        .iter()
+                        .map(|b| format!("{b:02x}"))
+                        .collect();
+                    writeln!(out, "{:<3} {addr:#05x}: {}", marks(self.flags[offset]), bytes.join(" ")).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    // an lcov tracefile of the instructions, by source line when there is a source map and otherwise by
+    // address, each as a line of `name`
+    pub fn lcov(&self, name: &str, map: Option<&SourceMap>) -> String {
+        let mut files: BTreeMap<String, BTreeMap<usize, bool>> = BTreeMap::new();
+        for (offset, item) in self.items() {
+            let Item::Code(_) = item else {
+                continue;
+            };
+            let addr = ORIGIN + offset;
+            let (file, line) = match map {
+                Some(map) => match map.lookup(addr as u16) {
+                    Some(l) => (l.file.clone(), l.line),
+                    None => continue,
+                },
+                None => (name.to_string(), addr),
+            };
+            let hit = files.entry(file).or_default().entry(line).or_default();
+            *hit |= self.is(offset, AccessKind::Execute);
+        }
+
+        let mut out = String::new();
+        for (file, lines) in files {
+            writeln!(out, "TN:\nSF:{file}").unwrap();
+            for (line, hit) in lines.iter() {
+                writeln!(out, "DA:{line},{}", *hit as u8).unwrap();
+            }
+            let hits = lines.values().filter(|h| **h).count();
+            writeln!(out, "LH:{hits}\nLF:{}\nend_of_record", lines.len()).unwrap();
+        }
+        out
+    }
+}
+
+// a keypad script, one "<cycle> <keys>" per line: the hex digits of the keys held from that cycle on, or -
+// for none
+pub fn parse_inputs(text: &str) -> Result<Vec<(u64, [bool; 16])>, String> {
+    let mut inputs = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = || format!("line {}: expected <cycle> <keys>", n + 1);
+        let (cycle, keys) = line.split_once(char::is_whitespace).ok_or_else(err)?;
+        let mut keypad = [false; 16];
+        for key in keys.trim().chars().filter(|c| *c != '-') {
+            keypad[key.to_digit(16).ok_or_else(err)? as usize] = true;
+        }
+        inputs.push((parse_number(cycle).ok_or_else(err)? as u64, keypad));
+    }
+    inputs.sort_by_key(|(cycle, _)| *cycle);
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Memory;
+    use crate::proc::Processor;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_coverage() {
+        #[rustfmt::skip]
+        let rom = [
+            0xa2, 0x0a, // ld i, 0x20a
+            0xd0, 0x02, // drw v0, v0, 2
+            0x12, 0x04, // jp 0x204
+            0x00, 0xe0, // cls, never reached
+            0xff, 0xff,
+            0x80, 0x40, // the sprite
+            0x00,
+        ];
+        let mut memory = Memory::new();
+        memory.load_array(512, &rom).unwrap();
+        memory.coverage = Some(Box::new([0; 4096]));
+        let mut proc = Processor::new(memory, Arc::new(Mutex::new([0; 2048])));
+        for _ in 0..4 {
+            proc.execute().unwrap();
+        }
+
+        let coverage = Coverage::new(&rom, &proc.memory.coverage.unwrap()[..]);
+        assert_eq!(
+            coverage.summary(),
+            Summary {
+                executed: 6,
+                data: 2,
+                written: 0,
+                untouched: 5,
+            }
+        );
+        let symbols = Symbols::parse("test.sym", "label 0x20a sprite").unwrap();
+        let text = coverage.text(&symbols);
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "x   0x200: a2 0a  LD I, 0x20a",
+                "x   0x202: d0 02  DRW V0, V0, 2",
+                "x   0x204: 12 04  JP 0x204",
+                "    0x206: 00 e0 ff ff",
+                "sprite:",
+                "r   0x20a: 80 40",
+                "    0x20c: 00",
+            ]
+        );
+
+        let map = SourceMap::parse("test.map", "0x200 a.asm:1\n0x202 a.asm:2\n0x204 a.asm:2\n").unwrap();
+        assert_eq!(
+            coverage.lcov("test.ch8", Some(&map)),
+            "TN:\nSF:a.asm\nDA:1,1\nDA:2,1\nLH:2\nLF:2\nend_of_record\n"
+        );
+        assert!(coverage
+            .lcov("test.ch8", None)
+            .starts_with("TN:\nSF:test.ch8\nDA:512,1\n"));
+
+        let inputs = parse_inputs("# start\n0 -\n100 5a\n").unwrap();
+        assert_eq!(inputs[1].0, 100);
+        assert!(inputs[1].1[5] && inputs[1].1[0xa] && !inputs[1].1[0]);
+    }
+}
@@ -0,0 +1,708 @@
+use super::expr::Expr;
+use super::{parse_number, Debugger, Stop, Trigger};
+use crate::asm::{SourceMap, Symbols};
+use crate::lint;
+use crate::proc::Processor;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+static THREAD_ID: u64 = 1;
+static REGISTERS: u64 = 1;
+static STACK: u64 = 2;
+static BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().fold(0u32, |n, b| n << 8 | *b as u32) << (8 * (3 - chunk.len()));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3f] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| *c != b'=') {
+        n = n << 6 | BASE64.iter().position(|b| *b == c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+// messages are JSON preceded by a Content-Length header, like in the language server protocol
+fn read_messages(mut reader: impl BufRead, messages: Sender<Value>) {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            match reader.read_line(&mut header) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+        let Some(length) = length else { continue };
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        // malformed messages can't be answered as there is no sequence number to answer to
+        if let Ok(message) = serde_json::from_slice(&body) {
+            if messages.send(message).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+enum Resume {
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+    StepBack,
+    ReverseContinue,
+}
+
+pub struct Session<W: Write> {
+    out: W,
+    seq: u64,
+    dbg: Option<Debugger>,
+    lines: SourceMap,
+    stop_on_entry: bool,
+    // ids of the breakpoints set for each source, setBreakpoints replaces them all at once
+    sources: HashMap<String, Vec<usize>>,
+    instruction_breakpoints: Vec<usize>,
+    // tracepoint messages, sent as output events once the program stops
+    messages: Arc<Mutex<Vec<String>>>,
+    // requests that came in while the program ran
+    pending: VecDeque<Value>,
+    done: bool,
+}
+
+// serves one client until it disconnects
+pub fn serve(reader: impl Read + Send + 'static, writer: impl Write) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || read_messages(BufReader::new(reader), tx));
+    let mut session = Session {
+        out: writer,
+        seq: 0,
+        dbg: None,
+        lines: SourceMap::default(),
+        stop_on_entry: false,
+        sources: HashMap::new(),
+        instruction_breakpoints: Vec::new(),
+        messages: Arc::new(Mutex::new(Vec::new())),
+        pending: VecDeque::new(),
+        done: false,
+    };
+    while !session.done {
+        let request = match session.pending.pop_front() {
+            Some(request) => request,
+            None => match rx.recv() {
+                Ok(request) => request,
+                Err(_) => break,
+            },
+        };
+        session.handle(&request, &rx)?;
+    }
+    Ok(())
+}
+
+fn argument<'a>(args: &'a Value, name: &str) -> Result<&'a Value, String> {
+    args.get(name).ok_or(format!("missing argument '{name}'"))
+}
+
+fn string<'a>(args: &'a Value, name: &str) -> Result<&'a str, String> {
+    argument(args, name)?
+        .as_str()
+        .ok_or(format!("'{name}' must be a string"))
+}
+
+fn address(reference: &str, offset: i64) -> Result<usize, String> {
+    let addr = parse_number(reference).ok_or(format!("invalid memory reference '{reference}'"))?;
+    Ok((addr as i64).saturating_add(offset).max(0) as usize)
+}
+
+impl<W: Write> Session<W> {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.out.flush()
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({"type": "event", "event": event, "body": body}))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn handle(&mut self, request: &Value, rx: &Receiver<Value>) -> io::Result<()> {
+        let args = &request["arguments"];
+        let resume = match request["command"].as_str().unwrap_or("") {
+            "continue" => Some(Resume::Continue),
+            "next" => Some(Resume::Next),
+            "stepIn" => Some(Resume::StepIn),
+            "stepOut" => Some(Resume::StepOut),
+            "stepBack" => Some(Resume::StepBack),
+            "reverseContinue" => Some(Resume::ReverseContinue),
+            _ => None,
+        };
+        // running commands are answered right away, the stopped event follows once the program stops
+        if let Some(resume) = resume {
+            if self.dbg.is_none() {
+                return self.respond(request, Err("no program launched".to_string()));
+            }
+            self.respond(request, Ok(json!({"allThreadsContinued": true})))?;
+            return self.resume(resume, rx);
+        }
+        let result = match request["command"].as_str().unwrap_or("") {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsLogPoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsStepBack": true,
+                "supportsSetVariable": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsEvaluateForHovers": true,
+            })),
+            "launch" => {
+                let result = self.launch(args);
+                if result.is_ok() {
+                    // breakpoints can only be set once there is a program, so configuration starts now
+                    self.respond(request, result)?;
+                    return self.event("initialized", json!({}));
+                }
+                result
+            }
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                if self.stop_on_entry {
+                    return self.stopped(json!({"reason": "entry"}));
+                }
+                return self.resume(Resume::Continue, rx);
+            }
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(json!({}))
+            }
+            "pause" => {
+                self.respond(request, Ok(json!({})))?;
+                return self.stopped(json!({"reason": "pause"}));
+            }
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "CHIP-8"}]})),
+            command => match self.dbg.is_some() {
+                true => self.inspect(command, args),
+                false => Err("no program launched".to_string()),
+            },
+        };
+        self.respond(request, result)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = Path::new(string(args, "program")?);
+        let rom = fs::read(program).map_err(|e| format!("{}: {e}", program.display()))?;
+        // like the console debugger, files the assembler wrote next to the rom are picked up
+        let companion = |option: &str, extension: &str| match args.get(option).and_then(Value::as_str) {
+            Some(p) => Some(Path::new(p).to_path_buf()),
+            None => Some(program.with_extension(extension)).filter(|p| p.exists()),
+        };
+        let load_error = |e: Box<dyn Error>| e.to_string();
+        let symbols = match companion("symbols", "sym") {
+            Some(p) => Symbols::load(&p).map_err(load_error)?,
+            None => Symbols::default(),
+        };
+        self.lines = match companion("sourceMap", "map") {
+            Some(p) => SourceMap::load(&p).map_err(load_error)?,
+            None => SourceMap::default(),
+        };
+
+        let display = Arc::new(Mutex::new([0; 2048]));
+        let mut dbg = Debugger::new(&rom, symbols, display).map_err(|e| e.to_string())?;
+        if let Some(name) = args.get("quirks").and_then(Value::as_str) {
+            dbg.proc.quirks = lint::quirks(name)?;
+        }
+        let messages = Arc::clone(&self.messages);
+        dbg.trace = Box::new(move |message| messages.lock().unwrap().push(message.to_string()));
+        self.dbg = Some(dbg);
+        self.stop_on_entry = args.get("stopOnEntry").and_then(Value::as_bool).unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    fn resume(&mut self, resume: Resume, rx: &Receiver<Value>) -> io::Result<()> {
+        // a pause, or the client going away, stops a running program; anything else waits until it stopped
+        let pending = &mut self.pending;
+        let mut interruption = None;
+        let mut frame = |_: &mut Processor| {
+            while let Ok(request) = rx.try_recv() {
+                match request["command"].as_str() {
+                    Some("pause" | "disconnect" | "terminate") => {
+                        interruption = Some(request);
+                        return false;
+                    }
+                    _ => pending.push_back(request),
+                }
+            }
+            true
+        };
+        let dbg = self.dbg.as_mut().unwrap();
+        let stop = match resume {
+            Resume::Continue => dbg.run(None, &mut frame),
+            Resume::Next => dbg.step_over(&mut frame),
+            Resume::StepIn => dbg.run(Some(1), &mut frame),
+            Resume::StepOut => dbg.step_out(&mut frame).unwrap_or(Stop::Done),
+            Resume::StepBack => dbg.reverse_step(1),
+            Resume::ReverseContinue => dbg.reverse_continue(),
+        };
+        let messages: Vec<String> = self.messages.lock().unwrap().drain(..).collect();
+        for message in messages {
+            self.event("output", json!({"category": "console", "output": message + "\n"}))?;
+        }
+        let body = match stop {
+            Stop::Done => json!({"reason": "step"}),
+            Stop::Breakpoint(id) => json!({"reason": "breakpoint", "hitBreakpointIds": [id]}),
+            Stop::Watchpoint { id, access, .. } => json!({
+                "reason": "data breakpoint",
+                "hitBreakpointIds": [id],
+                "text": format!("{:?} of {:#05x}", access.kind, access.addr),
+            }),
+            Stop::Interrupted => match interruption {
+                Some(request) if request["command"] == "pause" => {
+                    self.respond(&request, Ok(json!({})))?;
+                    json!({"reason": "pause"})
+                }
+                // disconnecting is handled like any other request
+                Some(request) => {
+                    self.pending.push_front(request);
+                    return Ok(());
+                }
+                None => json!({"reason": "pause"}),
+            },
+            Stop::Error(e) => json!({"reason": "exception", "description": "execution error", "text": e.to_string()}),
+            Stop::HistoryStart => json!({"reason": "step", "description": "start of history"}),
+        };
+        self.stopped(body)
+    }
+
+    fn stopped(&mut self, mut body: Value) -> io::Result<()> {
+        body["threadId"] = json!(THREAD_ID);
+        body["allThreadsStopped"] = json!(true);
+        self.event("stopped", body)
+    }
+
+    // requests that need a launched program
+    fn inspect(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        match command {
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({"scopes": [
+                {"name": "Registers", "presentationHint": "registers", "variablesReference": REGISTERS, "expensive": false},
+                {"name": "Stack", "variablesReference": STACK, "expensive": false},
+            ]})),
+            "variables" => Ok(self.variables(argument(args, "variablesReference")?.as_u64())),
+            "setVariable" => self.set_variable(args),
+            "evaluate" => {
+                let dbg = self.dbg.as_ref().unwrap();
+                let expr = Expr::parse(string(args, "expression")?, &dbg.symbols).map_err(|e| e.to_string())?;
+                let value = expr.eval(dbg).ok_or("can't be evaluated")?;
+                Ok(json!({"result": format!("{value} ({value:#x})"), "variablesReference": 0}))
+            }
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            _ => Err(format!("unsupported request '{command}'")),
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = string(argument(args, "source")?, "path")?.to_string();
+        let dbg = self.dbg.as_mut().unwrap();
+        for id in self.sources.remove(&path).unwrap_or_default() {
+            dbg.remove(id);
+        }
+        let mut ids = Vec::new();
+        let mut breakpoints = Vec::new();
+        let requested = args
+            .get("breakpoints")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        for b in requested {
+            let line = b.get("line").and_then(Value::as_u64).unwrap_or(0) as usize;
+            // breakpoints on lines without code move down to the next instruction
+            let Some((addr, line)) = self.lines.address_of(Path::new(&path), line) else {
+                breakpoints.push(json!({"verified": false, "message": "no code at or after this line"}));
+                continue;
+            };
+            let condition = b.get("condition").and_then(Value::as_str).filter(|c| !c.is_empty());
+            let message = b.get("logMessage").and_then(Value::as_str);
+            match dbg.add(Trigger::Address(addr as usize), condition, message) {
+                Ok(id) => {
+                    ids.push(id);
+                    breakpoints.push(json!({
+                        "id": id,
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("{addr:#05x}"),
+                    }));
+                }
+                Err(e) => breakpoints.push(json!({"verified": false, "message": e.to_string()})),
+            }
+        }
+        self.sources.insert(path, ids);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let dbg = self.dbg.as_mut().unwrap();
+        for id in self.instruction_breakpoints.drain(..) {
+            dbg.remove(id);
+        }
+        let mut breakpoints = Vec::new();
+        let requested = args
+            .get("breakpoints")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        for b in requested {
+            let reference = b.get("instructionReference").and_then(Value::as_str).unwrap_or("");
+            let offset = b.get("offset").and_then(Value::as_i64).unwrap_or(0);
+            let condition = b.get("condition").and_then(Value::as_str).filter(|c| !c.is_empty());
+            let added = address(reference, offset).and_then(|addr| {
+                dbg.add(Trigger::Address(addr), condition, None)
+                    .map_err(|e| e.to_string())
+            });
+            match added {
+                Ok(id) => {
+                    self.instruction_breakpoints.push(id);
+                    breakpoints.push(json!({"id": id, "verified": true}));
+                }
+                Err(e) => breakpoints.push(json!({"verified": false, "message": e})),
+            }
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    // the file and line an address was assembled from, as DAP wants them
+    fn location(&self, addr: usize) -> Value {
+        match self.lines.lookup(addr as u16) {
+            Some(l) => {
+                let path = Path::new(&l.file);
+                let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                json!({"source": {"name": name, "path": l.file}, "line": l.line, "column": 1})
+            }
+            None => json!({"line": 0, "column": 0}),
+        }
+    }
+
+    fn stack_trace(&self) -> Value {
+        let dbg = self.dbg.as_ref().unwrap();
+        let frames: Vec<Value> = dbg
+            .backtrace()
+            .iter()
+            .enumerate()
+            .map(|(id, addr)| {
+                let mut frame = self.location(*addr);
+                frame["id"] = json!(id);
+                frame["name"] = json!(dbg.symbolize(*addr));
+                frame["instructionPointerReference"] = json!(format!("{addr:#05x}"));
+                frame
+            })
+            .collect();
+        json!({"totalFrames": frames.len(), "stackFrames": frames})
+    }
+
+    fn variables(&self, reference: Option<u64>) -> Value {
+        let dbg = self.dbg.as_ref().unwrap();
+        let proc = &dbg.proc;
+        let mut variables = Vec::new();
+        if reference == Some(REGISTERS) {
+            for (n, v) in proc.registers.as_array().iter().enumerate() {
+                variables
+                    .push(json!({"name": format!("v{n:x}"), "value": format!("{v:#04x}"), "variablesReference": 0}));
+            }
+            let addresses = [("i", proc.i as usize), ("pc", proc.pc)];
+            for (name, addr) in addresses {
+                variables.push(json!({
+                    "name": name,
+                    "value": dbg.symbolize(addr),
+                    "memoryReference": format!("{addr:#05x}"),
+                    "variablesReference": 0,
+                }));
+            }
+            let counters = [
+                ("sp", proc.sp),
+                ("dt", proc.delay_timer as usize),
+                ("st", proc.sound_timer as usize),
+            ];
+            for (name, value) in counters {
+                variables.push(json!({"name": name, "value": value.to_string(), "variablesReference": 0}));
+            }
+        } else if reference == Some(STACK) {
+            for (n, ret) in proc.stack().iter().enumerate().rev() {
+                variables.push(
+                    json!({"name": format!("[{n}]"), "value": dbg.symbolize(*ret as usize), "variablesReference": 0}),
+                );
+            }
+        }
+        json!({ "variables": variables })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = string(args, "name")?;
+        let text = string(args, "value")?;
+        let dbg = self.dbg.as_mut().unwrap();
+        let value = dbg.resolve(text).ok_or(format!("invalid value '{text}'"))?;
+        let proc = &mut dbg.proc;
+        let byte = || u8::try_from(value).map_err(|_| format!("{name} holds a byte"));
+        match name {
+            "i" => proc.i = value as u16,
+            "pc" if value < 4096 => proc.pc = value,
+            "pc" => return Err(format!("{value:#x} is outside of memory")),
+            "dt" => proc.delay_timer = byte()?,
+            "st" => proc.sound_timer = byte()?,
+            r if r.len() == 2 && r.starts_with('v') => {
+                let index = u8::from_str_radix(&r[1..], 16).map_err(|_| format!("unknown register '{r}'"))?;
+                proc.registers.set(index, byte()?).map_err(|e| e.to_string())?;
+            }
+            _ => return Err(format!("{name} can't be changed")),
+        }
+        dbg.checkpoint();
+        Ok(json!({"value": text}))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let offset = args.get("offset").and_then(Value::as_i64).unwrap_or(0);
+        let addr = address(string(args, "memoryReference")?, offset)?;
+        let mem = &self.dbg.as_ref().unwrap().proc.memory.mem;
+        // no more than there is memory, whatever the client asks for
+        let count = (argument(args, "count")?.as_u64().unwrap_or(0) as usize).min(mem.len());
+        let bytes = mem
+            .get(addr..addr.saturating_add(count).min(mem.len()))
+            .unwrap_or_default();
+        Ok(json!({
+            "address": format!("{addr:#05x}"),
+            "data": base64_encode(bytes),
+            "unreadableBytes": count - bytes.len(),
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let offset = args.get("offset").and_then(Value::as_i64).unwrap_or(0);
+        let addr = address(string(args, "memoryReference")?, offset)?;
+        let data = base64_decode(string(args, "data")?).ok_or("invalid base64 data")?;
+        let dbg = self.dbg.as_mut().unwrap();
+        let mem = dbg
+            .proc
+            .memory
+            .mem
+            .get_mut(addr..addr.saturating_add(data.len()))
+            .ok_or("write past the end of memory")?;
+        mem.copy_from_slice(&data);
+        dbg.checkpoint();
+        Ok(json!({"bytesWritten": data.len()}))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let offset = args.get("offset").and_then(Value::as_i64).unwrap_or(0);
+        let first = args.get("instructionOffset").and_then(Value::as_i64).unwrap_or(0);
+        let start = parse_number(string(args, "memoryReference")?).ok_or("invalid memory reference")? as i64;
+        let dbg = self.dbg.as_ref().unwrap();
+        // every instruction in memory at most
+        let count = argument(args, "instructionCount")?
+            .as_i64()
+            .unwrap_or(0)
+            .min(dbg.proc.memory.mem.len() as i64);
+        let mut instructions = Vec::new();
+        for n in 0..count {
+            let addr = start
+                .saturating_add(offset)
+                .saturating_add(first.saturating_add(n).saturating_mul(2));
+            let Some(bs) = usize::try_from(addr)
+                .ok()
+                .and_then(|a| dbg.proc.memory.mem.get(a..a + 2))
+            else {
+                instructions
+                    .push(json!({"address": format!("{addr:#05x}"), "instruction": "", "presentationHint": "invalid"}));
+                continue;
+            };
+            let addr = addr as usize;
+            let text = match dbg.instruction_at(addr) {
+                Some(inst) => inst.to_string(),
+                None => format!("DB {:#04x}, {:#04x}", bs[0], bs[1]),
+            };
+            let mut instruction = self.location(addr);
+            instruction["address"] = json!(format!("{addr:#05x}"));
+            instruction["instructionBytes"] = json!(format!("{:02x} {:02x}", bs[0], bs[1]));
+            instruction["instruction"] = json!(text);
+            if let Some(label) = dbg.symbols.label_at(addr as u16) {
+                instruction["symbol"] = json!(label);
+            }
+            instructions.push(instruction);
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_base64() {
+        for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", &[0xff, 0x00, 0x7f, 0x80]] {
+            assert_eq!(base64_decode(&base64_encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(base64_encode(&[0x60, 0x05, 0x22, 0x06]), "YAUiBg==");
+    }
+
+    #[test]
+    fn test_session() {
+        let dir = std::env::temp_dir().join(format!("chip8-dap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("test.asm");
+        fs::write(
+            &source,
+            "
+start:  ld v0, 5
+        call sub
+        jp start
+sub:    add v1, 1
+        ret
+",
+        )
+        .unwrap();
+        let program = asm::assemble(&source).unwrap();
+        let rom = dir.join("test.ch8");
+        fs::write(&rom, &program.bytes).unwrap();
+        fs::write(dir.join("test.sym"), program.symbols.to_string()).unwrap();
+        fs::write(dir.join("test.map"), program.lines.to_string()).unwrap();
+
+        let requests = [
+            json!({"command": "initialize", "arguments": {"adapterID": "chip8"}}),
+            json!({"command": "launch", "arguments": {"program": rom, "stopOnEntry": true}}),
+            json!({"command": "setBreakpoints", "arguments": {
+                "source": {"path": source},
+                "breakpoints": [{"line": 5}, {"line": 1, "logMessage": "v1={v1}"}],
+            }}),
+            json!({"command": "configurationDone"}),
+            json!({"command": "continue", "arguments": {"threadId": 1}}),
+            json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+            json!({"command": "variables", "arguments": {"variablesReference": REGISTERS}}),
+            json!({"command": "readMemory", "arguments": {"memoryReference": "0x200", "count": 4}}),
+            json!({"command": "stepOut", "arguments": {"threadId": 1}}),
+            json!({"command": "evaluate", "arguments": {"expression": "v1 + pc"}}),
+            // sizes and offsets past anything in memory
+            json!({"command": "readMemory", "arguments": {"memoryReference": "0xffe", "count": u64::MAX}}),
+            json!({"command": "readMemory", "arguments": {"memoryReference": "0x200", "offset": i64::MAX, "count": 1}}),
+            json!({"command": "disassemble", "arguments": {"memoryReference": "0xffe", "instructionCount": i64::MAX}}),
+            json!({"command": "disconnect"}),
+        ];
+        let mut input = Vec::new();
+        for (seq, mut request) in requests.into_iter().enumerate() {
+            request["seq"] = json!(seq + 1);
+            request["type"] = json!("request");
+            let body = request.to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        }
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let messages: Vec<Value> = output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|m| serde_json::from_str(m.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect();
+        let response = |seq: u64| {
+            let response = messages.iter().find(|m| m["request_seq"] == seq).unwrap();
+            assert_eq!(response["success"], true, "{response}");
+            &response["body"]
+        };
+        let events = |name: &str| -> Vec<&Value> { messages.iter().filter(|m| m["event"] == name).collect() };
+
+        assert_eq!(response(1)["supportsStepBack"], true);
+        assert_eq!(events("initialized").len(), 1);
+        assert_eq!(
+            response(3)["breakpoints"],
+            json!([
+                {"id": 1, "verified": true, "line": 5, "instructionReference": "0x206"},
+                {"id": 2, "verified": true, "line": 2, "instructionReference": "0x200"},
+            ])
+        );
+        let reasons: Vec<&str> = events("stopped")
+            .iter()
+            .map(|e| e["body"]["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, ["entry", "breakpoint", "step"]);
+        // logpoints log without stopping, even on the instruction execution resumes from
+        let output: Vec<&Value> = events("output").iter().map(|e| &e["body"]["output"]).collect();
+        assert_eq!(output, [&json!("v1=0\n")]);
+
+        let frames = &response(6)["stackFrames"];
+        assert_eq!(frames[0]["name"], "0x206 <sub>");
+        assert_eq!((&frames[0]["line"], &frames[1]["line"]), (&json!(5), &json!(3)));
+        assert_eq!(frames[1]["source"]["name"], "test.asm");
+        assert_eq!(
+            response(7)["variables"][0],
+            json!({"name": "v0", "value": "0x05", "variablesReference": 0})
+        );
+        assert_eq!(response(8)["data"], "YAUiBg==");
+        assert_eq!(response(10)["result"], "517 (0x205)");
+        assert_eq!(
+            (&response(11)["data"], &response(11)["unreadableBytes"]),
+            (&json!("AAA="), &json!(4094))
+        );
+        assert_eq!(response(12)["unreadableBytes"], 1);
+        let instructions = response(13)["instructions"].as_array().unwrap();
+        assert_eq!(instructions.len(), 4096);
+        assert_eq!(instructions[1]["presentationHint"], "invalid");
+    }
+}
@@ -0,0 +1,369 @@
+use super::Debugger;
+use crate::asm::Symbols;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct ExprError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Error for ExprError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Var {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    Cycles,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+static OPERATORS: [(&str, Op, u8); 18] = [
+    ("||", Op::Or, 1),
+    ("&&", Op::And, 2),
+    ("==", Op::Eq, 6),
+    ("!=", Op::Ne, 6),
+    ("<=", Op::Le, 7),
+    (">=", Op::Ge, 7),
+    ("<<", Op::Shl, 8),
+    (">>", Op::Shr, 8),
+    ("|", Op::BitOr, 3),
+    ("^", Op::BitXor, 4),
+    ("&", Op::BitAnd, 5),
+    ("<", Op::Lt, 7),
+    (">", Op::Gt, 7),
+    ("+", Op::Add, 9),
+    ("-", Op::Sub, 9),
+    ("*", Op::Mul, 10),
+    ("/", Op::Div, 10),
+    ("%", Op::Rem, 10),
+];
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(i64),
+    Var(Var),
+    // the byte at an address
+    Memory(Box<Expr>),
+    Unary(char, Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(Op, u8),
+    Unary(char),
+    Open(char),
+    Close(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        let column = pos + 1;
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+            let start = pos;
+            while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '_' || chars[pos] == '$') {
+                pos += 1;
+            }
+            let word: String = chars[start..pos].iter().collect();
+            let token = match super::parse_number(&word) {
+                Some(n) => Token::Number(n as i64),
+                None if c.is_ascii_digit() || c == '$' => {
+                    return Err(ExprError {
+                        column,
+                        message: format!("invalid number '{word}'"),
+                    })
+                }
+                None => Token::Ident(word),
+            };
+            tokens.push((column, token));
+        } else if let Some((op, o, prec)) = OPERATORS
+            .iter()
+            .find(|(op, _, _)| chars[pos..].iter().take(op.len()).copied().eq(op.chars()))
+        {
+            tokens.push((column, Token::Op(*o, *prec)));
+            pos += op.len();
+        } else {
+            let token = match c {
+                '!' | '~' => Token::Unary(c),
+                '(' | '[' => Token::Open(c),
+                ')' | ']' => Token::Close(c),
+                _ => {
+                    return Err(ExprError {
+                        column,
+                        message: format!("unexpected '{c}'"),
+                    })
+                }
+            };
+            tokens.push((column, token));
+            pos += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+    symbols: &'a Symbols,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ExprError {
+        ExprError {
+            column: self.tokens.get(self.pos).map(|(c, _)| *c).unwrap_or(self.end),
+            message: message.to_string(),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn expression(&mut self, min_prec: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.operand()?;
+        while let Some((_, Token::Op(op, prec))) = self.tokens.get(self.pos) {
+            let (op, prec) = (*op, *prec);
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expression(prec + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn operand(&mut self) -> Result<Expr, ExprError> {
+        let start = self.pos;
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) => self.variable(&name).ok_or_else(|| {
+                self.pos = start;
+                self.error(&format!("unknown name '{name}'"))
+            }),
+            Some(Token::Op(Op::Sub, _)) => Ok(Expr::Unary('-', Box::new(self.operand()?))),
+            Some(Token::Unary(c)) => Ok(Expr::Unary(c, Box::new(self.operand()?))),
+            Some(Token::Open(open)) => {
+                let inner = self.expression(0)?;
+                let close = if open == '(' { ')' } else { ']' };
+                if self.next() != Some(Token::Close(close)) {
+                    self.pos -= 1;
+                    return Err(self.error(&format!("expected '{close}'")));
+                }
+                Ok(match open {
+                    '(' => inner,
+                    _ => Expr::Memory(Box::new(inner)),
+                })
+            }
+            _ => {
+                self.pos = start;
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<Expr> {
+        let lower = name.to_lowercase();
+        let var = match lower.as_str() {
+            "i" => Var::I,
+            "pc" => Var::Pc,
+            "sp" => Var::Sp,
+            "dt" => Var::Dt,
+            "st" => Var::St,
+            "cycles" => Var::Cycles,
+            r if r.len() == 2 && r.starts_with('v') => match u8::from_str_radix(&r[1..], 16) {
+                Ok(index) => Var::V(index),
+                Err(_) => return self.symbol(name),
+            },
+            _ => return self.symbol(name),
+        };
+        Some(Expr::Var(var))
+    }
+
+    fn symbol(&self, name: &str) -> Option<Expr> {
+        self.symbols.lookup(name).map(|s| Expr::Number(s.value as i64))
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Expr, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            end: text.chars().count() + 1,
+            symbols,
+        };
+        let expr = parser.expression(0)?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("unexpected input after expression"));
+        }
+        Ok(expr)
+    }
+
+    // None when the value can't be computed, e.g. on division by zero
+    pub fn eval(&self, dbg: &Debugger) -> Option<i64> {
+        let proc = &dbg.proc;
+        match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Var(var) => Some(match var {
+                Var::V(r) => proc.registers.get(*r).ok()? as i64,
+                Var::I => proc.i as i64,
+                Var::Pc => proc.pc as i64,
+                Var::Sp => proc.sp as i64,
+                Var::Dt => proc.delay_timer as i64,
+                Var::St => proc.sound_timer as i64,
+                Var::Cycles => dbg.cycles as i64,
+            }),
+            Expr::Memory(addr) => {
+                let addr = usize::try_from(addr.eval(dbg)?).ok()?;
+                proc.memory.get_byte(addr).ok().map(|b| b as i64)
+            }
+            Expr::Unary(op, e) => {
+                let v = e.eval(dbg)?;
+                Some(match op {
+                    '-' => v.wrapping_neg(),
+                    '~' => !v,
+                    _ => (v == 0) as i64,
+                })
+            }
+            Expr::Binary(op, a, b) => {
+                let a = a.eval(dbg)?;
+                // the right hand side of a short circuiting operator may not need to be computable
+                match op {
+                    Op::Or if a != 0 => return Some(1),
+                    Op::And if a == 0 => return Some(0),
+                    _ => {}
+                }
+                let b = b.eval(dbg)?;
+                Some(match op {
+                    Op::Or | Op::And => (b != 0) as i64,
+                    Op::BitOr => a | b,
+                    Op::BitXor => a ^ b,
+                    Op::BitAnd => a & b,
+                    Op::Eq => (a == b) as i64,
+                    Op::Ne => (a != b) as i64,
+                    Op::Lt => (a < b) as i64,
+                    Op::Le => (a <= b) as i64,
+                    Op::Gt => (a > b) as i64,
+                    Op::Ge => (a >= b) as i64,
+                    Op::Shl => a.checked_shl(u32::try_from(b).ok()?)?,
+                    Op::Shr => a.checked_shr(u32::try_from(b).ok()?)?,
+                    Op::Add => a.wrapping_add(b),
+                    Op::Sub => a.wrapping_sub(b),
+                    Op::Mul => a.wrapping_mul(b),
+                    Op::Div => a.checked_div(b)?,
+                    Op::Rem => a.checked_rem(b)?,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Value(Expr, char),
+}
+
+// a tracepoint message, with expressions in braces: "v3={v3} sprite at {i:x}"
+#[derive(Debug, Clone)]
+pub struct Format {
+    pub text: String,
+    segments: Vec<Segment>,
+}
+
+impl Format {
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Format, ExprError> {
+        let mut segments = Vec::new();
+        let mut rest = text;
+        let mut offset = 0;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..].find('}').ok_or(ExprError {
+                column: offset + open + 1,
+                message: "unterminated '{'".to_string(),
+            })? + open;
+            if open > 0 {
+                segments.push(Segment::Text(rest[..open].to_string()));
+            }
+            let inner = &rest[open + 1..close];
+            let (source, radix) = match inner.rsplit_once(':') {
+                Some((source, "x")) => (source, 'x'),
+                Some((source, "b")) => (source, 'b'),
+                _ => (inner, 'd'),
+            };
+            let expr = Expr::parse(source, symbols).map_err(|e| ExprError {
+                column: e.column + offset + open + 1,
+                message: e.message,
+            })?;
+            segments.push(Segment::Value(expr, radix));
+            offset += close + 1;
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Format {
+            text: text.to_string(),
+            segments,
+        })
+    }
+
+    pub fn render(&self, dbg: &Debugger) -> String {
+        self.segments
+            .iter()
+            .map(|s| match s {
+                Segment::Text(t) => t.clone(),
+                Segment::Value(e, radix) => match (e.eval(dbg), radix) {
+                    (None, _) => "?".to_string(),
+                    (Some(v), 'x') => format!("{v:#x}"),
+                    (Some(v), 'b') => format!("{v:#b}"),
+                    (Some(v), _) => v.to_string(),
+                },
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,506 @@
+use super::{Debugger, Stop, Trigger};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+static POLL: Duration = Duration::from_millis(100);
+static SUPPORTED: &str = "PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+;ReverseStep+;ReverseContinue+";
+
+// registers in the order of g packets: v0-vf, i, pc and sp, numbered 0-18 for p and P
+static TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+enum Incoming {
+    Packet(Vec<u8>),
+    // failed the checksum, the client sends it again after a nak
+    Corrupt,
+}
+
+enum Action {
+    Reply(String),
+    Detach,
+    Kill,
+}
+
+// reads packets on a thread of its own so an interrupt (a bare 0x03) can stop a running program
+fn read_packets(reader: impl Read, packets: Sender<Incoming>, interrupt: Arc<AtomicBool>) {
+    let mut bytes = BufReader::new(reader).bytes().map_while(Result::ok);
+    while let Some(b) = bytes.next() {
+        match b {
+            0x03 => interrupt.store(true, Ordering::Relaxed),
+            b'$' => {
+                let mut data = Vec::new();
+                let mut sum = 0u8;
+                for b in bytes.by_ref().take_while(|b| *b != b'#') {
+                    sum = sum.wrapping_add(b);
+                    data.push(b);
+                }
+                let checksum: Vec<u8> = bytes.by_ref().take(2).collect();
+                let checksum = std::str::from_utf8(&checksum)
+                    .ok()
+                    .and_then(|c| u8::from_str_radix(c, 16).ok());
+                let packet = match checksum == Some(sum) {
+                    true => Incoming::Packet(unescape(&data)),
+                    false => Incoming::Corrupt,
+                };
+                if packets.send(packet).is_err() {
+                    return;
+                }
+            }
+            // acks and naks, the connection is reliable
+            _ => {}
+        }
+    }
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'}' => out.extend(bytes.next().map(|b| b ^ 0x20)),
+            _ => out.push(*b),
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn number(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+// "addr,len" as used by m, M, X, Z and z
+fn address_and_length(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((number(addr)?, number(len)?))
+}
+
+pub struct Session<'a, W: Write> {
+    dbg: &'a mut Debugger,
+    out: W,
+    interrupt: Arc<AtomicBool>,
+    last_stop: String,
+}
+
+// serves one connection until the client detaches or disconnects, returning whether it asked to kill the program
+pub fn serve(dbg: &mut Debugger, reader: impl Read + Send + 'static, writer: impl Write) -> io::Result<bool> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let flag = Arc::clone(&interrupt);
+    thread::spawn(move || read_packets(reader, tx, flag));
+
+    let mut session = Session {
+        dbg,
+        out: writer,
+        interrupt,
+        last_stop: "S05".to_string(),
+    };
+    loop {
+        match rx.recv_timeout(POLL) {
+            Ok(Incoming::Packet(packet)) => {
+                session.out.write_all(b"+")?;
+                match session.handle(&packet) {
+                    Action::Reply(reply) => session.send(&reply)?,
+                    Action::Detach => {
+                        session.send("OK")?;
+                        return Ok(false);
+                    }
+                    Action::Kill => return Ok(true),
+                }
+            }
+            Ok(Incoming::Corrupt) => session.out.write_all(b"-")?,
+            // an interrupt while stopped is answered right away
+            Err(RecvTimeoutError::Timeout) => {
+                if session.interrupt.swap(false, Ordering::Relaxed) {
+                    session.send("S02")?;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(false),
+        }
+        session.out.flush()?;
+    }
+}
+
+// accepts connections on the loopback interface only, one at a time, until a client kills the program
+pub fn listen(dbg: &mut Debugger, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    println!("waiting for gdb on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        println!("connected to {}", stream.peer_addr()?);
+        if serve(dbg, stream.try_clone()?, stream)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl<W: Write> Session<'_, W> {
+    fn send(&mut self, reply: &str) -> io::Result<()> {
+        let mut data = Vec::with_capacity(reply.len());
+        for b in reply.bytes() {
+            match b {
+                b'#' | b'$' | b'}' | b'*' => data.extend([b'}', b ^ 0x20]),
+                _ => data.push(b),
+            }
+        }
+        let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        self.out.write_all(b"$")?;
+        self.out.write_all(&data)?;
+        write!(self.out, "#{sum:02x}")
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Action {
+        // X is the only packet with binary data
+        if let Some(rest) = packet.strip_prefix(b"X") {
+            return Action::Reply(self.write_binary(rest).unwrap_or("E01".to_string()));
+        }
+        let mut packet = String::from_utf8_lossy(packet).into_owned();
+        // s and c may give the address to resume at
+        if packet.len() > 1 && (packet.starts_with('s') || packet.starts_with('c')) {
+            match number(&packet[1..]).filter(|a| *a < 4096) {
+                Some(addr) => self.dbg.proc.pc = addr,
+                None => return Action::Reply("E01".to_string()),
+            }
+            packet.truncate(1);
+        }
+        let reply = match packet.as_str() {
+            "?" => Some(self.last_stop.clone()),
+            "g" => Some(self.read_registers()),
+            "s" => Some(self.resume(|dbg| dbg.run(Some(1), &mut |_| true))),
+            "c" => {
+                let interrupt = Arc::clone(&self.interrupt);
+                Some(self.resume(|dbg| dbg.run(None, &mut |_| !interrupt.swap(false, Ordering::Relaxed))))
+            }
+            "bs" => Some(self.resume(|dbg| dbg.reverse_step(1))),
+            "bc" => Some(self.resume(|dbg| dbg.reverse_continue())),
+            "D" => return Action::Detach,
+            "k" => return Action::Kill,
+            "qAttached" => Some("1".to_string()),
+            "qC" => Some("QC1".to_string()),
+            "qfThreadInfo" => Some("m1".to_string()),
+            "qsThreadInfo" => Some("l".to_string()),
+            p if p.starts_with("qSupported") => Some(SUPPORTED.to_string()),
+            p if p.starts_with('H') || p.starts_with('T') => Some("OK".to_string()),
+            p => self.handle_with_arguments(p),
+        };
+        // an empty reply tells the client the packet isn't supported
+        Action::Reply(reply.unwrap_or_default())
+    }
+
+    // None for unsupported packets, errors are replied as E01
+    fn handle_with_arguments(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let result = match command {
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "q" => match args.strip_prefix("Xfer:features:read:target.xml:") {
+                Some(range) => Self::features(range),
+                None => return None,
+            },
+            _ => return None,
+        };
+        Some(result.unwrap_or("E01".to_string()))
+    }
+
+    fn features(range: &str) -> Option<String> {
+        let (offset, len) = address_and_length(range)?;
+        let xml = TARGET_XML.get(offset.min(TARGET_XML.len())..)?;
+        Some(match xml.len() > len {
+            true => format!("m{}", &xml[..len]),
+            false => format!("l{xml}"),
+        })
+    }
+
+    fn resume(&mut self, run: impl FnOnce(&mut Debugger) -> Stop) -> String {
+        let stop = run(self.dbg);
+        self.last_stop = match stop {
+            Stop::Done => "S05".to_string(),
+            Stop::Breakpoint(_) => "T05swbreak:;".to_string(),
+            Stop::Watchpoint { id, access, .. } => {
+                let kind = match self.dbg.breakpoints.iter().find(|b| b.id == id).map(|b| &b.trigger) {
+                    Some(Trigger::Read(_)) => "rwatch",
+                    Some(Trigger::Access(_)) => "awatch",
+                    _ => "watch",
+                };
+                format!("T05{kind}:{:x};", access.addr)
+            }
+            Stop::Interrupted => "S02".to_string(),
+            Stop::Error(_) => "S04".to_string(),
+            Stop::HistoryStart => "T05replaylog:begin;".to_string(),
+        };
+        self.last_stop.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let proc = &self.dbg.proc;
+        let mut bytes = proc.registers.as_array().to_vec();
+        bytes.extend(proc.i.to_le_bytes());
+        bytes.extend((proc.pc as u16).to_le_bytes());
+        bytes.push(proc.sp as u8);
+        bytes
+    }
+
+    fn read_registers(&self) -> String {
+        hex(&self.registers())
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = parse_hex(args).filter(|b| b.len() == 21)?;
+        let proc = &mut self.dbg.proc;
+        let pc = u16::from_le_bytes([bytes[18], bytes[19]]) as usize;
+        let sp = bytes[20] as usize;
+        // frames can be dropped but not made up, as their return addresses aren't known
+        if pc >= 4096 || sp > proc.sp {
+            return None;
+        }
+        proc.registers.from_array(bytes[..16].try_into().ok()?);
+        proc.i = u16::from_le_bytes([bytes[16], bytes[17]]);
+        proc.pc = pc;
+        proc.sp = sp;
+        self.dbg.checkpoint();
+        Some("OK".to_string())
+    }
+
+    fn read_register(&self, args: &str) -> Option<String> {
+        let bytes = self.registers();
+        match number(args)? {
+            r @ 0..16 => Some(hex(&bytes[r..r + 1])),
+            16 => Some(hex(&bytes[16..18])),
+            17 => Some(hex(&bytes[18..20])),
+            18 => Some(hex(&bytes[20..])),
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let (register, value) = args.split_once('=')?;
+        let bytes = parse_hex(value)?;
+        let proc = &mut self.dbg.proc;
+        match (number(register)?, &bytes[..]) {
+            (r @ 0..16, &[v]) => proc.registers.set(r as u8, v).ok()?,
+            (16, &[lo, hi]) => proc.i = u16::from_le_bytes([lo, hi]),
+            (17, &[lo, hi]) if (u16::from_le_bytes([lo, hi]) as usize) < 4096 => {
+                proc.pc = u16::from_le_bytes([lo, hi]) as usize
+            }
+            (18, &[v]) if v as usize <= proc.sp => proc.sp = v as usize,
+            _ => return None,
+        }
+        self.dbg.checkpoint();
+        Some("OK".to_string())
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = address_and_length(args)?;
+        self.dbg.proc.memory.mem.get(addr..addr.checked_add(len)?).map(hex)
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = address_and_length(range)?;
+        let bytes = parse_hex(data).filter(|b| b.len() == len)?;
+        self.store(addr, &bytes)
+    }
+
+    fn write_binary(&mut self, args: &[u8]) -> Option<String> {
+        let colon = args.iter().position(|b| *b == b':')?;
+        let (addr, len) = address_and_length(std::str::from_utf8(&args[..colon]).ok()?)?;
+        let bytes = &args[colon + 1..];
+        if bytes.len() != len {
+            return None;
+        }
+        self.store(addr, bytes)
+    }
+
+    fn store(&mut self, addr: usize, bytes: &[u8]) -> Option<String> {
+        let memory = self.dbg.proc.memory.mem.get_mut(addr..addr.checked_add(bytes.len())?)?;
+        memory.copy_from_slice(bytes);
+        self.dbg.checkpoint();
+        Some("OK".to_string())
+    }
+
+    // Z0/Z1 break on an address, Z2, Z3 and Z4 watch writes, reads and both
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let addr = number(fields.next()?)?;
+        let len = number(fields.next()?)?.max(1);
+        let range = addr..addr.checked_add(len).filter(|end| *end <= 4096)?;
+        let trigger = match kind {
+            "0" | "1" => Trigger::Address(addr),
+            "2" => Trigger::Write(range),
+            "3" => Trigger::Read(range),
+            "4" => Trigger::Access(range),
+            _ => return None,
+        };
+        // breakpoints made in the debugger's own console, with conditions or messages, are left alone
+        let existing = self
+            .dbg
+            .breakpoints
+            .iter()
+            .find(|b| b.trigger == trigger && b.condition.is_none() && b.message.is_none())
+            .map(|b| b.id);
+        match (insert, existing) {
+            (true, None) => {
+                self.dbg.add(trigger, None, None).ok()?;
+            }
+            (false, Some(id)) => {
+                self.dbg.remove(id);
+            }
+            _ => {}
+        }
+        Some("OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    fn packet(data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        format!("${data}#{sum:02x}")
+    }
+
+    #[test]
+    fn test_session() {
+        let source = "
+start:  ld v0, 5
+        ld i, data
+        call sub
+        jp start
+sub:    ld [i], v0
+        ret
+data:   db 0x12, 0x34
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let mut dbg = Debugger::new(&program.bytes, program.symbols, Arc::new(Mutex::new([0; 2048]))).unwrap();
+        let requests = [
+            "qSupported:swbreak+",
+            "?",
+            "s",
+            "g",
+            "p11",
+            "P3=2a",
+            "m20c,2",
+            "Z0,208,2",
+            "c",
+            "z0,208,2",
+            "Z2,20c,1",
+            "c",
+            "M20d,1:ff",
+            "m20c,2",
+            "qXfer:features:read:target.xml:0,20",
+            "vMustReplyEmpty",
+            // out of range or overflowing values from the client
+            "P12=ff",
+            "P12=05",
+            &format!("G{}0a0205", "00".repeat(18)),
+            "P11=0010",
+            &format!("G{}ff", "00".repeat(20)),
+            &format!("G{}001000", "00".repeat(18)),
+            "Z2,ffffffffffffffff,2",
+            "Z3,fff,2",
+            "mffffffffffffffff,2",
+            "Mffffffffffffffff,2:0000",
+            "p12",
+            "k",
+        ];
+        let input: String = std::iter::once("+".to_string())
+            .chain(requests.iter().map(|r| packet(r)))
+            .collect();
+        let mut output = Vec::new();
+        assert!(serve(&mut dbg, Cursor::new(input), &mut output).unwrap());
+
+        let output = String::from_utf8(output).unwrap();
+        let replies: Vec<&str> = output
+            .split('$')
+            .skip(1)
+            .map(|r| r.split_once('#').unwrap().0)
+            .collect();
+        assert_eq!(
+            replies,
+            [
+                SUPPORTED,
+                "S05",
+                "S05",
+                "050000000000000000000000000000000000020200",
+                "0202",
+                "OK",
+                "1234",
+                "OK",
+                "T05swbreak:;",
+                "OK",
+                "OK",
+                "T05watch:20c;",
+                "OK",
+                "05ff",
+                "m<?xml version=\"1.0\"?>\n<!DOCTYPE ",
+                "",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                // still in sub, the stack pointer wasn't changed
+                "01",
+            ]
+        );
+        assert_eq!(dbg.proc.registers.v3, 0x2a);
+    }
+}
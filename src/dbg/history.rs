@@ -0,0 +1,81 @@
+use crate::proc::{Processor, Snapshot};
+use std::collections::VecDeque;
+
+// instructions between snapshots, going back replays at most this many
+static SNAPSHOT_INTERVAL: u64 = 1000;
+static MAX_SNAPSHOTS: usize = 1000;
+
+// what it takes to get back to any earlier cycle: periodic snapshots, plus the keypad changes in between
+// since those are the only thing not determined by the state
+#[derive(Default)]
+pub struct History {
+    snapshots: VecDeque<(u64, Snapshot)>,
+    inputs: Vec<(u64, [bool; 16])>,
+}
+
+impl History {
+    // called before executing the instruction at `cycle`
+    pub fn record(&mut self, cycle: u64, proc: &Processor) {
+        let last = self.snapshots.back().map(|(c, _)| *c);
+        if cycle.is_multiple_of(SNAPSHOT_INTERVAL) && last.is_none_or(|c| c < cycle) {
+            self.push(cycle, proc);
+        }
+        if proc.keypad != self.keypad_at(cycle) {
+            self.inputs.retain(|(c, _)| *c < cycle);
+            self.inputs.push((cycle, proc.keypad));
+        }
+    }
+
+    // a snapshot outside the interval, for state changed by hand
+    pub fn checkpoint(&mut self, cycle: u64, proc: &Processor) {
+        self.truncate(cycle);
+        self.snapshots.retain(|(c, _)| *c < cycle);
+        self.push(cycle, proc);
+    }
+
+    fn push(&mut self, cycle: u64, proc: &Processor) {
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((cycle, proc.snapshot()));
+    }
+
+    pub fn keypad_at(&self, cycle: u64) -> [bool; 16] {
+        self.inputs
+            .iter()
+            .rev()
+            .find(|(c, _)| *c <= cycle)
+            .map(|(_, keypad)| *keypad)
+            .unwrap_or_default()
+    }
+
+    // the latest snapshot at or before `cycle`
+    pub fn before(&self, cycle: u64) -> Option<&(u64, Snapshot)> {
+        self.snapshots.iter().rev().find(|(c, _)| *c <= cycle)
+    }
+
+    // cycles of the snapshots before `cycle`, latest first
+    pub fn starts(&self, cycle: u64) -> Vec<u64> {
+        self.snapshots
+            .iter()
+            .rev()
+            .map(|(c, _)| *c)
+            .filter(|c| *c < cycle)
+            .collect()
+    }
+
+    pub fn earliest(&self) -> Option<u64> {
+        self.snapshots.front().map(|(c, _)| *c)
+    }
+
+    // forgets everything after `cycle`, execution from there on may differ
+    pub fn truncate(&mut self, cycle: u64) {
+        self.snapshots.retain(|(c, _)| *c <= cycle);
+        self.inputs.retain(|(c, _)| *c <= cycle);
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.inputs.clear();
+    }
+}
@@ -0,0 +1,667 @@
+#![allow(dead_code)]
+use crate::asm::Symbols;
+use crate::inst::Instruction;
+use crate::mem::{Access, AccessKind, Heat, Memory, MemoryError};
+use crate::proc::{KeyInput, ProcError, Processor, Provenance, Quirks};
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+pub mod dap;
+pub mod expr;
+pub mod gdb;
+pub mod history;
+pub mod repl;
+
+use expr::{Expr, Format};
+use history::History;
+
+static ORIGIN: usize = 512;
+pub static INSTRUCTIONS_PER_FRAME: u64 = 10;
+
+#[derive(Debug)]
+pub enum Stop {
+    // the requested number of instructions ran
+    Done,
+    // the id of the breakpoint, hit before executing the instruction at pc
+    Breakpoint(usize),
+    // hit after executing the instruction at pc that made the access
+    Watchpoint { id: usize, access: Access, pc: usize },
+    Interrupted,
+    Error(ProcError),
+    // going back reached the oldest snapshot
+    HistoryStart,
+}
+
+// an opcode with wildcards, like Dxyn or Fx55
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pattern {
+    mask: u16,
+    value: u16,
+}
+
+impl Pattern {
+    // hex digits have to match, any other character matches any nibble
+    pub fn parse(s: &str) -> Option<Pattern> {
+        if s.chars().count() != 4 {
+            return None;
+        }
+        let (mut mask, mut value) = (0, 0);
+        for c in s.chars() {
+            if !c.is_ascii_alphanumeric() && c != '?' {
+                return None;
+            }
+            let digit = c.to_digit(16);
+            mask = mask << 4 | if digit.is_some() { 0xf } else { 0 };
+            value = value << 4 | digit.unwrap_or(0) as u16;
+        }
+        Some(Pattern { mask, value })
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for shift in [12, 8, 4, 0] {
+            match (self.mask >> shift) & 0xf {
+                0 => write!(f, "?")?,
+                _ => write!(f, "{:X}", (self.value >> shift) & 0xf)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    Address(usize),
+    Opcode(Pattern),
+    Read(Range<usize>),
+    Write(Range<usize>),
+    Access(Range<usize>),
+}
+
+impl Trigger {
+    fn is_watch(&self) -> bool {
+        matches!(self, Trigger::Read(_) | Trigger::Write(_) | Trigger::Access(_))
+    }
+
+    fn watches(&self, access: &Access) -> bool {
+        match self {
+            Trigger::Read(r) => access.kind == AccessKind::Read && r.contains(&access.addr),
+            Trigger::Write(r) => access.kind == AccessKind::Write && r.contains(&access.addr),
+            Trigger::Access(r) => access.kind != AccessKind::Execute && r.contains(&access.addr),
+            _ => false,
+        }
+    }
+}
+
+pub struct Breakpoint {
+    pub id: usize,
+    pub trigger: Trigger,
+    pub condition: Option<Expr>,
+    // the condition as typed
+    pub source: String,
+    // tracepoints log this message and carry on instead of stopping
+    pub message: Option<Format>,
+    pub hits: usize,
+}
+
+pub struct Debugger {
+    pub proc: Processor,
+    pub display: Arc<Mutex<[u8; 2048]>>,
+    pub symbols: Symbols,
+    pub breakpoints: Vec<Breakpoint>,
+    // receives the messages of tracepoints
+    pub trace: Box<dyn FnMut(&str) + Send>,
+    next_id: usize,
+    // instructions executed since the last reset, timers tick every INSTRUCTIONS_PER_FRAME of them
+    pub cycles: u64,
+    history: History,
+    rom: Vec<u8>,
+}
+
+fn load(rom: &[u8], display: Arc<Mutex<[u8; 2048]>>, quirks: Quirks) -> Result<Processor, MemoryError> {
+    let mut memory = Memory::new();
+    memory.load_array(ORIGIN, rom)?;
+    let mut proc = Processor::new(memory, display);
+    proc.quirks = quirks;
+    proc.key_input = KeyInput::Keypad;
+    proc.memory.heat = Some(Heat::default());
+    proc.provenance = Some(Provenance::default());
+    Ok(proc)
+}
+
+pub fn parse_number(s: &str) -> Option<usize> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        usize::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+impl Debugger {
+    pub fn new(rom: &[u8], symbols: Symbols, display: Arc<Mutex<[u8; 2048]>>) -> Result<Self, MemoryError> {
+        Ok(Debugger {
+            proc: load(rom, Arc::clone(&display), Quirks::chip8())?,
+            display,
+            symbols,
+            breakpoints: Vec::new(),
+            trace: Box::new(|message| println!("{message}")),
+            next_id: 1,
+            cycles: 0,
+            history: History::default(),
+            rom: rom.to_vec(),
+        })
+    }
+
+    // reloads the rom, keeping breakpoints and quirks
+    pub fn reset(&mut self) {
+        self.display.lock().unwrap().fill(0);
+        // the rom fit before, so it fits again
+        self.proc = load(&self.rom, Arc::clone(&self.display), self.proc.quirks).unwrap();
+        self.cycles = 0;
+        self.history.clear();
+        self.update_log();
+    }
+
+    // `source` is the condition as typed, shown when listing breakpoints
+    pub fn add(
+        &mut self,
+        trigger: Trigger,
+        condition: Option<&str>,
+        message: Option<&str>,
+    ) -> Result<usize, expr::ExprError> {
+        let id = self.next_id;
+        self.breakpoints.push(Breakpoint {
+            id,
+            trigger,
+            condition: condition.map(|c| Expr::parse(c, &self.symbols)).transpose()?,
+            source: condition.unwrap_or("").to_string(),
+            message: message.map(|m| Format::parse(m, &self.symbols)).transpose()?,
+            hits: 0,
+        });
+        self.next_id += 1;
+        self.update_log();
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.update_log();
+        self.breakpoints.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.update_log();
+    }
+
+    pub fn breakpoint_at(&self, addr: usize) -> bool {
+        self.breakpoints.iter().any(|b| b.trigger == Trigger::Address(addr))
+    }
+
+    // memory accesses are only recorded while something watches them
+    fn update_log(&mut self) {
+        let watching = self.breakpoints.iter().any(|b| b.trigger.is_watch());
+        self.proc.memory.log = if watching { Some(Vec::new()) } else { None };
+    }
+
+    fn holds(&self, index: usize) -> bool {
+        let condition = self.breakpoints[index].condition.as_ref();
+        condition.is_none_or(|c| c.eval(self).unwrap_or(0) != 0)
+    }
+
+    // counts a hit of breakpoint `index` if its condition holds, returning whether execution should stop
+    fn hit(&mut self, index: usize) -> bool {
+        if !self.holds(index) {
+            return false;
+        }
+        let b = &self.breakpoints[index];
+        let message = b.message.as_ref().map(|m| m.render(self));
+        self.breakpoints[index].hits += 1;
+        match message {
+            Some(message) => {
+                (self.trace)(&message);
+                false
+            }
+            None => true,
+        }
+    }
+
+    // breakpoints on the instruction about to be executed; tracepoints log even when `stop` is false
+    fn check_pc(&mut self, stop: bool) -> Option<usize> {
+        let mut hit = None;
+        for index in 0..self.breakpoints.len() {
+            let matches = self.at_pc(index);
+            let tracepoint = self.breakpoints[index].message.is_some();
+            if matches && (stop || tracepoint) && self.hit(index) && hit.is_none() {
+                hit = Some(self.breakpoints[index].id);
+            }
+        }
+        hit
+    }
+
+    fn at_pc(&self, index: usize) -> bool {
+        let pc = self.proc.pc;
+        match &self.breakpoints[index].trigger {
+            Trigger::Address(addr) => *addr == pc,
+            Trigger::Opcode(pattern) => {
+                let opcode = self.proc.memory.get_word(pc).map(u16::from_be_bytes);
+                opcode.is_ok_and(|op| pattern.matches(op))
+            }
+            _ => false,
+        }
+    }
+
+    // watchpoints on the accesses of the instruction just executed
+    fn check_accesses(&mut self) -> Option<(usize, Access)> {
+        let accesses = self.proc.memory.log.as_mut().map(std::mem::take).unwrap_or_default();
+        let mut hit = None;
+        for access in accesses.iter() {
+            for index in 0..self.breakpoints.len() {
+                if self.breakpoints[index].trigger.watches(access) && self.hit(index) && hit.is_none() {
+                    hit = Some((self.breakpoints[index].id, *access));
+                }
+            }
+        }
+        hit
+    }
+
+    pub fn step(&mut self) -> Result<(), ProcError> {
+        self.history.record(self.cycles, &self.proc);
+        self.proc.execute()?;
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(INSTRUCTIONS_PER_FRAME) {
+            self.proc.tick_timers();
+        }
+        Ok(())
+    }
+
+    // runs up to count instructions, or until something stops it. `frame` is called whenever the timers tick
+    // and can interrupt by returning false.
+    pub fn run(&mut self, count: Option<usize>, frame: &mut dyn FnMut(&mut Processor) -> bool) -> Stop {
+        self.run_until(count, &|_| false, frame)
+    }
+
+    // like run, but also done once `until` holds after an instruction
+    pub fn run_until(
+        &mut self,
+        count: Option<usize>,
+        until: &dyn Fn(&Processor) -> bool,
+        frame: &mut dyn FnMut(&mut Processor) -> bool,
+    ) -> Stop {
+        let mut executed = 0;
+        loop {
+            if count.is_some_and(|c| executed >= c) {
+                return Stop::Done;
+            }
+            // leaving a breakpoint doesn't hit it again
+            if !self.breakpoints.is_empty() {
+                if let Some(id) = self.check_pc(executed > 0) {
+                    return Stop::Breakpoint(id);
+                }
+            }
+            let pc = self.proc.pc;
+            if let Err(e) = self.step() {
+                return Stop::Error(e);
+            }
+            executed += 1;
+            if self.proc.memory.log.is_some() {
+                if let Some((id, access)) = self.check_accesses() {
+                    return Stop::Watchpoint { id, access, pc };
+                }
+            }
+            if until(&self.proc) {
+                return Stop::Done;
+            }
+            if self.cycles.is_multiple_of(INSTRUCTIONS_PER_FRAME) && !frame(&mut self.proc) {
+                return Stop::Interrupted;
+            }
+        }
+    }
+
+    // runs a whole subroutine call as one step
+    pub fn step_over(&mut self, frame: &mut dyn FnMut(&mut Processor) -> bool) -> Stop {
+        match self.instruction_at(self.proc.pc) {
+            Some(Instruction::CallSubroutine { .. }) => {
+                let (next, depth) = (self.proc.pc + 2, self.proc.sp);
+                self.run_until(None, &|p| p.pc == next && p.sp == depth, frame)
+            }
+            _ => self.run(Some(1), frame),
+        }
+    }
+
+    // runs until the current subroutine returns, None if there is none
+    pub fn step_out(&mut self, frame: &mut dyn FnMut(&mut Processor) -> bool) -> Option<Stop> {
+        let depth = self.proc.sp.checked_sub(1)?;
+        Some(self.run_until(None, &|p| p.sp == depth, frame))
+    }
+
+    pub fn run_to(&mut self, addr: usize, frame: &mut dyn FnMut(&mut Processor) -> bool) -> Stop {
+        self.run_until(None, &|p| p.pc == addr, frame)
+    }
+
+    // to be called after changing state by hand, so going back and forth doesn't lose the change
+    pub fn checkpoint(&mut self) {
+        self.history.checkpoint(self.cycles, &self.proc);
+    }
+
+    // restores the latest snapshot before `cycle` and re-executes up to it, with the keypad as it was
+    fn replay(&mut self, cycle: u64) -> Result<(), Stop> {
+        let (start, snapshot) = self.history.before(cycle).ok_or(Stop::HistoryStart)?;
+        self.proc.restore(snapshot);
+        self.cycles = *start;
+        while self.cycles < cycle {
+            self.proc.keypad = self.history.keypad_at(self.cycles);
+            self.step().map_err(Stop::Error)?;
+        }
+        Ok(())
+    }
+
+    // goes back to `cycle`, the history after it is dropped. the cycle was reached before so replaying should get
+    // there again, if it doesn't the error is where it stopped.
+    fn seek(&mut self, cycle: u64) -> Result<(), Stop> {
+        self.history.truncate(cycle);
+        let replayed = self.replay(cycle);
+        if let Some(log) = &mut self.proc.memory.log {
+            log.clear();
+        }
+        replayed
+    }
+
+    pub fn reverse_step(&mut self, count: u64) -> Stop {
+        let target = self.cycles.saturating_sub(count);
+        match self.history.earliest() {
+            Some(earliest) if earliest <= target => match self.seek(target) {
+                Ok(()) => Stop::Done,
+                Err(stop) => stop,
+            },
+            Some(earliest) => match self.seek(earliest) {
+                Ok(()) => Stop::HistoryStart,
+                Err(stop) => stop,
+            },
+            None => Stop::HistoryStart,
+        }
+    }
+
+    // runs backwards to the last breakpoint or watchpoint hit before the current cycle. Every stretch between
+    // two snapshots is replayed, latest first, watching for hits without counting them or logging tracepoints.
+    pub fn reverse_continue(&mut self) -> Stop {
+        let now = self.cycles;
+        let mut end = now;
+        for start in self.history.starts(now) {
+            if let Err(stop) = self.replay(start) {
+                return stop;
+            }
+            let mut last = None;
+            while self.cycles < end {
+                let pc = self.proc.pc;
+                let stops = (0..self.breakpoints.len()).find(|i| self.at_pc(*i) && self.stops(*i));
+                if let Some(index) = stops {
+                    last = Some((self.cycles, Stop::Breakpoint(self.breakpoints[index].id)));
+                }
+                self.proc.keypad = self.history.keypad_at(self.cycles);
+                if self.step().is_err() {
+                    break;
+                }
+                let accesses = self.proc.memory.log.as_mut().map(std::mem::take).unwrap_or_default();
+                for access in accesses {
+                    let watch = (0..self.breakpoints.len())
+                        .find(|i| self.breakpoints[*i].trigger.watches(&access) && self.stops(*i));
+                    if let Some(index) = watch.filter(|_| self.cycles < now) {
+                        let id = self.breakpoints[index].id;
+                        last = Some((self.cycles, Stop::Watchpoint { id, access, pc }));
+                        break;
+                    }
+                }
+            }
+            if let Some((cycle, stop)) = last {
+                return self.seek(cycle).err().unwrap_or(stop);
+            }
+            end = start;
+        }
+        if let Some(earliest) = self.history.earliest() {
+            if let Err(stop) = self.seek(earliest) {
+                return stop;
+            }
+        }
+        Stop::HistoryStart
+    }
+
+    // tracepoints don't stop
+    fn stops(&self, index: usize) -> bool {
+        self.breakpoints[index].message.is_none() && self.holds(index)
+    }
+
+    // pc followed by the calls that led to it, innermost first
+    pub fn backtrace(&self) -> Vec<usize> {
+        let calls = self.proc.stack().iter().rev().map(|ret| *ret as usize - 2);
+        std::iter::once(self.proc.pc).chain(calls).collect()
+    }
+
+    // the instruction that last wrote to `addr`, along with how often it was read, written and executed
+    pub fn last_writer(&self, addr: usize) -> Option<(Option<usize>, [u32; 3])> {
+        let heat = self.proc.memory.heat.as_ref()?;
+        let counts = *heat.counts.get(addr)?;
+        Some((heat.writers[addr].map(|pc| pc as usize), counts))
+    }
+
+    // an address given as a number or a symbol name
+    pub fn resolve(&self, s: &str) -> Option<usize> {
+        parse_number(s).or_else(|| self.symbols.lookup(s).map(|s| s.value as usize))
+    }
+
+    pub fn symbolize(&self, addr: usize) -> String {
+        match self.symbols.label_before(addr as u16) {
+            Some((label, 0)) => format!("{addr:#05x} <{label}>"),
+            Some((label, offset)) => format!("{addr:#05x} <{label}+{offset}>"),
+            None => format!("{addr:#05x}"),
+        }
+    }
+
+    pub fn instruction_at(&self, addr: usize) -> Option<Instruction> {
+        let bs = self.proc.memory.get_word(addr).ok()?;
+        Instruction::parse(bs).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+    use std::path::Path;
+
+    #[test]
+    fn test_run_to_breakpoint() {
+        let source = "
+start:  ld v0, 3
+loop:   add v1, 2
+        add v0, 0xff
+        se v0, 0
+        jp loop
+done:   jp done
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let display = Arc::new(Mutex::new([0; 2048]));
+        let mut dbg = Debugger::new(&program.bytes, program.symbols, display).unwrap();
+
+        let done = dbg.resolve("done").unwrap();
+        let id = dbg.add(Trigger::Address(done), None, None).unwrap();
+        let stop = dbg.run(None, &mut |_| true);
+        assert!(matches!(stop, Stop::Breakpoint(hit) if hit == id));
+        assert_eq!(dbg.proc.pc, done);
+        assert_eq!(dbg.proc.registers.v1, 6);
+        assert_eq!(dbg.cycles, 1 + 3 * 4 - 1);
+        assert_eq!(dbg.symbolize(done + 2), "0x20c <done+2>");
+
+        // continuing from a breakpoint leaves it first
+        assert!(matches!(dbg.run(Some(5), &mut |_| true), Stop::Breakpoint(_)));
+        assert!(matches!(dbg.run(Some(1), &mut |_| true), Stop::Done));
+
+        dbg.reset();
+        assert_eq!(dbg.proc.pc, 0x200);
+        assert_eq!(dbg.breakpoints.len(), 1);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let source = "
+main:   call outer
+        jp main
+outer:  ld v0, 1
+        call inner
+        ld v1, 2
+        ret
+inner:  add v0, 1
+        ret
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let mut dbg = Debugger::new(&program.bytes, program.symbols, Arc::new(Mutex::new([0; 2048]))).unwrap();
+        let frame = &mut |_: &mut Processor| true;
+
+        // main -> outer -> inner
+        assert!(matches!(dbg.run_to(dbg.resolve("inner").unwrap(), frame), Stop::Done));
+        let frames: Vec<String> = dbg.backtrace().iter().map(|a| dbg.symbolize(*a)).collect();
+        assert_eq!(frames, vec!["0x20c <inner>", "0x206 <outer+2>", "0x200 <main>"]);
+
+        assert!(matches!(dbg.step_out(frame), Some(Stop::Done)));
+        assert_eq!((dbg.proc.pc, dbg.proc.sp, dbg.proc.registers.v0), (0x208, 1, 2));
+
+        dbg.reset();
+        assert!(matches!(dbg.step_over(frame), Stop::Done));
+        assert_eq!((dbg.proc.pc, dbg.proc.sp, dbg.proc.registers.v1), (0x202, 0, 2));
+        assert!(dbg.step_out(frame).is_none());
+
+        // breakpoints inside the skipped call still stop
+        dbg.add(Trigger::Address(0x20c), None, None).unwrap();
+        dbg.step_over(frame);
+        assert!(matches!(dbg.step_over(frame), Stop::Breakpoint(_)));
+        assert_eq!(dbg.proc.pc, 0x20c);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let source = "
+loop:   rnd v0, 0xff
+        sknp v2
+        add v1, 1
+        add v3, 1
+        ld i, 0x300
+        se v3, 0x80
+        jp loop
+        ld [i], v0
+        jp loop
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let mut dbg = Debugger::new(&program.bytes, program.symbols, Arc::new(Mutex::new([0; 2048]))).unwrap();
+        let frame = &mut |_: &mut Processor| true;
+        let state = |dbg: &Debugger| (dbg.proc.pc, dbg.proc.registers.as_array(), dbg.proc.memory.mem[0x300]);
+
+        // random numbers and key presses come back the same
+        dbg.run(Some(1200), frame);
+        dbg.proc.keypad[0] = true;
+        dbg.run(Some(500), frame);
+        dbg.proc.keypad[0] = false;
+        dbg.run(Some(800), frame);
+        let before = state(&dbg);
+        dbg.run(Some(2000), frame);
+        let after = state(&dbg);
+        assert!(matches!(dbg.reverse_step(2000), Stop::Done));
+        assert_eq!((dbg.cycles, state(&dbg)), (2500, before));
+        dbg.run(Some(2000), frame);
+        assert_eq!(state(&dbg), after);
+
+        let write = dbg.add(Trigger::Write(0x300..0x301), None, None).unwrap();
+        let stop = dbg.reverse_continue();
+        assert!(matches!(stop, Stop::Watchpoint { id, pc: 0x20e, .. } if id == write));
+        assert_eq!(
+            (dbg.proc.registers.v3, dbg.proc.memory.mem[0x300]),
+            (0x80, dbg.proc.registers.v0)
+        );
+        let cycles = dbg.cycles;
+
+        dbg.add(Trigger::Address(0x200), Some("v3 == 0x40"), None).unwrap();
+        assert!(matches!(dbg.reverse_continue(), Stop::Breakpoint(_)));
+        assert_eq!((dbg.proc.pc, dbg.proc.registers.v3), (0x200, 0x40));
+        assert!(cycles - dbg.cycles < 8 * 0x40);
+
+        dbg.clear();
+        assert!(matches!(dbg.reverse_continue(), Stop::HistoryStart));
+        assert_eq!(dbg.cycles, 0);
+
+        // where pixels were drawn from goes back with the display
+        let source = "
+        ld i, 0x50
+loop:   drw v0, v1, 1
+        add v0, 8
+        jp loop
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let display = Arc::new(Mutex::new([0; 2048]));
+        let mut dbg = Debugger::new(&program.bytes, program.symbols, Arc::clone(&display)).unwrap();
+        dbg.proc.provenance = Some(Default::default());
+        let drawn = |dbg: &Debugger| {
+            let pixels = &dbg.proc.provenance.as_ref().unwrap().pixels;
+            (
+                pixels.iter().filter(|o| o.is_some()).count(),
+                display.lock().unwrap().iter().filter(|p| **p != 0).count(),
+            )
+        };
+        dbg.run(Some(20), frame);
+        assert_eq!(drawn(&dbg), (28, 28));
+        assert!(matches!(dbg.reverse_step(15), Stop::Done));
+        assert_eq!(drawn(&dbg), (8, 8));
+    }
+
+    #[test]
+    fn test_watch_and_trace() {
+        let source = "
+        ld i, buffer
+        ld v0, 1
+loop:   ld v1, v0
+        ld [i], v1
+        add v0, 1
+        jp loop
+buffer: db 0, 0
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let mut dbg = Debugger::new(&program.bytes, program.symbols, Arc::new(Mutex::new([0; 2048]))).unwrap();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&messages);
+        dbg.trace = Box::new(move |m| log.lock().unwrap().push(m.to_string()));
+
+        assert!(dbg.proc.memory.log.is_none());
+        dbg.add(
+            Trigger::Opcode(Pattern::parse("Fx55").unwrap()),
+            None,
+            Some("I={i:x} v0={v0}"),
+        )
+        .unwrap();
+        let watch = dbg.add(Trigger::Write(0x210..0x212), Some("v0 == 3"), None).unwrap();
+        assert!(dbg.proc.memory.log.is_some());
+
+        // chip8 quirks advance I, so the third store hits buffer + 4
+        let stop = dbg.run(None, &mut |_| true);
+        assert!(matches!(stop, Stop::Watchpoint { id, access, pc }
+            if id == watch && access.addr == 0x210 && pc == 0x206));
+        assert_eq!(
+            *messages.lock().unwrap(),
+            vec!["I=0x20c v0=1", "I=0x20e v0=2", "I=0x210 v0=3"]
+        );
+        assert!(Expr::parse("v0 +", &dbg.symbols).is_err());
+        assert_eq!(
+            Expr::parse("(v0 << 4 | 1) == [buffer] + 0x30 && !(i < buffer)", &dbg.symbols)
+                .unwrap()
+                .eval(&dbg),
+            Some(1)
+        );
+
+        dbg.clear();
+        assert!(dbg.proc.memory.log.is_none());
+    }
+}
@@ -0,0 +1,754 @@
+use super::{parse_number, Breakpoint, Debugger, Pattern, Stop, Trigger, INSTRUCTIONS_PER_FRAME};
+use crate::asm::Symbols;
+use crate::disp::overlay::Status;
+use crate::mem::AccessKind;
+use crate::proc::{Processor, Provenance};
+use crate::sprite::SharedMemory;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+static DISASSEMBLY_LINES: usize = 10;
+static HEXDUMP_LEN: usize = 64;
+
+static HELP: &str = "commands:
+  s, step [n]              execute n instructions (default 1)
+  n, next [n]              step over subroutine calls
+  finish                   run until the current subroutine returns
+  until <addr>             run until pc reaches an address or symbol
+  c, continue              run until a breakpoint, an error or an interrupt (enter or space in the window)
+  rs, rstep [n]            go back n instructions
+  rc, rcontinue            go back to the last breakpoint or watchpoint hit
+  b, break [addr] [if c]   set a breakpoint at an address or symbol, or list breakpoints
+  b op <pattern> [if c]    break on an opcode pattern, hex digits match exactly: Dxyn, Fx55, 8xy6
+  watch [r|w|rw] <range> [if c]
+                           stop after memory in addr, addr..end or addr+len is read or written (default w)
+  trace <addr|op pattern> \"msg\" [if c]
+                           log msg without stopping, {expr} in it is replaced by its value, {expr:x} in hex
+  d, delete <id|all>       clear a breakpoint, watchpoint or tracepoint
+  r, regs                  show registers, timers and the stack
+  stack                    show the return addresses on the stack
+  bt, backtrace            show the calls leading to pc
+  symbols <file>           load an assembler symbol file
+  x <addr> [len]           hexdump memory
+  who <addr>               show which instruction last wrote an address, and how often it was accessed
+  pixel [x y]              show which instruction and sprite drew a pixel, or the pixels the last draw turned off
+  set <reg|addr> <value..> set v0-vf, i, pc, dt or st, or write bytes to memory
+  l, dis [addr] [n]        disassemble around pc, or n instructions from addr
+  fb, screen               show the framebuffer
+  key [k [up]]             press or release keypad key k, or show the keypad
+  reset                    reload the rom
+  q, quit                  leave the debugger
+conditions and {expr} are expressions over v0-vf, i, pc, sp, dt, st, cycles, symbols and [addr] for memory,
+with the operators of C: v3 == 5 && i > 0x300
+an empty line repeats the last command";
+
+pub struct Repl {
+    pub dbg: Debugger,
+    pub quit: bool,
+    // keys held in the SDL window, copied into the keypad on every frame
+    keys: Option<Arc<Mutex<[bool; 16]>>>,
+    // set by the window to stop a running program
+    interrupt: Arc<AtomicBool>,
+    // lines read from the terminal, any line typed while running stops the program
+    input: Option<Receiver<String>>,
+    interactive: bool,
+    // a command typed while running, executed once the program stopped
+    pending: Option<String>,
+    // with a window attached programs run at 60 frames per second instead of as fast as possible
+    realtime: bool,
+    shared: Shared,
+    last: String,
+}
+
+// splits "location if condition"
+fn split_condition(s: &str) -> (&str, Option<&str>) {
+    match s.split_once(" if ") {
+        Some((s, condition)) => (s.trim(), Some(condition.trim())),
+        None => (s, None),
+    }
+}
+
+// state shown live in windows next to the display, copied out on every frame and after every command
+#[derive(Clone, Default)]
+struct Shared {
+    // access counts, for the heatmap
+    heat: Option<Arc<Mutex<Vec<[u32; 3]>>>>,
+    // memory and I, for the sprite viewer
+    memory: Option<SharedMemory>,
+    // registers and code around pc, for the overlay
+    status: Option<Arc<Mutex<Status>>>,
+    // where pixels were drawn from, for hovering over the display
+    provenance: Option<Arc<Mutex<Provenance>>>,
+}
+
+impl Shared {
+    fn update(&self, proc: &Processor, cycles: u64) {
+        if let (Some(shared), Some(heat)) = (&self.heat, &proc.memory.heat) {
+            shared.lock().unwrap().copy_from_slice(&heat.counts);
+        }
+        if let Some(shared) = &self.memory {
+            let mut shared = shared.lock().unwrap();
+            shared.0.copy_from_slice(&proc.memory.mem);
+            shared.1 = proc.i;
+        }
+        if let Some(status) = &self.status {
+            *status.lock().unwrap() = Status::capture(proc, cycles);
+        }
+        if let (Some(shared), Some(provenance)) = (&self.provenance, &proc.provenance) {
+            shared.lock().unwrap().clone_from(provenance);
+        }
+    }
+}
+
+fn number<T: TryFrom<usize>>(s: Option<&str>, what: &str) -> Result<T, String> {
+    let s = s.ok_or(format!("missing {what}"))?;
+    parse_number(s)
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or(format!("invalid {what} '{s}'"))
+}
+
+impl Repl {
+    pub fn new(dbg: Debugger) -> Self {
+        Repl {
+            dbg,
+            quit: false,
+            keys: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            input: None,
+            interactive: false,
+            pending: None,
+            realtime: false,
+            shared: Shared::default(),
+            last: String::new(),
+        }
+    }
+
+    // hooks the repl up to an SDL window showing the display
+    pub fn attach(&mut self, keys: Arc<Mutex<[bool; 16]>>, interrupt: Arc<AtomicBool>) {
+        self.keys = Some(keys);
+        self.interrupt = interrupt;
+        self.realtime = true;
+    }
+
+    // shares the memory access counts with a heatmap window
+    pub fn attach_heatmap(&mut self, heat: Arc<Mutex<Vec<[u32; 3]>>>) {
+        self.shared.heat = Some(heat);
+        self.shared.update(&self.dbg.proc, self.dbg.cycles);
+    }
+
+    // shares memory and I with a sprite viewer window
+    pub fn attach_sprites(&mut self, memory: SharedMemory) {
+        self.shared.memory = Some(memory);
+        self.shared.update(&self.dbg.proc, self.dbg.cycles);
+    }
+
+    // shares the registers and the code around pc with the window's overlay
+    pub fn attach_overlay(&mut self, status: Arc<Mutex<Status>>) {
+        self.shared.status = Some(status);
+        self.shared.update(&self.dbg.proc, self.dbg.cycles);
+    }
+
+    // shares where pixels were drawn from with the display window
+    pub fn attach_provenance(&mut self, provenance: Arc<Mutex<Provenance>>) {
+        self.shared.provenance = Some(provenance);
+        self.shared.update(&self.dbg.proc, self.dbg.cycles);
+    }
+
+    // reads commands from stdin until quit or end of input
+    pub fn run(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        self.input = Some(rx);
+        // piped commands shouldn't interrupt each other
+        self.interactive = io::stdin().is_terminal();
+
+        print!("{}", self.disassemble(self.dbg.proc.pc, 1));
+        while !self.quit {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => {
+                    print!("(dbg) ");
+                    let _ = io::stdout().flush();
+                    match self.input.as_ref().unwrap().recv() {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    }
+                }
+            };
+            match self.execute(&line) {
+                Ok(out) => print!("{out}"),
+                Err(e) => println!("error: {e}"),
+            }
+            self.shared.update(&self.dbg.proc, self.dbg.cycles);
+        }
+    }
+
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        self.last = line.clone();
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+        // everything after the command, for those taking expressions
+        let rest = line.trim_start()[command.len()..].trim();
+
+        let mut out = String::new();
+        match command {
+            "h" | "help" => writeln!(out, "{HELP}").unwrap(),
+            "s" | "step" => {
+                let count = args.first().map(|a| number(Some(a), "count")).transpose()?;
+                let count = count.unwrap_or(1);
+                let stop = self.resume(Some(count));
+                out += &self.report(stop);
+            }
+            "rs" | "rstep" => {
+                let count = args.first().map(|a| number(Some(a), "count")).transpose()?;
+                let stop = self.dbg.reverse_step(count.unwrap_or(1));
+                out += &self.report(stop);
+            }
+            "rc" | "rcontinue" => {
+                let stop = self.dbg.reverse_continue();
+                out += &self.report(stop);
+            }
+            "n" | "next" => {
+                let count = args.first().map(|a| number(Some(a), "count")).transpose()?;
+                for _ in 0..count.unwrap_or(1) {
+                    let stop = self.with_frame(|dbg, frame| dbg.step_over(frame));
+                    if !matches!(stop, Stop::Done) {
+                        out += &self.report(stop);
+                        return Ok(out);
+                    }
+                }
+                out += &self.report(Stop::Done);
+            }
+            "finish" => {
+                let stop = self
+                    .with_frame(|dbg, frame| dbg.step_out(frame))
+                    .ok_or("not in a subroutine")?;
+                out += &self.report(stop);
+            }
+            "until" => {
+                let addr = self.address(args.first().ok_or("missing address")?)?;
+                let stop = self.with_frame(|dbg, frame| dbg.run_to(addr, frame));
+                out += &self.report(stop);
+            }
+            "c" | "continue" => {
+                let stop = self.resume(None);
+                out += &self.report(stop);
+            }
+            "b" | "break" if args.is_empty() => {
+                for b in self.dbg.breakpoints.iter() {
+                    writeln!(out, "{}", self.describe(b)).unwrap();
+                }
+            }
+            "b" | "break" => {
+                let (location, condition) = split_condition(rest);
+                let trigger = self.location(location)?;
+                self.dbg.add(trigger, condition, None).map_err(|e| e.to_string())?;
+                writeln!(out, "{}", self.describe(self.dbg.breakpoints.last().unwrap())).unwrap();
+            }
+            "watch" => {
+                let (range, condition) = split_condition(rest);
+                let (kind, range) = match range.split_once(' ') {
+                    Some((kind @ ("r" | "w" | "rw"), range)) => (kind, range.trim()),
+                    _ => ("w", range),
+                };
+                let range = self.range(range)?;
+                let trigger = match kind {
+                    "r" => Trigger::Read(range),
+                    "w" => Trigger::Write(range),
+                    _ => Trigger::Access(range),
+                };
+                self.dbg.add(trigger, condition, None).map_err(|e| e.to_string())?;
+                writeln!(out, "{}", self.describe(self.dbg.breakpoints.last().unwrap())).unwrap();
+            }
+            "trace" => {
+                let (location, message) = rest.split_once('"').ok_or("missing \"message\"")?;
+                let (message, condition) = message.rsplit_once('"').ok_or("unterminated message")?;
+                let condition = match condition.trim() {
+                    "" => None,
+                    c => Some(c.strip_prefix("if ").ok_or(format!("unexpected '{c}'"))?.trim()),
+                };
+                let trigger = self.location(location.trim())?;
+                self.dbg
+                    .add(trigger, condition, Some(message))
+                    .map_err(|e| e.to_string())?;
+                writeln!(out, "{}", self.describe(self.dbg.breakpoints.last().unwrap())).unwrap();
+            }
+            "d" | "delete" => match args.first() {
+                Some(&"all") => self.dbg.clear(),
+                Some(id) => {
+                    let id = number(Some(id), "id")?;
+                    if !self.dbg.remove(id) {
+                        return Err(format!("no breakpoint {id}"));
+                    }
+                }
+                None => return Err("missing id".to_string()),
+            },
+            "r" | "regs" => out += &self.registers(),
+            "stack" => {
+                for addr in self.dbg.proc.stack().iter().rev() {
+                    writeln!(out, "{}", self.dbg.symbolize(*addr as usize)).unwrap();
+                }
+            }
+            "bt" | "backtrace" => {
+                for (n, addr) in self.dbg.backtrace().iter().enumerate() {
+                    writeln!(out, "#{n} {}", self.dbg.symbolize(*addr)).unwrap();
+                }
+            }
+            "symbols" => {
+                let path = args.first().ok_or("missing file")?;
+                self.dbg.symbols = Symbols::load(Path::new(path)).map_err(|e| e.to_string())?;
+                writeln!(out, "{} symbols", self.dbg.symbols.symbols.len()).unwrap();
+            }
+            "who" => {
+                let addr = self.address(args.first().ok_or("missing address")?)?;
+                let (writer, counts) = self.dbg.last_writer(addr).ok_or("no access counts")?;
+                match writer {
+                    Some(pc) => write!(out, "{addr:#05x} last written by {}", self.dbg.symbolize(pc)).unwrap(),
+                    None => write!(out, "{addr:#05x} never written").unwrap(),
+                }
+                writeln!(
+                    out,
+                    ", read {} written {} executed {} times",
+                    counts[AccessKind::Read as usize],
+                    counts[AccessKind::Write as usize],
+                    counts[AccessKind::Execute as usize]
+                )
+                .unwrap();
+            }
+            "pixel" => {
+                let provenance = self.dbg.proc.provenance.as_ref().ok_or("no pixel provenance")?;
+                if args.is_empty() {
+                    let pixels: Vec<String> = provenance
+                        .collisions
+                        .iter()
+                        .map(|p| format!("{},{}", p % 64, p / 64))
+                        .collect();
+                    if pixels.is_empty() {
+                        writeln!(out, "the last draw turned no pixels off").unwrap();
+                    } else {
+                        writeln!(out, "the last draw turned off {}", pixels.join(" ")).unwrap();
+                    }
+                } else {
+                    let x: usize = number(args.first().copied(), "x")?;
+                    let y: usize = number(args.get(1).copied(), "y")?;
+                    if x >= 64 || y >= 32 {
+                        return Err(format!("{x},{y} is off the screen"));
+                    }
+                    match provenance.pixels[x + y * 64] {
+                        Some(origin) => writeln!(
+                            out,
+                            "{x},{y} drawn by {} from {:#05x}, sprite at {:#05x}",
+                            self.dbg.symbolize(origin.pc as usize),
+                            origin.row,
+                            origin.i
+                        )
+                        .unwrap(),
+                        None => writeln!(out, "{x},{y} not drawn since the last clear").unwrap(),
+                    }
+                }
+            }
+            "x" => {
+                let addr = self.address(args.first().ok_or("missing address")?)?;
+                let len = args.get(1).map(|a| number(Some(a), "length")).transpose()?;
+                let len = len.unwrap_or(HEXDUMP_LEN);
+                out += &self.hexdump(addr, len);
+            }
+            "set" => self.set(&args)?,
+            "l" | "dis" => {
+                let (addr, count) = match args.first() {
+                    Some(a) => {
+                        let count = args.get(1).map(|a| number(Some(a), "count")).transpose()?;
+                        let count = count.unwrap_or(DISASSEMBLY_LINES);
+                        (self.address(a)?, count)
+                    }
+                    // start a few instructions before pc
+                    None => (self.dbg.proc.pc.saturating_sub(8).max(0x200), DISASSEMBLY_LINES),
+                };
+                out += &self.disassemble(addr, count);
+            }
+            "fb" | "screen" => {
+                let display = self.dbg.display.lock().unwrap();
+                for row in display.chunks(64) {
+                    let line: String = row.iter().map(|p| if *p == 1 { '#' } else { '.' }).collect();
+                    writeln!(out, "{line}").unwrap();
+                }
+            }
+            "key" => match args.first() {
+                Some(k) => {
+                    let k = u8::from_str_radix(k, 16)
+                        .ok()
+                        .filter(|k| *k < 16)
+                        .ok_or("keys are 0-f")?;
+                    self.dbg.proc.keypad[k as usize] = args.get(1) != Some(&"up");
+                }
+                None => {
+                    let down: Vec<String> = (0..16)
+                        .filter(|k| self.dbg.proc.keypad[*k])
+                        .map(|k| format!("{k:X}"))
+                        .collect();
+                    writeln!(out, "keys down: {}", down.join(" ")).unwrap();
+                }
+            },
+            "reset" => {
+                self.dbg.reset();
+                out += &self.disassemble(self.dbg.proc.pc, 1);
+            }
+            "q" | "quit" => self.quit = true,
+            _ => return Err(format!("unknown command '{command}', try help")),
+        }
+        Ok(out)
+    }
+
+    fn address(&self, s: &str) -> Result<usize, String> {
+        self.dbg
+            .resolve(s)
+            .filter(|a| *a < 4096)
+            .ok_or(format!("unknown address '{s}'"))
+    }
+
+    // an address or symbol, or op followed by an opcode pattern
+    fn location(&self, s: &str) -> Result<Trigger, String> {
+        match s.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["op", pattern] => Pattern::parse(pattern)
+                .map(Trigger::Opcode)
+                .ok_or(format!("invalid pattern '{pattern}'")),
+            [addr] => Ok(Trigger::Address(self.address(addr)?)),
+            _ => Err("expected an address, a symbol or op <pattern>".to_string()),
+        }
+    }
+
+    // addr, addr..end or addr+len
+    fn range(&self, s: &str) -> Result<Range<usize>, String> {
+        let range = if let Some((start, end)) = s.split_once("..") {
+            self.address(start.trim())?..self.address(end.trim())?
+        } else if let Some((start, len)) = s.split_once('+') {
+            let start = self.address(start.trim())?;
+            start..start + number::<usize>(Some(len.trim()), "length")?
+        } else {
+            let addr = self.address(s)?;
+            addr..addr + 1
+        };
+        match range.is_empty() {
+            true => Err(format!("empty range '{s}'")),
+            false => Ok(range),
+        }
+    }
+
+    fn describe(&self, b: &Breakpoint) -> String {
+        let mut out = match (&b.trigger, &b.message) {
+            (Trigger::Address(addr), None) => format!("{}: break {}", b.id, self.dbg.symbolize(*addr)),
+            (Trigger::Opcode(pattern), None) => format!("{}: break op {pattern}", b.id),
+            (Trigger::Address(addr), Some(m)) => {
+                format!("{}: trace {} \"{}\"", b.id, self.dbg.symbolize(*addr), m.text)
+            }
+            (Trigger::Opcode(pattern), Some(m)) => format!("{}: trace op {pattern} \"{}\"", b.id, m.text),
+            (Trigger::Read(r), _) => format!("{}: watch r {:#05x}..{:#05x}", b.id, r.start, r.end),
+            (Trigger::Write(r), _) => format!("{}: watch w {:#05x}..{:#05x}", b.id, r.start, r.end),
+            (Trigger::Access(r), _) => format!("{}: watch rw {:#05x}..{:#05x}", b.id, r.start, r.end),
+        };
+        if b.condition.is_some() {
+            write!(out, " if {}", b.source).unwrap();
+        }
+        if b.hits > 0 {
+            write!(out, " ({} hits)", b.hits).unwrap();
+        }
+        out
+    }
+
+    fn resume(&mut self, count: Option<usize>) -> Stop {
+        self.with_frame(|dbg, frame| dbg.run(count, frame))
+    }
+
+    // runs `f` with a frame callback that feeds in the window's keys, keeps real time and watches for interrupts
+    fn with_frame<T>(&mut self, f: impl FnOnce(&mut Debugger, &mut dyn FnMut(&mut Processor) -> bool) -> T) -> T {
+        let keys = self.keys.clone();
+        let interrupt = Arc::clone(&self.interrupt);
+        let input = self.input.as_ref().filter(|_| self.interactive);
+        let pending = &mut self.pending;
+        let realtime = self.realtime;
+        let shared = self.shared.clone();
+        // the callback runs every INSTRUCTIONS_PER_FRAME cycles
+        let mut cycles = self.dbg.cycles;
+        let mut next_frame = Instant::now();
+        interrupt.store(false, Ordering::Relaxed);
+        f(&mut self.dbg, &mut |proc: &mut Processor| {
+            if let Some(keys) = &keys {
+                proc.keypad = *keys.lock().unwrap();
+            }
+            cycles = (cycles / INSTRUCTIONS_PER_FRAME + 1) * INSTRUCTIONS_PER_FRAME;
+            shared.update(proc, cycles);
+            if realtime {
+                next_frame += FRAME;
+                thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+            }
+            let typed = match input.map(|i| i.try_recv()) {
+                Some(Ok(line)) => {
+                    if !line.trim().is_empty() {
+                        *pending = Some(line);
+                    }
+                    true
+                }
+                _ => false,
+            };
+            !typed && !interrupt.swap(false, Ordering::Relaxed)
+        })
+    }
+
+    fn report(&self, stop: Stop) -> String {
+        let pc = self.dbg.proc.pc;
+        let mut out = match stop {
+            Stop::Done => String::new(),
+            Stop::Breakpoint(id) => format!("breakpoint {id} at {}\n", self.dbg.symbolize(pc)),
+            Stop::Watchpoint { id, access, pc } => {
+                let kind = match access.kind {
+                    AccessKind::Read => "read",
+                    _ => "write",
+                };
+                let value = self.dbg.proc.memory.mem[access.addr];
+                format!(
+                    "watchpoint {id}: {kind} of {value:#04x} at {:#05x} by {}\n",
+                    access.addr,
+                    self.dbg.symbolize(pc)
+                )
+            }
+            Stop::Interrupted => format!("interrupted at {}\n", self.dbg.symbolize(pc)),
+            Stop::Error(e) => format!("error: {e} at {}\n", self.dbg.symbolize(pc)),
+            Stop::HistoryStart => format!("reached the start of the history at cycle {}\n", self.dbg.cycles),
+        };
+        out += &self.disassemble(pc, 1);
+        out
+    }
+
+    fn registers(&self) -> String {
+        let proc = &self.dbg.proc;
+        let mut out = String::new();
+        for (r, v) in proc.registers.as_array().iter().enumerate() {
+            let sep = if r % 8 == 7 { "\n" } else { "  " };
+            write!(out, "V{r:X} {v:02x}{sep}").unwrap();
+        }
+        writeln!(
+            out,
+            "I  {:#05x}  PC {}  SP {}  DT {:02x}  ST {:02x}  cycles {}",
+            proc.i,
+            self.dbg.symbolize(proc.pc),
+            proc.sp,
+            proc.delay_timer,
+            proc.sound_timer,
+            self.dbg.cycles
+        )
+        .unwrap();
+        let stack: Vec<String> = proc.stack().iter().map(|a| format!("{a:#05x}")).collect();
+        writeln!(out, "stack: {}", stack.join(" ")).unwrap();
+        out
+    }
+
+    fn hexdump(&self, addr: usize, len: usize) -> String {
+        let end = (addr + len).min(4096);
+        let memory = &self.dbg.proc.memory.mem;
+        let mut out = String::new();
+        for start in (addr..end).step_by(16) {
+            let row = &memory[start..(start + 16).min(end)];
+            let hex: Vec<String> = row.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = row
+                .iter()
+                .map(|b| if b.is_ascii_graphic() { *b as char } else { '.' })
+                .collect();
+            writeln!(out, "{start:#05x}: {:<47}  |{ascii}|", hex.join(" ")).unwrap();
+        }
+        out
+    }
+
+    fn disassemble(&self, addr: usize, count: usize) -> String {
+        let mut out = String::new();
+        for addr in (addr..4095).step_by(2).take(count) {
+            if let Some(label) = self.dbg.symbols.label_at(addr as u16) {
+                writeln!(out, "{label}:").unwrap();
+            }
+            let marker = if addr == self.dbg.proc.pc { "=>" } else { "  " };
+            let bp = if self.dbg.breakpoint_at(addr) { '*' } else { ' ' };
+            let bs = &self.dbg.proc.memory.mem[addr..addr + 2];
+            let text = match self.dbg.instruction_at(addr) {
+                Some(inst) => inst.to_string(),
+                None => format!("DB {:#04x}, {:#04x}", bs[0], bs[1]),
+            };
+            writeln!(out, "{marker}{bp} {addr:#05x}: {:02x} {:02x}  {text}", bs[0], bs[1]).unwrap();
+        }
+        out
+    }
+
+    fn set(&mut self, args: &[&str]) -> Result<(), String> {
+        let target = args.first().ok_or("missing register or address")?.to_lowercase();
+        let value = args.get(1).copied();
+        match target.as_str() {
+            "i" => self.dbg.proc.i = self.address(value.ok_or("missing value")?)? as u16,
+            "pc" => self.dbg.proc.pc = self.address(value.ok_or("missing value")?)?,
+            "dt" => self.dbg.proc.delay_timer = number(value, "value")?,
+            "st" => self.dbg.proc.sound_timer = number(value, "value")?,
+            r if r.len() == 2 && r.starts_with('v') => {
+                let index = u8::from_str_radix(&r[1..], 16).map_err(|_| format!("unknown register '{r}'"))?;
+                let v = number(value, "value")?;
+                self.dbg.proc.registers.set(index, v).map_err(|e| e.to_string())?;
+            }
+            _ => {
+                let addr = self.address(&target)?;
+                if value.is_none() {
+                    return Err("missing value".to_string());
+                }
+                for (offset, v) in args[1..].iter().enumerate() {
+                    let v = number(Some(v), "byte")?;
+                    self.dbg
+                        .proc
+                        .memory
+                        .set_byte(addr + offset, v)
+                        .map_err(|_| "address out of range")?;
+                }
+            }
+        }
+        self.dbg.checkpoint();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+    use std::path::Path;
+
+    #[test]
+    fn test_commands() {
+        let source = "
+start:  ld i, sprite
+        ld v0, 1
+        drw v0, v0, 2
+        call sub
+end:    jp end
+sub:    ret
+sprite: db 0b11000000, 0b01000000
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let dbg = Debugger::new(&program.bytes, program.symbols, Arc::new(Mutex::new([0; 2048]))).unwrap();
+        let mut repl = Repl::new(dbg);
+
+        assert_eq!(repl.execute("b sub").unwrap(), "1: break 0x20a <sub>\n");
+        assert_eq!(
+            repl.execute("c").unwrap(),
+            "breakpoint 1 at 0x20a <sub>\nsub:\n=>* 0x20a: 00 ee  RET\n"
+        );
+        assert_eq!(repl.execute("stack").unwrap(), "0x208 <end>\n");
+        assert_eq!(repl.execute("bt").unwrap(), "#0 0x20a <sub>\n#1 0x206 <start+6>\n");
+        assert!(repl.execute("r").unwrap().contains("PC 0x20a <sub>  SP 1"));
+
+        // an empty line steps again
+        repl.execute("s").unwrap();
+        assert_eq!(repl.execute("").unwrap(), "end:\n=>  0x208: 12 08  JP 0x208\n");
+
+        let screen = repl.execute("fb").unwrap();
+        let screen: Vec<&str> = screen.lines().map(|l| &l[..4]).take(3).collect();
+        assert_eq!(screen, vec!["....", ".##.", "..#."]);
+        assert_eq!(
+            repl.execute("pixel 2 2").unwrap(),
+            "2,2 drawn by 0x204 <start+4> from 0x20d, sprite at 0x20c\n"
+        );
+        assert_eq!(
+            repl.execute("pixel 0 0").unwrap(),
+            "0,0 not drawn since the last clear\n"
+        );
+        assert_eq!(repl.execute("pixel").unwrap(), "the last draw turned no pixels off\n");
+        assert!(repl.execute("pixel 64 0").is_err());
+
+        repl.execute("set v3 0x2a").unwrap();
+        assert_eq!(repl.dbg.proc.registers.v3, 0x2a);
+        repl.execute("set 0x300 1 2 0x41").unwrap();
+        assert!(repl.execute("x 0x300 4").unwrap().starts_with("0x300: 01 02 41 00"));
+        assert!(repl.execute("set vg 1").is_err());
+        assert!(repl.execute("b nowhere").is_err());
+
+        assert_eq!(
+            repl.execute("watch rw sprite+2 if v0 == 1").unwrap(),
+            "2: watch rw 0x20c..0x20e if v0 == 1\n"
+        );
+        assert_eq!(
+            repl.execute("trace op Dxyn \"drew at {i:x}\"").unwrap(),
+            "3: trace op D??? \"drew at {i:x}\"\n"
+        );
+        repl.execute("reset").unwrap();
+        assert_eq!(
+            repl.execute("c").unwrap(),
+            "watchpoint 2: read of 0xc0 at 0x20c by 0x204 <start+4>\n=>  0x206: 22 0a  CALL 0x20a\n"
+        );
+        assert!(repl
+            .execute("b")
+            .unwrap()
+            .contains("3: trace op D??? \"drew at {i:x}\" (1 hits)"));
+        assert_eq!(
+            repl.execute("who sprite").unwrap(),
+            "0x20c never written, read 1 written 0 executed 0 times\n"
+        );
+        repl.execute("d all").unwrap();
+        assert_eq!(repl.execute("b").unwrap(), "");
+
+        // drawing the sprite again erases it
+        repl.execute("set pc 0x204").unwrap();
+        repl.execute("s").unwrap();
+        assert_eq!(repl.execute("pixel").unwrap(), "the last draw turned off 1,1 2,1 2,2\n");
+    }
+
+    #[test]
+    fn test_reverse_who() {
+        let source = "
+start:  ld i, buffer
+        ld [i], v0
+        ld i, buffer
+        ld [i], v0
+end:    jp end
+buffer: db 0
+";
+        let program = asm::assemble_source("test.asm", source, Path::new(".")).unwrap();
+        let dbg = Debugger::new(&program.bytes, program.symbols, Arc::new(Mutex::new([0; 2048]))).unwrap();
+        let mut repl = Repl::new(dbg);
+
+        repl.execute("s 4").unwrap();
+        assert_eq!(
+            repl.execute("who buffer").unwrap(),
+            "0x20a last written by 0x206 <start+6>, read 0 written 2 executed 0 times\n"
+        );
+        // going back forgets the accesses after it, rather than counting them again when replaying
+        repl.execute("rs 2").unwrap();
+        assert_eq!(
+            repl.execute("who buffer").unwrap(),
+            "0x20a last written by 0x202 <start+2>, read 0 written 1 executed 0 times\n"
+        );
+        repl.execute("rs 2").unwrap();
+        assert_eq!(
+            repl.execute("who buffer").unwrap(),
+            "0x20a never written, read 0 written 0 executed 0 times\n"
+        );
+        assert_eq!(
+            repl.execute("who start").unwrap(),
+            "0x200 never written, read 0 written 0 executed 0 times\n"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+#![allow(dead_code)]
+use crate::inst::{Flow, Instruction};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+static ORIGIN: usize = 512;
+static DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Asm,
+    Octo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum LabelKind {
+    Data,
+    Jump,
+    Subroutine,
+    Entry,
+}
+
+enum Item {
+    Code(Instruction),
+    Data(Vec<u8>),
+}
+
+// addresses (relative to the start of memory) that control flow reaches from the reset vector
+pub fn trace(rom: &[u8]) -> Vec<bool> {
+    let end = ORIGIN + rom.len();
+    let mut code = vec![false; end];
+    let mut pending = vec![ORIGIN];
+    while let Some(pc) = pending.pop() {
+        if pc < ORIGIN || pc + 1 >= end || code[pc] {
+            continue;
+        }
+        let Ok(inst) = Instruction::parse([rom[pc - ORIGIN], rom[pc + 1 - ORIGIN]]) else {
+            continue;
+        };
+        code[pc] = true;
+        match inst.flow() {
+            Flow::Next => pending.push(pc + 2),
+            Flow::Skip => pending.extend([pc + 2, pc + 4]),
+            Flow::Jump(addr) => pending.push(addr as usize),
+            Flow::Call(addr) => pending.extend([pc + 2, addr as usize]),
+            // the target of a computed jump depends on V0 and isn't known statically
+            Flow::Return | Flow::ComputedJump(_) => {}
+        }
+    }
+    code
+}
+
+fn items(rom: &[u8]) -> Vec<(usize, Item)> {
+    let code = trace(rom);
+    let end = ORIGIN + rom.len();
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut addr = ORIGIN;
+    while addr < end {
+        if code[addr] {
+            let inst = Instruction::parse([rom[addr - ORIGIN], rom[addr + 1 - ORIGIN]]).unwrap();
+            items.push((addr, Item::Code(inst)));
+            addr += 2;
+        } else {
+            match items.last_mut() {
+                Some((_, Item::Data(bs))) => bs.push(rom[addr - ORIGIN]),
+                _ => items.push((addr, Item::Data(vec![rom[addr - ORIGIN]]))),
+            }
+            addr += 1;
+        }
+    }
+    items
+}
+
+fn labels(items: &[(usize, Item)], end: usize, syntax: Syntax) -> BTreeMap<usize, String> {
+    let mut kinds: BTreeMap<usize, LabelKind> = BTreeMap::new();
+    kinds.insert(ORIGIN, LabelKind::Entry);
+    for (_, item) in items.iter() {
+        let Item::Code(inst) = item else {
+            continue;
+        };
+        let kind = match inst {
+            Instruction::CallSubroutine { .. } => LabelKind::Subroutine,
+            Instruction::Goto { .. } | Instruction::GotoPlusV0 { .. } => LabelKind::Jump,
+            Instruction::SetI { .. } => LabelKind::Data,
+            _ => continue,
+        };
+        let target = inst.address().unwrap() as usize;
+        let k = kinds.entry(target).or_insert(kind);
+        if kind > *k {
+            *k = kind;
+        }
+    }
+
+    // a label can't point into the middle of an instruction, those targets stay numeric
+    let mut inside_code = vec![false; end + 1];
+    for (addr, item) in items.iter() {
+        if let Item::Code(_) = item {
+            inside_code[addr + 1] = true;
+        }
+    }
+    kinds
+        .into_iter()
+        .filter(|(addr, _)| (ORIGIN..end).contains(addr) && !inside_code[*addr])
+        .map(|(addr, kind)| {
+            let name = match (kind, syntax) {
+                (LabelKind::Entry, Syntax::Asm) => "start".to_string(),
+                (LabelKind::Entry, Syntax::Octo) => "main".to_string(),
+                (LabelKind::Subroutine, _) => format!("sub_{addr:03x}"),
+                (LabelKind::Jump, _) => format!("label_{addr:03x}"),
+                (LabelKind::Data, _) => format!("data_{addr:03x}"),
+            };
+            (addr, name)
+        })
+        .collect()
+}
+
+fn asm_statement(inst: &Instruction, target: &str) -> String {
+    match inst {
+        Instruction::Call { .. } => format!("SYS {target}"),
+        Instruction::Goto { .. } => format!("JP {target}"),
+        Instruction::GotoPlusV0 { .. } => format!("JP V0, {target}"),
+        Instruction::CallSubroutine { .. } => format!("CALL {target}"),
+        Instruction::SetI { .. } => format!("LD I, {target}"),
+        _ => inst.to_string(),
+    }
+}
+
+fn octo_statement(inst: &Instruction, target: &str) -> String {
+    let v = |r: &u8| format!("v{r:x}");
+    match inst {
+        Instruction::Call { .. } => format!("native {target}"),
+        Instruction::ClearDisplay => "clear".to_string(),
+        Instruction::Return => "return".to_string(),
+        Instruction::Goto { .. } => format!("jump {target}"),
+        Instruction::GotoPlusV0 { .. } => format!("jump0 {target}"),
+        Instruction::CallSubroutine { .. } => format!(":call {target}"),
+        // skips read as the inverse condition guarding the next instruction
+        Instruction::SkipIfRegisterEquals { register, value } => format!("if {} != {value:#04x} then", v(register)),
+        Instruction::SkipIfRegisterNotEquals { register, value } => {
+            format!("if {} == {value:#04x} then", v(register))
+        }
+        Instruction::SkipIfRegistersEqual { register_1, register_2 } => {
+            format!("if {} != {} then", v(register_1), v(register_2))
+        }
+        Instruction::SkipIfRegistersNotEqual { register_1, register_2 } => {
+            format!("if {} == {} then", v(register_1), v(register_2))
+        }
+        Instruction::SkipIfKeyPressed { register } => format!("if {} -key then", v(register)),
+        Instruction::SkipIfKeyNotPressed { register } => format!("if {} key then", v(register)),
+        Instruction::SetRegister { register, value } => format!("{} := {value:#04x}", v(register)),
+        Instruction::SetRegisterRandomBitwiseAnd { register, and_operand } => {
+            format!("{} := random {and_operand:#04x}", v(register))
+        }
+        Instruction::AddToRegister { register, value } => format!("{} += {value:#04x}", v(register)),
+        Instruction::CopyRegister {
+            src_register,
+            dst_register,
+        } => format!("{} := {}", v(dst_register), v(src_register)),
+        Instruction::ApplyBitwiseOr {
+            value_register,
+            operand_register,
+        } => format!("{} |= {}", v(value_register), v(operand_register)),
+        Instruction::ApplyBitwiseAnd {
+            value_register,
+            operand_register,
+        } => format!("{} &= {}", v(value_register), v(operand_register)),
+        Instruction::ApplyBitwiseXor {
+            value_register,
+            operand_register,
+        } => format!("{} ^= {}", v(value_register), v(operand_register)),
+        Instruction::AddRegisters {
+            value_register,
+            operand_register,
+        } => format!("{} += {}", v(value_register), v(operand_register)),
+        Instruction::SubtractRegisters {
+            value_register,
+            operand_register,
+        } => format!("{} -= {}", v(value_register), v(operand_register)),
+        Instruction::SubtractRegistersReversed {
+            value_register,
+            operand_register,
+        } => format!("{} =- {}", v(value_register), v(operand_register)),
+        Instruction::ShiftRight {
+            value_register,
+            operand_register,
+        } => format!("{} >>= {}", v(value_register), v(operand_register)),
+        Instruction::ShiftLeft {
+            value_register,
+            operand_register,
+        } => format!("{} <<= {}", v(value_register), v(operand_register)),
+        Instruction::GetKey { register } => format!("{} := key", v(register)),
+        Instruction::GetDelayTimer { register } => format!("{} := delay", v(register)),
+        Instruction::SetDelayTimer { register } => format!("delay := {}", v(register)),
+        Instruction::SetSoundTimer { register } => format!("buzzer := {}", v(register)),
+        Instruction::SetI { .. } => format!("i := {target}"),
+        Instruction::AddToI { register } => format!("i += {}", v(register)),
+        Instruction::SetIToFontSprite { register } => format!("i := hex {}", v(register)),
+        Instruction::StoreBcd { register } => format!("bcd {}", v(register)),
+        Instruction::DumpRegisters { end_register } => format!("save {}", v(end_register)),
+        Instruction::LoadRegisters { end_register } => format!("load {}", v(end_register)),
+        Instruction::Draw {
+            reg_x,
+            reg_y,
+            sprite_height,
+        } => format!("sprite {} {} {sprite_height}", v(reg_x), v(reg_y)),
+        Instruction::NoOp => "0x00 0x00".to_string(),
+    }
+}
+
+fn data_line(bs: &[u8], syntax: Syntax) -> String {
+    let bytes: Vec<String> = bs.iter().map(|b| format!("{b:#04x}")).collect();
+    match syntax {
+        Syntax::Asm => format!("DB {}", bytes.join(", ")),
+        Syntax::Octo => bytes.join(" "),
+    }
+}
+
+// produces source that reassembles to exactly `rom` with asm or octo, depending on syntax
+pub fn decompile(rom: &[u8], syntax: Syntax) -> String {
+    let end = ORIGIN + rom.len();
+    let items = items(rom);
+    let labels = labels(&items, end, syntax);
+    let (comment, indent) = match syntax {
+        Syntax::Asm => (";", "    "),
+        Syntax::Octo => ("#", "  "),
+    };
+
+    let mut out = String::new();
+    let label = |out: &mut String, addr: usize| {
+        if let Some(name) = labels.get(&addr) {
+            match syntax {
+                Syntax::Asm => _ = writeln!(out, "{name}:"),
+                Syntax::Octo => _ = writeln!(out, ": {name}"),
+            }
+        }
+    };
+    for (addr, item) in items.iter() {
+        match item {
+            Item::Code(inst) => {
+                label(&mut out, *addr);
+                let target = inst
+                    .address()
+                    .map(|a| labels.get(&(a as usize)).cloned().unwrap_or(format!("{a:#05x}")))
+                    .unwrap_or_default();
+                let text = match syntax {
+                    Syntax::Asm => asm_statement(inst, &target),
+                    Syntax::Octo => octo_statement(inst, &target),
+                };
+                let bs = inst.encode();
+                _ = writeln!(
+                    out,
+                    "{indent}{text:<28} {comment} {addr:03x}: {:02x}{:02x}",
+                    bs[0], bs[1]
+                );
+            }
+            Item::Data(bs) => {
+                // split data runs so that every label lands at the start of a line
+                let mut start = 0;
+                while start < bs.len() {
+                    let line_addr = addr + start;
+                    label(&mut out, line_addr);
+                    let mut len = 1;
+                    while len < DATA_PER_LINE && start + len < bs.len() && !labels.contains_key(&(line_addr + len)) {
+                        len += 1;
+                    }
+                    let text = data_line(&bs[start..start + len], syntax);
+                    _ = writeln!(out, "{indent}{text:<28} {comment} {line_addr:03x}");
+                    start += len;
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, octo};
+    use std::path::Path;
+
+    static KEYPAD_TEST: &[u8] = include_bytes!("../../rom/keypad_test.ch8");
+
+    #[test]
+    fn test_round_trip() {
+        let source = decompile(KEYPAD_TEST, Syntax::Asm);
+        let program = asm::assemble_source("keypad_test.s", &source, Path::new(".")).unwrap();
+        assert_eq!(program.bytes, KEYPAD_TEST);
+
+        let source = decompile(KEYPAD_TEST, Syntax::Octo);
+        let program = octo::compile_source("keypad_test.8o", &source).unwrap();
+        assert_eq!(program.bytes, KEYPAD_TEST);
+    }
+
+    #[test]
+    fn test_code_and_data() {
+        let rom = [
+            0xa2, 0x0a, // LD I, data
+            0x3a, 0x00, // SE VA, 0
+            0x22, 0x08, // CALL sub
+            0x12, 0x00, // JP start
+            0x00, 0xee, // sub: RET
+            0xff, 0x18, 0x00, // data
+        ];
+        let code = trace(&rom);
+        let starts: Vec<usize> = (0..code.len()).filter(|a| code[*a]).collect();
+        assert_eq!(starts, vec![0x200, 0x202, 0x204, 0x206, 0x208]);
+
+        let source = decompile(&rom, Syntax::Asm);
+        assert!(source.contains("LD I, data_20a"));
+        assert!(source.contains("CALL sub_208"));
+        assert!(source.contains("DB 0xff, 0x18, 0x00"));
+        let program = asm::assemble_source("test.s", &source, Path::new(".")).unwrap();
+        assert_eq!(program.bytes, rom);
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+
+// turned off pixels fade out over this many frames unless given
+static DEFAULT_DECAY: u32 = 4;
+// the most frames in a row the erase-free filter skips
+static HOLD_FRAMES: u32 = 4;
+
+// how frames of the display buffer are turned into what is shown, against the flicker of sprites being erased
+// and drawn again with XOR
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    None,
+    // pixels fade out over a number of frames after turning off, like the phosphor of a CRT
+    Phosphor(u32),
+    // each frame is the average of the buffer now and at the last frame
+    Blend,
+    // a frame with fewer pixels lit than the one on screen is taken to be caught between erasing a sprite and
+    // drawing it again, and skipped for up to HOLD_FRAMES in a row
+    EraseFree,
+}
+
+impl Filter {
+    // "none", "phosphor", "phosphor:8", "blend", "erase-free"
+    pub fn parse(s: &str) -> Option<Filter> {
+        match s.split_once(':') {
+            Some(("phosphor", frames)) => match frames.parse() {
+                Ok(frames @ 1..) => Some(Filter::Phosphor(frames)),
+                _ => None,
+            },
+            Some(_) => None,
+            None => match s {
+                "none" => Some(Filter::None),
+                "phosphor" => Some(Filter::Phosphor(DEFAULT_DECAY)),
+                "blend" => Some(Filter::Blend),
+                "erase-free" => Some(Filter::EraseFree),
+                _ => None,
+            },
+        }
+    }
+
+    // for cycling through the filters with a key
+    pub fn next(self) -> Filter {
+        match self {
+            Filter::None => Filter::Phosphor(DEFAULT_DECAY),
+            Filter::Phosphor(_) => Filter::Blend,
+            Filter::Blend => Filter::EraseFree,
+            Filter::EraseFree => Filter::None,
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::None => write!(f, "none"),
+            Filter::Phosphor(frames) => write!(f, "phosphor:{frames}"),
+            Filter::Blend => write!(f, "blend"),
+            Filter::EraseFree => write!(f, "erase-free"),
+        }
+    }
+}
+
+// what the filters remember of earlier frames
+pub struct Frames {
+    levels: Vec<f32>,
+    last: Vec<u8>,
+    shown: Vec<u8>,
+    held: u32,
+}
+
+impl Default for Frames {
+    fn default() -> Self {
+        Frames::new(2048)
+    }
+}
+
+impl Frames {
+    pub fn new(pixels: usize) -> Self {
+        Frames {
+            levels: vec![0.0; pixels],
+            last: vec![0; pixels],
+            shown: vec![0; pixels],
+            held: 0,
+        }
+    }
+
+    // the brightness of each pixel for the next frame of `buffer`, from 0 for off to 1 for on
+    pub fn apply(&mut self, filter: Filter, buffer: &[u8]) -> &[f32] {
+        match filter {
+            Filter::None => {
+                for (level, p) in self.levels.iter_mut().zip(buffer) {
+                    *level = *p as f32;
+                }
+            }
+            Filter::Phosphor(frames) => {
+                for (level, p) in self.levels.iter_mut().zip(buffer) {
+                    *level = match p {
+                        1 => 1.0,
+                        _ => (*level - 1.0 / frames.max(1) as f32).max(0.0),
+                    };
+                }
+            }
+            Filter::Blend => {
+                for ((level, p), last) in self.levels.iter_mut().zip(buffer).zip(&self.last) {
+                    *level = (*p + *last) as f32 / 2.0;
+                }
+            }
+            Filter::EraseFree => {
+                let lit = |frame: &[u8]| frame.iter().filter(|p| **p == 1).count();
+                if lit(buffer) >= lit(&self.shown) || self.held >= HOLD_FRAMES {
+                    self.shown.copy_from_slice(buffer);
+                    self.held = 0;
+                } else {
+                    self.held += 1;
+                }
+                for (level, p) in self.levels.iter_mut().zip(&self.shown) {
+                    *level = *p as f32;
+                }
+            }
+        }
+        if filter != Filter::EraseFree {
+            self.shown.copy_from_slice(buffer);
+            self.held = 0;
+        }
+        self.last.copy_from_slice(buffer);
+        &self.levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(Filter::parse("phosphor:8"), Some(Filter::Phosphor(8)));
+        assert_eq!(Filter::parse("phosphor"), Some(Filter::Phosphor(4)));
+        assert_eq!(Filter::parse("phosphor:0"), None);
+        assert_eq!(Filter::parse("erase-free"), Some(Filter::EraseFree));
+        assert_eq!(Filter::Phosphor(2).next().next().next(), Filter::None);
+        assert_eq!(Filter::Phosphor(2).to_string(), "phosphor:2");
+
+        // a sprite of one pixel moving right, erased from 0 before it's drawn at 1
+        let (mut on, erased, mut moved) = ([0; 2048], [0; 2048], [0; 2048]);
+        on[0] = 1;
+        moved[1] = 1;
+
+        let mut frames = Frames::default();
+        frames.apply(Filter::Phosphor(2), &on);
+        assert_eq!(frames.apply(Filter::Phosphor(2), &erased)[0], 0.5);
+        assert_eq!(frames.apply(Filter::Phosphor(2), &moved)[..2], [0.0, 1.0]);
+
+        let mut frames = Frames::default();
+        frames.apply(Filter::Blend, &on);
+        assert_eq!(frames.apply(Filter::Blend, &moved)[..2], [0.5, 0.5]);
+
+        let mut frames = Frames::default();
+        frames.apply(Filter::EraseFree, &on);
+        assert_eq!(frames.apply(Filter::EraseFree, &erased)[..2], [1.0, 0.0]);
+        assert_eq!(frames.apply(Filter::EraseFree, &moved)[..2], [0.0, 1.0]);
+        // a screen cleared for good shows up after a few frames
+        for _ in 0..HOLD_FRAMES {
+            assert_eq!(frames.apply(Filter::EraseFree, &erased)[1], 1.0);
+        }
+        assert_eq!(frames.apply(Filter::EraseFree, &erased)[1], 0.0);
+    }
+}
@@ -0,0 +1,72 @@
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+use std::sync::{Arc, Mutex};
+
+// each frame without accesses an address keeps this much of its glow
+static FADE: f32 = 0.9;
+// addresses accessed at some point stay this bright
+static SEEN: u8 = 48;
+
+// the 4 KiB address space as 64 rows of 64 bytes: red for writes, green for reads and blue for execution,
+// bright where accesses are happening and dim where they happened before
+pub struct Heatmap {
+    canvas: Canvas<Window>,
+    // a pixel per address, scaled up to the window
+    texture: Texture<'static>,
+    counts: Arc<Mutex<Vec<[u32; 3]>>>,
+    last: Vec<[u32; 3]>,
+    glow: Vec<[f32; 3]>,
+}
+
+impl Heatmap {
+    pub fn new(video: &VideoSubsystem, cell_size: u32, counts: Arc<Mutex<Vec<[u32; 3]>>>) -> Self {
+        let window = video
+            .window("CHIP-8 memory", 64 * cell_size, 64 * cell_size)
+            .build()
+            .unwrap();
+        let len = counts.lock().unwrap().len();
+        let canvas = window.into_canvas().build().unwrap();
+        // leaked like the display's, for the texture to borrow
+        let creator = Box::leak(Box::new(canvas.texture_creator()));
+        let texture = creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, 64, (len / 64) as u32)
+            .unwrap();
+        Heatmap {
+            canvas,
+            texture,
+            counts,
+            last: vec![[0; 3]; len],
+            glow: vec![[0.0; 3]; len],
+        }
+    }
+
+    pub fn update(&mut self) {
+        // copied out first so the program isn't held up while the frame is drawn
+        let counts = self.counts.lock().unwrap().clone();
+        let mut pixels = Vec::with_capacity(counts.len() * 3);
+        for (addr, count) in counts.iter().enumerate() {
+            let mut rgb = [0; 3];
+            for kind in 0..3 {
+                let glow = &mut self.glow[addr][kind];
+                // counts start over when the program is reloaded
+                *glow = if count[kind] != self.last[addr][kind] {
+                    1.0
+                } else {
+                    *glow * FADE
+                };
+                let seen = if count[kind] > 0 { SEEN } else { 0 };
+                rgb[kind] = seen.max((*glow * 255.0) as u8);
+            }
+            self.last[addr] = *count;
+            // rgb is indexed by AccessKind: read, write, execute
+            pixels.extend([rgb[1], rgb[0], rgb[2]]);
+        }
+        self.texture.update(None, &pixels, 64 * 3).unwrap();
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
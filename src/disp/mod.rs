@@ -1,27 +1,84 @@
 extern crate sdl2;
 
+use crate::proc::Provenance;
+use crate::rec::Recorder;
+use crate::shot::{Format, Screenshot};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::{Point, Rect};
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 use sdl2::EventPump;
+use std::fs::File;
+use std::io;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+
+pub mod filter;
+pub mod heat;
+pub mod overlay;
+pub mod scale;
+pub mod sprites;
+pub mod theme;
+
+// the size of the framebuffer, the processor only has the 64x32 display
+static RESOLUTION: (u32, u32) = (64, 32);
+
+// position on the hex keypad for keys of the usual 1234/QWER/ASDF/ZXCV layout
+pub fn keypad_index(key: Keycode) -> Option<usize> {
+    let layout = [
+        Keycode::X,
+        Keycode::Num1,
+        Keycode::Num2,
+        Keycode::Num3,
+        Keycode::Q,
+        Keycode::W,
+        Keycode::E,
+        Keycode::A,
+        Keycode::S,
+        Keycode::D,
+        Keycode::Z,
+        Keycode::C,
+        Keycode::Num4,
+        Keycode::R,
+        Keycode::F,
+        Keycode::V,
+    ];
+    layout.iter().position(|k| *k == key)
+}
+
+// `level` of the way from one color to the other
+fn shade(from: Color, to: Color, level: f32) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * level) as u8;
+    Color::RGB(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
 pub struct Display {
-    pixel_size: u32,
     sdl_context: sdl2::Sdl,
     // window: Window,
     canvas: Canvas<Window>,
+    // the framebuffer, drawn scaled up to the window
+    texture: Texture<'static>,
+    // the overlay panel, drawn at a pixel per glyph pixel and scaled up like the framebuffer
+    panel: Texture<'static>,
+    scaling: scale::Scaling,
+    fullscreen: bool,
     pub event_pump: EventPump,
     screen_buffer: Arc<Mutex<[u8; 2048]>>,
     paused: bool,
     draw_grid: bool,
-    pixel_color: Color,
-    grid_color: Color,
-    pause_color: Color,
-    background_color: Color,
+    theme: theme::Theme,
+    // the processor state for the side panel, shown while show_overlay is set
+    overlay: Option<Arc<Mutex<overlay::Status>>>,
+    show_overlay: bool,
+    rate: overlay::Rate,
+    // where pixels were drawn from, pixels the last draw turned off are shown in the collision color
+    provenance: Option<Arc<Mutex<Provenance>>>,
+    filter: filter::Filter,
+    frames: filter::Frames,
+    recorder: Option<Recorder>,
 }
 
 impl Display {
@@ -30,32 +87,187 @@ impl Display {
         let video_subsystem = sdl_context.video().unwrap();
 
         let window = video_subsystem
-            .window("CHIP-8", 64 * pixel_size, 32 * pixel_size)
+            .window("CHIP-8", RESOLUTION.0 * pixel_size, RESOLUTION.1 * pixel_size)
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
+        // a GPU renderer where there is one, SDL falls back to its software renderer otherwise. both scale textures.
         let canvas = window.into_canvas().build().unwrap();
+        // leaked as textures borrow it for as long as the display is open
+        let creator = Box::leak(Box::new(canvas.texture_creator()));
+        let texture = creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, RESOLUTION.0, RESOLUTION.1)
+            .unwrap();
+        let panel = creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, overlay::WIDTH, overlay::HEIGHT)
+            .unwrap();
         let event_pump = sdl_context.event_pump().unwrap();
         Self {
-            pixel_size: pixel_size,
             screen_buffer: buffer,
             sdl_context: sdl_context,
             // window: window,
             canvas: canvas,
             event_pump: event_pump, // canvas: canvas,
+            texture,
+            panel,
+            scaling: scale::Scaling::Integer,
+            fullscreen: false,
             draw_grid: false,
             paused: false,
-            background_color: Color::BLACK,
-            pixel_color: Color::WHITE,
-            grid_color: Color::BLUE,
-            pause_color: Color::MAGENTA,
+            theme: theme::builtin().remove(0),
+            overlay: None,
+            show_overlay: false,
+            rate: overlay::Rate::default(),
+            provenance: None,
+            filter: filter::Filter::None,
+            frames: filter::Frames::default(),
+            recorder: None,
+        }
+    }
+
+    // for opening more windows next to the display
+    pub fn video(&self) -> sdl2::VideoSubsystem {
+        self.sdl_context.video().unwrap()
+    }
+
+    pub fn set_overlay(&mut self, status: Arc<Mutex<overlay::Status>>) {
+        self.overlay = Some(status);
+    }
+
+    pub fn set_provenance(&mut self, provenance: Arc<Mutex<Provenance>>) {
+        self.provenance = Some(provenance);
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // shows the instruction and sprite that drew the pixel under the mouse in the title
+    pub fn hover(&mut self, x: i32, y: i32) {
+        let Some(provenance) = &self.provenance else {
+            return;
+        };
+        let viewport = self.viewport();
+        // over the bars around the display or the overlay panel
+        if !viewport.contains_point((x, y)) {
+            self.canvas.window_mut().set_title("CHIP-8").unwrap();
+            return;
+        }
+        let x = (x - viewport.x()) as u32 * RESOLUTION.0 / viewport.width();
+        let y = (y - viewport.y()) as u32 * RESOLUTION.1 / viewport.height();
+        let title = match provenance.lock().unwrap().pixels.get((x + y * RESOLUTION.0) as usize) {
+            Some(Some(origin)) => format!(
+                "CHIP-8: {x},{y} drawn by {:#05x} from {:#05x}, sprite at {:#05x}",
+                origin.pc, origin.row, origin.i
+            ),
+            _ => format!("CHIP-8: {x},{y}"),
+        };
+        self.canvas.window_mut().set_title(&title).unwrap();
+    }
+
+    // glyph pixels are scaled so the panel fits the height of the window
+    fn overlay_scale(&self) -> u32 {
+        let (_, height) = self.canvas.output_size().unwrap();
+        (height / overlay::HEIGHT).max(1)
+    }
+
+    fn panel_width(&self) -> u32 {
+        match (&self.overlay, self.show_overlay) {
+            (Some(_), true) => overlay::WIDTH * self.overlay_scale(),
+            _ => 0,
+        }
+    }
+
+    // where the display is drawn, left of the panel
+    fn viewport(&self) -> Rect {
+        let (width, height) = self.canvas.output_size().unwrap();
+        let area = (width.saturating_sub(self.panel_width()), height);
+        scale::viewport(area, RESOLUTION, self.scaling)
+    }
+
+    // widens the window for the panel, or back. in fullscreen the panel takes room from the display instead.
+    pub fn set_show_overlay(&mut self, v: bool) {
+        if self.overlay.is_none() || v == self.show_overlay {
+            return;
+        }
+        let panel = overlay::WIDTH * self.overlay_scale();
+        self.show_overlay = v;
+        if self.fullscreen {
+            return;
+        }
+        let (width, height) = self.canvas.window().size();
+        let width = if v { width + panel } else { width.saturating_sub(panel) };
+        self.canvas.window_mut().set_size(width, height).unwrap();
+    }
+
+    pub fn set_scaling(&mut self, scaling: scale::Scaling) {
+        self.scaling = scaling;
+    }
+
+    pub fn set_fullscreen(&mut self, v: bool) {
+        if v == self.fullscreen {
+            return;
         }
+        self.fullscreen = v;
+        let mode = if v {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Off
+        };
+        self.canvas.window_mut().set_fullscreen(mode).unwrap();
     }
 
     pub fn set_pause(&mut self, v: bool) {
         self.paused = v;
     }
 
+    // saves the display in its theme and at the scale it is shown, in the format of the file extension
+    pub fn screenshot(&self, path: &Path) -> io::Result<()> {
+        let buffer = *self.screen_buffer.lock().unwrap();
+        let shot = Screenshot {
+            pixels: &buffer,
+            width: RESOLUTION.0 as usize,
+            height: RESOLUTION.1 as usize,
+            theme: &self.theme,
+        };
+        let format = Format::from_path(path).unwrap_or(Format::Png);
+        let scale = (self.viewport().width() / RESOLUTION.0).max(1);
+        shot.write(io::BufWriter::new(File::create(path)?), format, scale as usize)
+    }
+
+    // records each frame from now on, in the theme and at the scale the display is shown
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        let scale = (self.viewport().width() / RESOLUTION.0).max(1);
+        let (width, height) = (RESOLUTION.0 as usize, RESOLUTION.1 as usize);
+        self.recorder = Some(Recorder::create(path, width, height, scale as usize, &self.theme)?);
+        Ok(())
+    }
+
+    // the number of frames recorded, if recording
+    pub fn stop_recording(&mut self) -> io::Result<Option<usize>> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(None);
+        };
+        let frames = recorder.frames();
+        recorder.finish()?;
+        Ok(Some(frames))
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub fn set_theme(&mut self, theme: &theme::Theme) {
+        if self.theme != *theme {
+            self.theme = theme.clone();
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: filter::Filter) {
+        self.filter = filter;
+    }
+
     pub fn set_grid(&mut self, v: bool) {
         self.draw_grid = v;
     }
@@ -64,66 +276,84 @@ impl Display {
     }
 
     pub fn update(&mut self) {
-        self.canvas.set_draw_color(self.background_color);
+        // copied out first so the program isn't held up while the frame is drawn
+        let buffer = *self.screen_buffer.lock().unwrap();
+        let collisions = match &self.provenance {
+            Some(provenance) => provenance.lock().unwrap().collisions.clone(),
+            None => Vec::new(),
+        };
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.frame(&buffer) {
+                eprintln!("recording stopped: {e}");
+                if let Some(recorder) = self.recorder.take() {
+                    let _ = recorder.finish();
+                }
+            }
+        }
+
+        let levels = self.frames.apply(self.filter, &buffer[..]);
+        let mut pixels = Vec::with_capacity(levels.len() * 3);
+        for (level, value) in levels.iter().zip(buffer) {
+            // the filters work on a single plane, pixels of other planes are shown in their palette colors
+            let color = match value {
+                0 | 1 => shade(self.theme.background(), self.theme.pixel(), *level),
+                _ => self.theme.color(value),
+            };
+            pixels.extend([color.r, color.g, color.b]);
+        }
+        for p in collisions {
+            let color = self.theme.collision;
+            pixels[p * 3..p * 3 + 3].copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        self.texture.update(None, &pixels, RESOLUTION.0 as usize * 3).unwrap();
+
+        self.canvas.set_draw_color(self.theme.background());
         self.canvas.clear();
+        let viewport = self.viewport();
+        self.canvas.copy(&self.texture, None, viewport).unwrap();
 
-        self.canvas.set_draw_color(self.pause_color);
-        if self.paused {
-            self.canvas
-                .fill_rect(Rect::new(
-                    self.pixel_size as i32 * 60,
-                    self.pixel_size as i32 * 1,
-                    self.pixel_size,
-                    self.pixel_size * 4,
-                ))
-                .unwrap();
-            self.canvas
-                .fill_rect(Rect::new(
-                    self.pixel_size as i32 * 62,
-                    self.pixel_size as i32 * 1,
-                    self.pixel_size,
-                    self.pixel_size * 4,
-                ))
-                .unwrap();
+        let (columns, rows) = (RESOLUTION.0 as i32, RESOLUTION.1 as i32);
+        let (left, top) = (viewport.x(), viewport.y());
+        let (width, height) = (viewport.width() as i32, viewport.height() as i32);
+        if self.draw_grid {
+            self.canvas.set_draw_color(self.theme.grid);
+            for x in 0..columns {
+                let x = left + width * x / columns;
+                self.canvas
+                    .draw_line(Point::new(x, top), Point::new(x, top + height))
+                    .unwrap();
+            }
+            for y in 0..rows {
+                let y = top + height * y / rows;
+                self.canvas
+                    .draw_line(Point::new(left, y), Point::new(left + width, y))
+                    .unwrap();
+            }
         }
-        self.canvas.set_draw_color(self.grid_color);
-
-        {
-            let screen_buffer = self.screen_buffer.lock().unwrap();
-            for x in 0..64 {
-                if self.draw_grid {
-                    self.canvas
-                        .draw_line(
-                            Point::new(self.pixel_size as i32 * x, 0),
-                            Point::new(self.pixel_size as i32 * x, self.pixel_size as i32 * 32),
-                        )
-                        .unwrap();
-                }
 
-                for y in 0..32 {
-                    if self.draw_grid {
-                        self.canvas
-                            .draw_line(
-                                Point::new(0, self.pixel_size as i32 * y),
-                                Point::new(self.pixel_size as i32 * 64, self.pixel_size as i32 * y),
-                            )
-                            .unwrap();
-                    }
-
-                    if screen_buffer[(x + y * 64) as usize] == 1 {
-                        self.canvas.set_draw_color(self.pixel_color);
-
-                        self.canvas
-                            .fill_rect(Rect::new(
-                                (x as i32) * (self.pixel_size as i32),
-                                (y as i32) * (self.pixel_size as i32),
-                                self.pixel_size,
-                                self.pixel_size,
-                            ))
-                            .unwrap();
-                        self.canvas.set_draw_color(self.grid_color);
-                    }
-                }
+        if self.paused {
+            self.canvas.set_draw_color(self.theme.pause);
+            let size = (width / columns).max(1);
+            for x in [columns - 4, columns - 2] {
+                self.canvas
+                    .fill_rect(Rect::new(left + size * x, top + size, size as u32, size as u32 * 4))
+                    .unwrap();
+            }
+        }
+        if let Some(status) = &self.overlay {
+            let status = status.lock().unwrap().clone();
+            self.rate.update(status.cycles);
+            if self.show_overlay {
+                let lines = status.lines(self.rate.rate);
+                let (window_width, _) = self.canvas.output_size().unwrap();
+                let (x, scale) = (
+                    window_width.saturating_sub(self.panel_width()) as i32,
+                    self.overlay_scale(),
+                );
+                let pixels = overlay::render(&lines, self.theme.pixel(), self.theme.background());
+                self.panel.update(None, &pixels, overlay::WIDTH as usize * 3).unwrap();
+                let area = Rect::new(x, 0, overlay::WIDTH * scale, overlay::HEIGHT * scale);
+                self.canvas.copy(&self.panel, None, area).unwrap();
             }
         }
         self.canvas.present();
@@ -0,0 +1,200 @@
+use crate::inst::Instruction;
+use crate::mem::FONT;
+use crate::proc::Processor;
+use sdl2::pixels::Color;
+use std::time::{Duration, Instant};
+
+// glyphs are 4x5 like the hex digits programs draw with, which are used for 0-F, in cells of 5x7
+pub static CELL_WIDTH: u32 = 5;
+pub static CELL_HEIGHT: u32 = 7;
+pub static COLUMNS: u32 = 26;
+pub static ROWS: u32 = 23;
+// the size of the panel in glyph pixels, with a pixel of margin
+pub static WIDTH: u32 = COLUMNS * CELL_WIDTH + 1;
+pub static HEIGHT: u32 = ROWS * CELL_HEIGHT + 1;
+// instructions shown before and after pc
+static CODE_BEFORE: usize = 3;
+static CODE_AFTER: usize = 8;
+static RATE_PERIOD: Duration = Duration::from_millis(500);
+
+#[rustfmt::skip]
+static GLYPHS: &[(char, [u8; 5])] = &[
+    ('G', [0xF0, 0x80, 0xB0, 0x90, 0xF0]), ('H', [0x90, 0x90, 0xF0, 0x90, 0x90]),
+    ('I', [0x70, 0x20, 0x20, 0x20, 0x70]), ('J', [0x10, 0x10, 0x10, 0x90, 0xF0]),
+    ('K', [0x90, 0xA0, 0xC0, 0xA0, 0x90]), ('L', [0x80, 0x80, 0x80, 0x80, 0xF0]),
+    ('M', [0x90, 0xF0, 0xF0, 0x90, 0x90]), ('N', [0x90, 0xD0, 0xB0, 0x90, 0x90]),
+    ('O', [0x60, 0x90, 0x90, 0x90, 0x60]), ('P', [0xF0, 0x90, 0xF0, 0x80, 0x80]),
+    ('Q', [0x60, 0x90, 0x90, 0xB0, 0x70]), ('R', [0xE0, 0x90, 0xE0, 0xA0, 0x90]),
+    ('S', [0x70, 0x80, 0x60, 0x10, 0xE0]), ('T', [0x70, 0x20, 0x20, 0x20, 0x20]),
+    ('U', [0x90, 0x90, 0x90, 0x90, 0xF0]), ('V', [0x90, 0x90, 0x90, 0x60, 0x60]),
+    ('W', [0x90, 0x90, 0xF0, 0xF0, 0x90]), ('X', [0x90, 0x90, 0x60, 0x90, 0x90]),
+    ('Y', [0xA0, 0xA0, 0x40, 0x40, 0x40]), ('Z', [0xF0, 0x10, 0x60, 0x80, 0xF0]),
+    ('x', [0x00, 0x90, 0x60, 0x60, 0x90]), (' ', [0x00, 0x00, 0x00, 0x00, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x20, 0x40]), (':', [0x00, 0x20, 0x00, 0x20, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x20]), ('[', [0x60, 0x40, 0x40, 0x40, 0x60]),
+    (']', [0x60, 0x20, 0x20, 0x20, 0x60]), ('+', [0x00, 0x20, 0x70, 0x20, 0x00]),
+    ('-', [0x00, 0x00, 0x70, 0x00, 0x00]), ('>', [0x40, 0x20, 0x10, 0x20, 0x40]),
+    ('<', [0x10, 0x20, 0x40, 0x20, 0x10]), ('#', [0x50, 0xF0, 0x50, 0xF0, 0x50]),
+    ('*', [0x00, 0x50, 0x20, 0x50, 0x00]), ('/', [0x10, 0x10, 0x20, 0x40, 0x40]),
+    ('=', [0x00, 0x70, 0x00, 0x70, 0x00]), ('_', [0x00, 0x00, 0x00, 0x00, 0xF0]),
+    ('?', [0xF0, 0x10, 0x60, 0x00, 0x40]),
+];
+
+// other letters are drawn in upper case, anything else as ?
+pub fn glyph(c: char) -> [u8; 5] {
+    let c = if c == 'x' { c } else { c.to_ascii_uppercase() };
+    if let Some(digit) = c.to_digit(16) {
+        return FONT[digit as usize];
+    }
+    GLYPHS
+        .iter()
+        .find(|(g, _)| *g == c)
+        .or(GLYPHS.iter().find(|(g, _)| *g == '?'))
+        .map(|(_, bits)| *bits)
+        .unwrap()
+}
+
+// the processor state the panel shows, copied out of the thread running the program
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: usize,
+    pub dt: u8,
+    pub st: u8,
+    pub stack: Vec<u16>,
+    pub code: Vec<(u16, Option<Instruction>)>,
+    // instructions executed so far, for the rate
+    pub cycles: u64,
+}
+
+impl Status {
+    pub fn capture(proc: &Processor, cycles: u64) -> Self {
+        let first = proc.pc.saturating_sub(2 * CODE_BEFORE);
+        let code = (first..=proc.pc + 2 * CODE_AFTER)
+            .step_by(2)
+            .filter_map(|addr| {
+                let bs = proc.memory.get_word(addr).ok()?;
+                Some((addr as u16, Instruction::parse(bs).ok()))
+            })
+            .collect();
+        Status {
+            v: proc.registers.as_array(),
+            i: proc.i,
+            pc: proc.pc as u16,
+            sp: proc.sp,
+            dt: proc.delay_timer,
+            st: proc.sound_timer,
+            stack: proc.stack().to_vec(),
+            code,
+            cycles,
+        }
+    }
+
+    pub fn lines(&self, rate: Option<u64>) -> Vec<String> {
+        let mut lines = vec![
+            format!("PC {:03X}  I {:03X}  SP {}", self.pc, self.i, self.sp),
+            format!("DT {:02X}  ST {:02X}", self.dt, self.st),
+        ];
+        for (n, v) in self.v.chunks(4).enumerate() {
+            let regs: Vec<String> = v
+                .iter()
+                .enumerate()
+                .map(|(m, v)| format!("V{:X} {v:02X}", n * 4 + m))
+                .collect();
+            lines.push(regs.join(" "));
+        }
+        let stack: Vec<String> = self.stack.iter().rev().map(|a| format!("{a:03X}")).collect();
+        lines.push(format!("STACK {}", stack.join(" ")));
+        lines.push(String::new());
+        for (addr, inst) in self.code.iter() {
+            let marker = if *addr == self.pc { '>' } else { ' ' };
+            let inst = inst.as_ref().map(|i| i.to_string()).unwrap_or("?".to_string());
+            lines.push(format!("{marker}{addr:03X} {inst}"));
+        }
+        lines.push(String::new());
+        match rate {
+            Some(rate) => lines.push(format!("IPS {rate}")),
+            None => lines.push("IPS -".to_string()),
+        }
+        lines
+    }
+}
+
+// instructions per second, from how far the cycle count moved over the last period
+#[derive(Default)]
+pub struct Rate {
+    last: Option<(Instant, u64)>,
+    pub rate: Option<u64>,
+}
+
+impl Rate {
+    pub fn update(&mut self, cycles: u64) {
+        match self.last {
+            Some((at, from)) if at.elapsed() >= RATE_PERIOD => {
+                self.rate = Some((cycles.saturating_sub(from) as f64 / at.elapsed().as_secs_f64()) as u64);
+                self.last = Some((Instant::now(), cycles));
+            }
+            Some(_) => {}
+            None => self.last = Some((Instant::now(), cycles)),
+        }
+    }
+}
+
+// the panel as WIDTH x HEIGHT RGB pixels, to be scaled up
+pub fn render(lines: &[String], foreground: Color, background: Color) -> Vec<u8> {
+    let mut pixels = [background.r, background.g, background.b].repeat((WIDTH * HEIGHT) as usize);
+    for (row, line) in lines.iter().take(ROWS as usize).enumerate() {
+        for (column, c) in line.chars().take(COLUMNS as usize).enumerate() {
+            let (cx, cy) = (column as u32 * CELL_WIDTH + 1, row as u32 * CELL_HEIGHT + 1);
+            for (gy, bits) in glyph(c).iter().enumerate() {
+                for gx in (0..4).filter(|gx| bits & (0x80 >> gx) != 0) {
+                    let p = ((cy + gy as u32) * WIDTH + cx + gx) as usize * 3;
+                    pixels[p..p + 3].copy_from_slice(&[foreground.r, foreground.g, foreground.b]);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Memory;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_status() {
+        assert_eq!(glyph('a'), FONT[0xa]);
+        assert_eq!(glyph('x'), [0x00, 0x90, 0x60, 0x60, 0x90]);
+        assert_eq!(glyph('~'), glyph('?'));
+        let pixels = render(&["1".to_string()], Color::WHITE, Color::BLACK);
+        assert_eq!(pixels.len(), (WIDTH * HEIGHT * 3) as usize);
+        // the top row of 1, 0x20, lights the third pixel of the first cell
+        let lit = |x: u32, y: u32| pixels[((y * WIDTH + x) * 3) as usize] == 0xff;
+        assert!(lit(3, 1) && !lit(2, 1) && !lit(0, 0));
+
+        let mut memory = Memory::new();
+        #[rustfmt::skip]
+        memory.load_array(512, &[
+            0x60, 0x05, // ld v0, 5
+            0x22, 0x06, // call 0x206
+            0x12, 0x04, // jp 0x204
+            0xa3, 0x00, // ld i, 0x300
+        ]).unwrap();
+        let mut proc = Processor::new(memory, Arc::new(Mutex::new([0; 2048])));
+        for _ in 0..3 {
+            proc.execute().unwrap();
+        }
+        let lines = Status::capture(&proc, 3).lines(Some(600));
+        assert_eq!(lines[0], "PC 208  I 300  SP 1");
+        assert_eq!(lines[2], "V0 05 V1 00 V2 00 V3 00");
+        assert_eq!(lines[6], "STACK 204");
+        assert_eq!(lines[8], " 202 CALL 0x206");
+        assert_eq!(lines[11], ">208 SYS 0x000");
+        assert_eq!(lines.last().unwrap(), "IPS 600");
+        assert!(lines.iter().all(|l| l.len() <= COLUMNS as usize));
+    }
+}
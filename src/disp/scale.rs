@@ -0,0 +1,77 @@
+use sdl2::rect::Rect;
+
+// how the display is scaled up to fill the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    // by the largest whole number that fits, so all pixels are the same size
+    Integer,
+    // as large as fits
+    Fit,
+}
+
+impl Scaling {
+    pub fn parse(s: &str) -> Option<Scaling> {
+        match s {
+            "integer" => Some(Scaling::Integer),
+            "fit" => Some(Scaling::Fit),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Scaling {
+        match self {
+            Scaling::Integer => Scaling::Fit,
+            Scaling::Fit => Scaling::Integer,
+        }
+    }
+}
+
+// where the display goes in an area of the window: centered, with bars to either side or above and below to
+// keep its aspect ratio
+pub fn viewport(area: (u32, u32), resolution: (u32, u32), scaling: Scaling) -> Rect {
+    let scale = (area.0 as f64 / resolution.0 as f64).min(area.1 as f64 / resolution.1 as f64);
+    let scale = match scaling {
+        Scaling::Integer => scale.floor().max(1.0),
+        Scaling::Fit => scale,
+    };
+    let (width, height) = (
+        (resolution.0 as f64 * scale) as u32,
+        (resolution.1 as f64 * scale) as u32,
+    );
+    Rect::new(
+        (area.0 as i32 - width as i32) / 2,
+        (area.1 as i32 - height as i32) / 2,
+        width.max(1),
+        height.max(1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewport() {
+        assert_eq!(Scaling::parse("fit"), Some(Scaling::Fit));
+
+        assert_eq!(
+            viewport((640, 320), (64, 32), Scaling::Integer),
+            Rect::new(0, 0, 640, 320)
+        );
+        // letterboxed above and below
+        assert_eq!(
+            viewport((700, 500), (64, 32), Scaling::Integer),
+            Rect::new(30, 90, 640, 320)
+        );
+        assert_eq!(viewport((700, 500), (64, 32), Scaling::Fit), Rect::new(0, 75, 700, 350));
+        // pillarboxed
+        assert_eq!(
+            viewport((1000, 300), (128, 64), Scaling::Fit),
+            Rect::new(200, 0, 600, 300)
+        );
+        assert_eq!(
+            viewport((100, 40), (128, 64), Scaling::Integer),
+            Rect::new(-14, -12, 128, 64)
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::sprite::{Layout, SharedMemory, Sheet};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+use std::ops::Range;
+
+static TITLE: &str = "CHIP-8 sprites";
+
+// a range of memory as a grid of sprites, following it as the program runs. clicking a sprite shows its
+// address in the title.
+pub struct SpriteViewer {
+    scale: u32,
+    canvas: Canvas<Window>,
+    // a pixel per sheet pixel, scaled up to the window
+    texture: Texture<'static>,
+    memory: SharedMemory,
+    range: Range<usize>,
+    layout: Layout,
+    columns: usize,
+}
+
+impl SpriteViewer {
+    pub fn new(
+        video: &VideoSubsystem,
+        scale: u32,
+        memory: SharedMemory,
+        range: Range<usize>,
+        layout: Layout,
+        columns: usize,
+    ) -> Self {
+        // the sheet keeps its size as the memory under it changes
+        let (width, height) = {
+            let memory = memory.lock().unwrap();
+            Sheet::new(&memory.0, range.clone(), layout, columns, None).size()
+        };
+        let (width, height) = (width as u32, height as u32);
+        let canvas = video
+            .window(TITLE, width * scale, height * scale)
+            .build()
+            .unwrap()
+            .into_canvas()
+            .build()
+            .unwrap();
+        // leaked like the display's, for the texture to borrow
+        let creator = Box::leak(Box::new(canvas.texture_creator()));
+        let texture = creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+            .unwrap();
+        SpriteViewer {
+            scale,
+            canvas,
+            texture,
+            memory,
+            range,
+            layout,
+            columns,
+        }
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn sheet(&self) -> Sheet {
+        let memory = self.memory.lock().unwrap();
+        Sheet::new(&memory.0, self.range.clone(), self.layout, self.columns, Some(memory.1))
+    }
+
+    pub fn update(&mut self) {
+        let sheet = self.sheet();
+        let pixels: Vec<u8> = sheet.rgb().into_iter().flatten().collect();
+        self.texture.update(None, &pixels, sheet.size().0 * 3).unwrap();
+        self.canvas.copy(&self.texture, None, None).unwrap();
+        self.canvas.present();
+    }
+
+    // x and y are in window coordinates
+    pub fn click(&mut self, x: i32, y: i32) {
+        let (x, y) = (
+            x.max(0) as usize / self.scale as usize,
+            y.max(0) as usize / self.scale as usize,
+        );
+        let title = match self.sheet().address_at(x, y) {
+            Some((sprite, byte)) => format!("{TITLE}: {sprite:#05x}, byte {byte:#05x}"),
+            None => TITLE.to_string(),
+        };
+        self.canvas.window_mut().set_title(&title).unwrap();
+    }
+}
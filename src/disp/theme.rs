@@ -0,0 +1,194 @@
+use sdl2::pixels::Color;
+
+// colors of pixels lit in more than one plane, after the four a theme picks
+static EXTENDED: [(u8, u8, u8); 12] = [
+    (0xcc, 0x33, 0x33),
+    (0x33, 0xcc, 0x33),
+    (0x33, 0x33, 0xcc),
+    (0xcc, 0xcc, 0x33),
+    (0xcc, 0x33, 0xcc),
+    (0x33, 0xcc, 0xcc),
+    (0x99, 0x66, 0x33),
+    (0x66, 0x99, 0x33),
+    (0x33, 0x66, 0x99),
+    (0x99, 0x33, 0x66),
+    (0x66, 0x66, 0x66),
+    (0xcc, 0xcc, 0xcc),
+];
+
+// the colors the display is drawn in. pixels are looked up in the palette by their value, the bits of the planes
+// they are lit in, so 0 is the background and 1 a pixel of the first plane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub palette: Vec<Color>,
+    pub grid: Color,
+    pub pause: Color,
+    pub collision: Color,
+}
+
+fn rgb(rgb: u32) -> Color {
+    Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+
+// "#rrggbb"
+fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(rgb)
+}
+
+impl Theme {
+    // four colors for two planes and the rest for more
+    fn new(name: &str, colors: [u32; 4], grid: u32, pause: u32, collision: u32) -> Self {
+        let mut palette: Vec<Color> = colors.iter().map(|c| rgb(*c)).collect();
+        palette.extend(EXTENDED.iter().map(|(r, g, b)| Color::RGB(*r, *g, *b)));
+        Theme {
+            name: name.to_string(),
+            palette,
+            grid: rgb(grid),
+            pause: rgb(pause),
+            collision: rgb(collision),
+        }
+    }
+
+    pub fn background(&self) -> Color {
+        self.palette[0]
+    }
+
+    pub fn pixel(&self) -> Color {
+        self.palette[1]
+    }
+
+    // the color for a pixel value past the palette is the last one
+    pub fn color(&self, value: u8) -> Color {
+        self.palette[(value as usize).min(self.palette.len() - 1)]
+    }
+
+    // a theme file has a "key = value" per line and comments on lines starting with #. keys missing from it are
+    // those of the classic theme:
+    //   name = mine
+    //   background = #000000
+    //   pixel = #ffffff
+    //   grid, pause, collision = #rrggbb
+    //   palette = #000000 #ffffff #aa0000 #00aa00
+    // a palette of 2, 4 or 16 colors sets the background, the pixels and the colors of further planes
+    pub fn parse(text: &str) -> Result<Theme, String> {
+        let mut theme = builtin().remove(0);
+        theme.name = "custom".to_string();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |what: &str| format!("line {}: {what}", n + 1);
+            let (key, value) = line.split_once('=').ok_or_else(|| err("expected key = value"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let color = || parse_color(value).ok_or_else(|| err(&format!("invalid color '{value}'")));
+            match key {
+                "name" => theme.name = value.to_string(),
+                "background" => theme.palette[0] = color()?,
+                "pixel" => theme.palette[1] = color()?,
+                "grid" => theme.grid = color()?,
+                "pause" => theme.pause = color()?,
+                "collision" => theme.collision = color()?,
+                "palette" => {
+                    let colors = value
+                        .split_whitespace()
+                        .map(|c| parse_color(c).ok_or_else(|| err(&format!("invalid color '{c}'"))))
+                        .collect::<Result<Vec<Color>, String>>()?;
+                    if ![2, 4, 16].contains(&colors.len()) {
+                        return Err(err("a palette has 2, 4 or 16 colors"));
+                    }
+                    theme.palette[..colors.len()].copy_from_slice(&colors);
+                }
+                _ => return Err(err(&format!("unknown key '{key}'"))),
+            }
+        }
+        Ok(theme)
+    }
+}
+
+// in the order a key cycles through them
+pub fn builtin() -> Vec<Theme> {
+    vec![
+        Theme::new(
+            "classic",
+            [0x000000, 0xffffff, 0xaaaaaa, 0x555555],
+            0x0000ff,
+            0xff00ff,
+            0xff0000,
+        ),
+        Theme::new(
+            "green",
+            [0x001400, 0x33ff66, 0x1a9933, 0xb3ffc6],
+            0x0a3a14,
+            0xa0ffa0,
+            0xffff00,
+        ),
+        Theme::new(
+            "amber",
+            [0x140a00, 0xffb000, 0x995c00, 0xffd98a],
+            0x3a2400,
+            0xffe0a0,
+            0xff3000,
+        ),
+        Theme::new(
+            "lcd",
+            [0x9bbc0f, 0x0f380f, 0x306230, 0x8bac0f],
+            0x8bac0f,
+            0x306230,
+            0xd03020,
+        ),
+        Theme::new(
+            "high-contrast",
+            [0x000000, 0xffff00, 0x00ffff, 0xffffff],
+            0xffffff,
+            0x00ffff,
+            0xff00ff,
+        ),
+        // the Okabe-Ito colors, told apart with any kind of color blindness
+        Theme::new(
+            "colorblind",
+            [0x000000, 0x56b4e9, 0xe69f00, 0xf0e442],
+            0x0072b2,
+            0xcc79a7,
+            0xd55e00,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_themes() {
+        let classic = builtin().remove(0);
+        assert_eq!((classic.background(), classic.pixel()), (Color::BLACK, Color::WHITE));
+        assert_eq!(classic.palette.len(), 16);
+        assert_eq!(classic.color(0xff), classic.palette[15]);
+
+        let theme =
+            Theme::parse("name = mine\n# comment\npixel = #00ff00\npalette = #000010 #ffffff #aa0000 #00aa00\n")
+                .unwrap();
+        assert_eq!(theme.name, "mine");
+        // the palette came after the pixel color and replaced it
+        assert_eq!(theme.pixel(), Color::WHITE);
+        assert_eq!(theme.background(), Color::RGB(0, 0, 0x10));
+        assert_eq!(theme.color(3), Color::RGB(0, 0xaa, 0));
+        assert_eq!(theme.grid, Color::BLUE);
+
+        assert_eq!(
+            Theme::parse("palette = #000000 #ffffff #aa0000").unwrap_err(),
+            "line 1: a palette has 2, 4 or 16 colors"
+        );
+        assert_eq!(
+            Theme::parse("pixel = white").unwrap_err(),
+            "line 1: invalid color 'white'"
+        );
+        assert!(Theme::parse("border = #000000").is_err());
+    }
+}
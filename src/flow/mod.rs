@@ -0,0 +1,404 @@
+#![allow(dead_code)]
+use crate::decomp;
+use crate::inst::{Flow, Instruction};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+static ORIGIN: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    FallThrough,
+    Skip,
+    Jump,
+    Call,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Subroutine {
+    pub entry: usize,
+    pub blocks: Vec<usize>,
+}
+
+// a write through I into memory, `target` is None when I can't be determined statically
+#[derive(Debug, Clone)]
+pub struct CodeWrite {
+    pub addr: usize,
+    pub instruction: Instruction,
+    pub target: Option<Range<usize>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub subroutines: Vec<Subroutine>,
+    pub unreachable: Vec<Range<usize>>,
+    pub computed_jumps: Vec<(usize, Instruction)>,
+    pub code_writes: Vec<CodeWrite>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IValue {
+    Unvisited,
+    Known(u16),
+    Unknown,
+}
+
+impl IValue {
+    fn meet(self, other: IValue) -> IValue {
+        match (self, other) {
+            (IValue::Unvisited, v) | (v, IValue::Unvisited) => v,
+            (IValue::Known(a), IValue::Known(b)) if a == b => IValue::Known(a),
+            _ => IValue::Unknown,
+        }
+    }
+}
+
+// value of I after inst, and the memory range written by it
+fn step_i(inst: &Instruction, i: IValue) -> (IValue, Option<Option<Range<usize>>>) {
+    let range = |len: usize| match i {
+        IValue::Known(v) => Some(v as usize..v as usize + len),
+        _ => None,
+    };
+    let advance = |n: u16| match i {
+        IValue::Known(v) => IValue::Known(v.wrapping_add(n)),
+        v => v,
+    };
+    match *inst {
+        Instruction::SetI { addr } => (IValue::Known(addr), None),
+        Instruction::AddToI { .. } | Instruction::SetIToFontSprite { .. } => (IValue::Unknown, None),
+        Instruction::StoreBcd { .. } => (i, Some(range(3))),
+        Instruction::DumpRegisters { end_register } => {
+            (advance(end_register as u16 + 1), Some(range(end_register as usize + 1)))
+        }
+        Instruction::LoadRegisters { end_register } => (advance(end_register as u16 + 1), None),
+        _ => (i, None),
+    }
+}
+
+fn instruction_at(rom: &[u8], addr: usize) -> Instruction {
+    Instruction::parse([rom[addr - ORIGIN], rom[addr + 1 - ORIGIN]]).unwrap()
+}
+
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let end = ORIGIN + rom.len();
+    let code = decomp::trace(rom);
+    let starts: Vec<usize> = (ORIGIN..end).filter(|a| code[*a]).collect();
+
+    // blocks begin at the reset vector, at branch targets and after anything that isn't straight-line code
+    let mut leaders = BTreeSet::from([ORIGIN]);
+    let mut call_targets = BTreeSet::new();
+    for pc in starts.iter() {
+        match instruction_at(rom, *pc).flow() {
+            Flow::Next => {}
+            Flow::Skip => {
+                leaders.extend([pc + 2, pc + 4]);
+            }
+            Flow::Jump(addr) => {
+                leaders.extend([addr as usize, pc + 2]);
+            }
+            Flow::Call(addr) => {
+                leaders.extend([addr as usize, pc + 2]);
+                call_targets.insert(addr as usize);
+            }
+            Flow::Return | Flow::ComputedJump(_) => {
+                leaders.insert(pc + 2);
+            }
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut computed_jumps = Vec::new();
+    for leader in leaders.iter().filter(|l| **l < end && code[**l]) {
+        let mut block = BasicBlock {
+            start: *leader,
+            end: *leader,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+        };
+        let mut pc = *leader;
+        loop {
+            let inst = instruction_at(rom, pc);
+            block.instructions.push((pc, inst.clone()));
+            pc += 2;
+            let edge = |target: usize, kind| Edge { target, kind };
+            match inst.flow() {
+                Flow::Next => {
+                    if pc < end && code[pc] && !leaders.contains(&pc) {
+                        continue;
+                    }
+                    if pc < end && code[pc] {
+                        block.successors.push(edge(pc, EdgeKind::FallThrough));
+                    }
+                }
+                Flow::Skip => {
+                    block.successors.push(edge(pc, EdgeKind::FallThrough));
+                    block.successors.push(edge(pc + 2, EdgeKind::Skip));
+                }
+                Flow::Jump(addr) => block.successors.push(edge(addr as usize, EdgeKind::Jump)),
+                Flow::Call(addr) => {
+                    block.successors.push(edge(addr as usize, EdgeKind::Call));
+                    block.successors.push(edge(pc, EdgeKind::FallThrough));
+                }
+                Flow::ComputedJump(_) => computed_jumps.push((pc - 2, inst)),
+                Flow::Return => {}
+            }
+            break;
+        }
+        block.end = pc;
+        // only keep edges to decodable code, anything else is outside of what trace reached
+        block.successors.retain(|e| e.target < end && code[e.target]);
+        blocks.insert(block.start, block);
+    }
+
+    let subroutines = call_targets
+        .iter()
+        .filter(|t| blocks.contains_key(*t))
+        .map(|entry| {
+            let mut seen = BTreeSet::from([*entry]);
+            let mut pending = vec![*entry];
+            while let Some(b) = pending.pop() {
+                let Some(block) = blocks.get(&b) else {
+                    continue;
+                };
+                for e in block.successors.iter() {
+                    if e.kind != EdgeKind::Call && seen.insert(e.target) {
+                        pending.push(e.target);
+                    }
+                }
+            }
+            Subroutine {
+                entry: *entry,
+                blocks: seen.into_iter().collect(),
+            }
+        })
+        .collect();
+
+    let mut covered = vec![false; end];
+    for pc in starts.iter() {
+        covered[*pc] = true;
+        covered[pc + 1] = true;
+    }
+    let mut unreachable: Vec<Range<usize>> = Vec::new();
+    for addr in (ORIGIN..end).filter(|a| !covered[*a]) {
+        match unreachable.last_mut() {
+            Some(r) if r.end == addr => r.end += 1,
+            _ => unreachable.push(addr..addr + 1),
+        }
+    }
+
+    let code_writes = code_writes(&blocks, &covered);
+    Analysis {
+        blocks,
+        subroutines,
+        unreachable,
+        computed_jumps,
+        code_writes,
+    }
+}
+
+// propagates the value of I through the graph, then looks for writes that may hit code
+fn code_writes(blocks: &BTreeMap<usize, BasicBlock>, code: &[bool]) -> Vec<CodeWrite> {
+    let mut i_in: BTreeMap<usize, IValue> = blocks.keys().map(|b| (*b, IValue::Unvisited)).collect();
+    // an empty rom, or one that doesn't start with an instruction, has no code to follow
+    let mut pending: Vec<usize> = Vec::new();
+    if blocks.contains_key(&ORIGIN) {
+        i_in.insert(ORIGIN, IValue::Unknown);
+        pending.push(ORIGIN);
+    }
+    while let Some(b) = pending.pop() {
+        let Some(block) = blocks.get(&b) else {
+            continue;
+        };
+        let mut i = i_in[&b];
+        for (_, inst) in block.instructions.iter() {
+            i = step_i(inst, i).0;
+        }
+        for e in block.successors.iter() {
+            // the callee may change I before returning
+            let returned_from_call = matches!(block.instructions.last(), Some((_, inst)) if matches!(inst.flow(), Flow::Call(_)))
+                && e.kind == EdgeKind::FallThrough;
+            let out = if returned_from_call { IValue::Unknown } else { i };
+            let Some(old) = i_in.get(&e.target).copied() else {
+                continue;
+            };
+            let new = old.meet(out);
+            if new != old {
+                i_in.insert(e.target, new);
+                pending.push(e.target);
+            }
+        }
+    }
+
+    let mut writes = Vec::new();
+    for (start, block) in blocks.iter() {
+        let mut i = i_in[start];
+        for (addr, inst) in block.instructions.iter() {
+            let (next, written) = step_i(inst, i);
+            match written {
+                Some(None) => writes.push(CodeWrite {
+                    addr: *addr,
+                    instruction: inst.clone(),
+                    target: None,
+                }),
+                Some(Some(r)) if r.clone().any(|a| code.get(a) == Some(&true)) => writes.push(CodeWrite {
+                    addr: *addr,
+                    instruction: inst.clone(),
+                    target: Some(r),
+                }),
+                _ => {}
+            }
+            i = next;
+        }
+    }
+    writes
+}
+
+impl Analysis {
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph rom {\n    node [shape=box fontname=\"monospace\"];\n");
+        let flagged: BTreeSet<usize> = self
+            .computed_jumps
+            .iter()
+            .map(|(a, _)| *a)
+            .chain(self.code_writes.iter().map(|w| w.addr))
+            .collect();
+        for (i, s) in self.subroutines.iter().enumerate() {
+            _ = writeln!(
+                out,
+                "    subgraph cluster_{i} {{\n        label=\"sub {:03x}\";",
+                s.entry
+            );
+            for b in s.blocks.iter() {
+                _ = writeln!(out, "        b{b:03x};");
+            }
+            _ = writeln!(out, "    }}");
+        }
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (addr, inst) in block.instructions.iter() {
+                _ = write!(label, "{addr:03x}: {inst}\\l");
+            }
+            let color = match block.instructions.iter().any(|(a, _)| flagged.contains(a)) {
+                true => " color=red",
+                false => "",
+            };
+            _ = writeln!(out, "    b{:03x} [label=\"{label}\"{color}];", block.start);
+            for e in block.successors.iter() {
+                let style = match e.kind {
+                    EdgeKind::FallThrough => "",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Call => " [label=\"call\" style=dashed]",
+                };
+                _ = writeln!(out, "    b{:03x} -> b{:03x}{style};", block.start, e.target);
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl std::fmt::Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{} basic blocks", self.blocks.len())?;
+        for s in self.subroutines.iter() {
+            writeln!(f, "subroutine {:#05x}: {} blocks", s.entry, s.blocks.len())?;
+        }
+        for r in self.unreachable.iter() {
+            writeln!(
+                f,
+                "never executed: {:#05x}..{:#05x} ({} bytes)",
+                r.start,
+                r.end,
+                r.len()
+            )?;
+        }
+        for (addr, inst) in self.computed_jumps.iter() {
+            writeln!(f, "computed jump at {addr:#05x}: {inst}")?;
+        }
+        for w in self.code_writes.iter() {
+            match &w.target {
+                Some(r) => writeln!(
+                    f,
+                    "self-modifying write at {:#05x}: {} writes {:#05x}..{:#05x}",
+                    w.addr, w.instruction, r.start, r.end
+                )?,
+                None => writeln!(
+                    f,
+                    "possible self-modifying write at {:#05x}: {} with unknown I",
+                    w.addr, w.instruction
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let rom = [
+            0x22, 0x08, // 200: CALL 0x208
+            0x3a, 0x00, // 202: SE VA, 0
+            0xb2, 0x10, // 204: JP V0, 0x210
+            0x12, 0x02, // 206: JP 0x202
+            0xa2, 0x02, // 208: LD I, 0x202
+            0xf1, 0x55, // 20a: LD [I], V1
+            0x00, 0xee, // 20c: RET
+            0xff, 0xff, // 20e: data
+        ];
+        let analysis = analyze(&rom);
+        let starts: Vec<usize> = analysis.blocks.keys().copied().collect();
+        assert_eq!(starts, vec![0x200, 0x202, 0x204, 0x206, 0x208]);
+        assert_eq!(
+            analysis.blocks[&0x202].successors,
+            vec![
+                Edge {
+                    target: 0x204,
+                    kind: EdgeKind::FallThrough
+                },
+                Edge {
+                    target: 0x206,
+                    kind: EdgeKind::Skip
+                }
+            ]
+        );
+        assert_eq!(analysis.subroutines.len(), 1);
+        assert_eq!(analysis.subroutines[0].entry, 0x208);
+        assert_eq!(analysis.unreachable, vec![0x20e..0x210]);
+        assert_eq!(analysis.computed_jumps[0].0, 0x204);
+        assert_eq!(analysis.code_writes[0].addr, 0x20a);
+        assert_eq!(analysis.code_writes[0].target, Some(0x202..0x204));
+
+        let dot = analysis.to_dot();
+        assert!(dot.contains("b200 -> b208 [label=\"call\" style=dashed];"));
+        assert!(dot.contains("b208 [label=\"208: LD I, 0x202\\l20a: LD [I], V1\\l20c: RET\\l\" color=red];"));
+
+        // nothing to follow when the entry point isn't code
+        for rom in [&[][..], &[0xff, 0xff, 0x12, 0x00]] {
+            let analysis = analyze(rom);
+            assert!(analysis.blocks.is_empty());
+            assert!(analysis.code_writes.is_empty());
+            assert_eq!(analysis.unreachable.len(), rom.len().min(1));
+        }
+    }
+}
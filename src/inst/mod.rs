@@ -30,6 +30,9 @@ pub enum Instruction {
     SkipIfRegisterEquals { register: u8, value: u8 },
     SkipIfRegisterNotEquals { register: u8, value: u8 },
     SkipIfRegistersEqual { register_1: u8, register_2: u8 },
+    SkipIfRegistersNotEqual { register_1: u8, register_2: u8 },
+    SkipIfKeyPressed { register: u8 },
+    SkipIfKeyNotPressed { register: u8 },
     SetRegister { register: u8, value: u8 },
     SetRegisterRandomBitwiseAnd { register: u8, and_operand: u8 },
     AddToRegister { register: u8, value: u8 },
@@ -39,9 +42,19 @@ pub enum Instruction {
     ApplyBitwiseXor { value_register: u8, operand_register: u8 },
     AddRegisters { value_register: u8, operand_register: u8 },
     SubtractRegisters { value_register: u8, operand_register: u8 },
+    SubtractRegistersReversed { value_register: u8, operand_register: u8 },
+    ShiftRight { value_register: u8, operand_register: u8 },
+    ShiftLeft { value_register: u8, operand_register: u8 },
     GetKey { register: u8 },
+    GetDelayTimer { register: u8 },
+    SetDelayTimer { register: u8 },
+    SetSoundTimer { register: u8 },
     SetI { addr: u16 },
+    AddToI { register: u8 },
+    SetIToFontSprite { register: u8 },
+    StoreBcd { register: u8 },
     DumpRegisters { end_register: u8 },
+    LoadRegisters { end_register: u8 },
     Draw { reg_x: u8, reg_y: u8, sprite_height: u8 },
     NoOp,
 }
@@ -107,6 +120,22 @@ impl Instruction {
                 value_register: r_value,
                 operand_register: r_op,
             }),
+            [0x8, r_value, r_op, 0x6] => Ok(Self::ShiftRight {
+                value_register: r_value,
+                operand_register: r_op,
+            }),
+            [0x8, r_value, r_op, 0x7] => Ok(Self::SubtractRegistersReversed {
+                value_register: r_value,
+                operand_register: r_op,
+            }),
+            [0x8, r_value, r_op, 0xe] => Ok(Self::ShiftLeft {
+                value_register: r_value,
+                operand_register: r_op,
+            }),
+            [0x9, r1, r2, 0x0] => Ok(Self::SkipIfRegistersNotEqual {
+                register_1: r1,
+                register_2: r2,
+            }),
             [0xa, _, _, _] => Ok(Self::SetI { addr: addr_12bit }),
             [0xb, _, _, _] => Ok(Self::GotoPlusV0 { addr: addr_12bit }),
             [0xc, reg, _, _] => Ok(Self::SetRegisterRandomBitwiseAnd {
@@ -118,13 +147,226 @@ impl Instruction {
                 reg_y,
                 sprite_height,
             }),
-            [0xf, r_end, 0x5, 0x5] => Ok(Self::DumpRegisters { end_register: r_end }),
+            [0xe, reg, 0x9, 0xe] => Ok(Self::SkipIfKeyPressed { register: reg }),
+            [0xe, reg, 0xa, 0x1] => Ok(Self::SkipIfKeyNotPressed { register: reg }),
+            [0xf, reg, 0x0, 0x7] => Ok(Self::GetDelayTimer { register: reg }),
             [0xf, reg, 0x0, 0xa] => Ok(Self::GetKey { register: reg }),
+            [0xf, reg, 0x1, 0x5] => Ok(Self::SetDelayTimer { register: reg }),
+            [0xf, reg, 0x1, 0x8] => Ok(Self::SetSoundTimer { register: reg }),
+            [0xf, reg, 0x1, 0xe] => Ok(Self::AddToI { register: reg }),
+            [0xf, reg, 0x2, 0x9] => Ok(Self::SetIToFontSprite { register: reg }),
+            [0xf, reg, 0x3, 0x3] => Ok(Self::StoreBcd { register: reg }),
+            [0xf, r_end, 0x5, 0x5] => Ok(Self::DumpRegisters { end_register: r_end }),
+            [0xf, r_end, 0x6, 0x5] => Ok(Self::LoadRegisters { end_register: r_end }),
             _ => Err(UnknownInstructionError {
                 bytes: instruction_bytes,
             }),
         }
     }
+
+    pub fn flow(&self) -> Flow {
+        match *self {
+            Self::Goto { addr } => Flow::Jump(addr),
+            Self::GotoPlusV0 { addr } => Flow::ComputedJump(addr),
+            Self::CallSubroutine { addr } => Flow::Call(addr),
+            Self::Return => Flow::Return,
+            Self::SkipIfRegisterEquals { .. }
+            | Self::SkipIfRegisterNotEquals { .. }
+            | Self::SkipIfRegistersEqual { .. }
+            | Self::SkipIfRegistersNotEqual { .. }
+            | Self::SkipIfKeyPressed { .. }
+            | Self::SkipIfKeyNotPressed { .. } => Flow::Skip,
+            _ => Flow::Next,
+        }
+    }
+
+    // the memory address an instruction refers to, if any
+    pub fn address(&self) -> Option<u16> {
+        match *self {
+            Self::Call { addr }
+            | Self::Goto { addr }
+            | Self::GotoPlusV0 { addr }
+            | Self::CallSubroutine { addr }
+            | Self::SetI { addr } => Some(addr),
+            _ => None,
+        }
+    }
+
+    // inverse of parse, NoOp has no encoding of its own and is emitted as 0x0000
+    pub fn encode(&self) -> [u8; 2] {
+        match *self {
+            Self::Call { addr } => encode_addr(0x0, addr),
+            Self::ClearDisplay => [0x00, 0xe0],
+            Self::Return => [0x00, 0xee],
+            Self::Goto { addr } => encode_addr(0x1, addr),
+            Self::GotoPlusV0 { addr } => encode_addr(0xb, addr),
+            Self::CallSubroutine { addr } => encode_addr(0x2, addr),
+            Self::SkipIfRegisterEquals { register, value } => encode_byte(0x3, register, value),
+            Self::SkipIfRegisterNotEquals { register, value } => encode_byte(0x4, register, value),
+            Self::SkipIfRegistersEqual { register_1, register_2 } => encode_nibbles(0x5, register_1, register_2, 0x0),
+            Self::SkipIfRegistersNotEqual { register_1, register_2 } => {
+                encode_nibbles(0x9, register_1, register_2, 0x0)
+            }
+            Self::SkipIfKeyPressed { register } => encode_byte(0xe, register, 0x9e),
+            Self::SkipIfKeyNotPressed { register } => encode_byte(0xe, register, 0xa1),
+            Self::SetRegister { register, value } => encode_byte(0x6, register, value),
+            Self::SetRegisterRandomBitwiseAnd { register, and_operand } => encode_byte(0xc, register, and_operand),
+            Self::AddToRegister { register, value } => encode_byte(0x7, register, value),
+            Self::CopyRegister {
+                src_register,
+                dst_register,
+            } => encode_nibbles(0x8, dst_register, src_register, 0x0),
+            Self::ApplyBitwiseOr {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x1),
+            Self::ApplyBitwiseAnd {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x2),
+            Self::ApplyBitwiseXor {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x3),
+            Self::AddRegisters {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x4),
+            Self::SubtractRegisters {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x5),
+            Self::ShiftRight {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x6),
+            Self::SubtractRegistersReversed {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0x7),
+            Self::ShiftLeft {
+                value_register,
+                operand_register,
+            } => encode_nibbles(0x8, value_register, operand_register, 0xe),
+            Self::GetKey { register } => encode_byte(0xf, register, 0x0a),
+            Self::GetDelayTimer { register } => encode_byte(0xf, register, 0x07),
+            Self::SetDelayTimer { register } => encode_byte(0xf, register, 0x15),
+            Self::SetSoundTimer { register } => encode_byte(0xf, register, 0x18),
+            Self::SetI { addr } => encode_addr(0xa, addr),
+            Self::AddToI { register } => encode_byte(0xf, register, 0x1e),
+            Self::SetIToFontSprite { register } => encode_byte(0xf, register, 0x29),
+            Self::StoreBcd { register } => encode_byte(0xf, register, 0x33),
+            Self::DumpRegisters { end_register } => encode_byte(0xf, end_register, 0x55),
+            Self::LoadRegisters { end_register } => encode_byte(0xf, end_register, 0x65),
+            Self::Draw {
+                reg_x,
+                reg_y,
+                sprite_height,
+            } => encode_nibbles(0xd, reg_x, reg_y, sprite_height),
+            Self::NoOp => [0x00, 0x00],
+        }
+    }
+}
+
+// how an instruction affects the program counter, from the point of view of a static analysis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Next,
+    Skip,
+    Jump(u16),
+    Call(u16),
+    Return,
+    ComputedJump(u16),
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Call { addr } => write!(f, "SYS {addr:#05x}"),
+            Self::ClearDisplay => write!(f, "CLS"),
+            Self::Return => write!(f, "RET"),
+            Self::Goto { addr } => write!(f, "JP {addr:#05x}"),
+            Self::GotoPlusV0 { addr } => write!(f, "JP V0, {addr:#05x}"),
+            Self::CallSubroutine { addr } => write!(f, "CALL {addr:#05x}"),
+            Self::SkipIfRegisterEquals { register, value } => write!(f, "SE V{register:X}, {value:#04x}"),
+            Self::SkipIfRegisterNotEquals { register, value } => write!(f, "SNE V{register:X}, {value:#04x}"),
+            Self::SkipIfRegistersEqual { register_1, register_2 } => write!(f, "SE V{register_1:X}, V{register_2:X}"),
+            Self::SkipIfRegistersNotEqual { register_1, register_2 } => {
+                write!(f, "SNE V{register_1:X}, V{register_2:X}")
+            }
+            Self::SkipIfKeyPressed { register } => write!(f, "SKP V{register:X}"),
+            Self::SkipIfKeyNotPressed { register } => write!(f, "SKNP V{register:X}"),
+            Self::SetRegister { register, value } => write!(f, "LD V{register:X}, {value:#04x}"),
+            Self::SetRegisterRandomBitwiseAnd { register, and_operand } => {
+                write!(f, "RND V{register:X}, {and_operand:#04x}")
+            }
+            Self::AddToRegister { register, value } => write!(f, "ADD V{register:X}, {value:#04x}"),
+            Self::CopyRegister {
+                src_register,
+                dst_register,
+            } => write!(f, "LD V{dst_register:X}, V{src_register:X}"),
+            Self::ApplyBitwiseOr {
+                value_register,
+                operand_register,
+            } => write!(f, "OR V{value_register:X}, V{operand_register:X}"),
+            Self::ApplyBitwiseAnd {
+                value_register,
+                operand_register,
+            } => write!(f, "AND V{value_register:X}, V{operand_register:X}"),
+            Self::ApplyBitwiseXor {
+                value_register,
+                operand_register,
+            } => write!(f, "XOR V{value_register:X}, V{operand_register:X}"),
+            Self::AddRegisters {
+                value_register,
+                operand_register,
+            } => write!(f, "ADD V{value_register:X}, V{operand_register:X}"),
+            Self::SubtractRegisters {
+                value_register,
+                operand_register,
+            } => write!(f, "SUB V{value_register:X}, V{operand_register:X}"),
+            Self::SubtractRegistersReversed {
+                value_register,
+                operand_register,
+            } => write!(f, "SUBN V{value_register:X}, V{operand_register:X}"),
+            Self::ShiftRight {
+                value_register,
+                operand_register,
+            } => write!(f, "SHR V{value_register:X}, V{operand_register:X}"),
+            Self::ShiftLeft {
+                value_register,
+                operand_register,
+            } => write!(f, "SHL V{value_register:X}, V{operand_register:X}"),
+            Self::GetKey { register } => write!(f, "LD V{register:X}, K"),
+            Self::GetDelayTimer { register } => write!(f, "LD V{register:X}, DT"),
+            Self::SetDelayTimer { register } => write!(f, "LD DT, V{register:X}"),
+            Self::SetSoundTimer { register } => write!(f, "LD ST, V{register:X}"),
+            Self::SetI { addr } => write!(f, "LD I, {addr:#05x}"),
+            Self::AddToI { register } => write!(f, "ADD I, V{register:X}"),
+            Self::SetIToFontSprite { register } => write!(f, "LD F, V{register:X}"),
+            Self::StoreBcd { register } => write!(f, "LD B, V{register:X}"),
+            Self::DumpRegisters { end_register } => write!(f, "LD [I], V{end_register:X}"),
+            Self::LoadRegisters { end_register } => write!(f, "LD V{end_register:X}, [I]"),
+            Self::Draw {
+                reg_x,
+                reg_y,
+                sprite_height,
+            } => write!(f, "DRW V{reg_x:X}, V{reg_y:X}, {sprite_height}"),
+            Self::NoOp => write!(f, "NOP"),
+        }
+    }
+}
+
+fn encode_nibbles(n0: u8, n1: u8, n2: u8, n3: u8) -> [u8; 2] {
+    [(n0 << 4) | (n1 & 0xf), (n2 << 4) | (n3 & 0xf)]
+}
+
+fn encode_addr(n0: u8, addr: u16) -> [u8; 2] {
+    [(n0 << 4) | ((addr >> 8) as u8 & 0xf), addr as u8]
+}
+
+fn encode_byte(n0: u8, register: u8, value: u8) -> [u8; 2] {
+    [(n0 << 4) | (register & 0xf), value]
 }
 
 #[cfg(test)]
@@ -145,4 +387,28 @@ mod tests {
             })
         ));
     }
+
+    #[test]
+    fn test_instruction_encode() {
+        for bs in [
+            [0x00, 0xe0],
+            [0x00, 0xee],
+            [0x12, 0x34],
+            [0x2a, 0xbc],
+            [0x3a, 0x29],
+            [0x5a, 0xb0],
+            [0x88, 0x95],
+            [0x8a, 0xbe],
+            [0x9c, 0xd0],
+            [0xb3, 0x00],
+            [0xda, 0xb5],
+            [0xe4, 0x9e],
+            [0xe4, 0xa1],
+            [0xf8, 0x55],
+            [0xf2, 0x65],
+            [0xf1, 0x33],
+        ] {
+            assert_eq!(Instruction::parse(bs).unwrap().encode(), bs);
+        }
+    }
 }
@@ -0,0 +1,642 @@
+#![allow(dead_code)]
+use crate::flow::{self, Analysis};
+use crate::inst::Instruction;
+use crate::mem::Memory;
+use crate::proc::{Processor, Quirks};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+static ORIGIN: usize = 512;
+static DISPLAY_WIDTH: usize = 64;
+static DISPLAY_HEIGHT: usize = 32;
+static INSTRUCTIONS_PER_FRAME: usize = 10;
+// how many blocks to follow when looking for the next use of I or VF
+static SEARCH_DEPTH: usize = 16;
+
+type Profile = (&'static str, fn() -> Quirks);
+
+pub static PROFILES: [Profile; 3] = [
+    ("chip8", Quirks::chip8),
+    ("schip", Quirks::schip),
+    ("xochip", Quirks::xochip),
+];
+
+// the quirks of a profile by its name, as given to --quirks
+pub fn quirks(name: &str) -> Result<Quirks, String> {
+    PROFILES
+        .iter()
+        .find(|(profile, _)| *profile == name)
+        .map(|(_, quirks)| quirks())
+        .ok_or(format!("unknown quirks '{name}', expected chip8, schip or xochip"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quirk {
+    Shift,
+    LoadStore,
+    Jump,
+    Clip,
+    VfReset,
+}
+
+impl Quirk {
+    pub fn enabled(&self, quirks: &Quirks) -> bool {
+        match self {
+            Quirk::Shift => quirks.shift_uses_vy,
+            Quirk::LoadStore => quirks.load_store_increments_i,
+            Quirk::Jump => quirks.jump_uses_vx,
+            Quirk::Clip => quirks.clip_sprites,
+            Quirk::VfReset => quirks.logic_resets_vf,
+        }
+    }
+
+    // what the program sees with the quirk turned on or off
+    pub fn describe(&self, enabled: bool) -> &'static str {
+        match (self, enabled) {
+            (Quirk::Shift, true) => "shifts Vy into Vx",
+            (Quirk::Shift, false) => "shifts Vx in place",
+            (Quirk::LoadStore, true) => "I advances past the registers",
+            (Quirk::LoadStore, false) => "I stays unchanged",
+            (Quirk::Jump, true) => "jumps to xnn + Vx",
+            (Quirk::Jump, false) => "jumps to nnn + V0",
+            (Quirk::Clip, true) => "sprites are clipped",
+            (Quirk::Clip, false) => "sprites wrap around",
+            (Quirk::VfReset, true) => "VF is reset",
+            (Quirk::VfReset, false) => "VF is kept",
+        }
+    }
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Quirk::Shift => "shift",
+            Quirk::LoadStore => "load/store",
+            Quirk::Jump => "jump",
+            Quirk::Clip => "clip",
+            Quirk::VfReset => "vf reset",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub quirk: Quirk,
+    pub addr: usize,
+    pub instruction: Instruction,
+    // whether the code appears to rely on the quirk being on or off, if that can be told
+    pub expects: Option<bool>,
+    // seen in the trace with values for which the profiles disagree
+    pub dynamic: bool,
+    pub note: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub profile: &'static str,
+    pub conflicts: Vec<String>,
+    pub ambiguous: BTreeSet<Quirk>,
+    pub error: Option<String>,
+    pub executed: Option<usize>,
+}
+
+impl Verdict {
+    pub fn is_safe(&self) -> bool {
+        self.conflicts.is_empty() && self.ambiguous.is_empty() && self.error.is_none()
+    }
+
+    // lower is better
+    fn rank(&self) -> (bool, usize, usize) {
+        (self.error.is_some(), self.conflicts.len(), self.ambiguous.len())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    pub verdicts: Vec<Verdict>,
+    pub recommended: &'static str,
+}
+
+enum Access {
+    Use,
+    Kill,
+    Neither,
+}
+
+fn i_access(inst: &Instruction) -> Access {
+    match inst {
+        Instruction::Draw { .. }
+        | Instruction::DumpRegisters { .. }
+        | Instruction::LoadRegisters { .. }
+        | Instruction::StoreBcd { .. }
+        | Instruction::AddToI { .. } => Access::Use,
+        Instruction::SetI { .. } | Instruction::SetIToFontSprite { .. } => Access::Kill,
+        _ => Access::Neither,
+    }
+}
+
+fn vf_access(inst: &Instruction) -> Access {
+    if reads(inst, 0xf) {
+        Access::Use
+    } else if writes(inst, 0xf) {
+        Access::Kill
+    } else {
+        Access::Neither
+    }
+}
+
+fn reads(inst: &Instruction, r: u8) -> bool {
+    match *inst {
+        Instruction::SkipIfRegisterEquals { register, .. }
+        | Instruction::SkipIfRegisterNotEquals { register, .. }
+        | Instruction::SkipIfKeyPressed { register }
+        | Instruction::SkipIfKeyNotPressed { register }
+        | Instruction::AddToRegister { register, .. }
+        | Instruction::SetDelayTimer { register }
+        | Instruction::SetSoundTimer { register }
+        | Instruction::AddToI { register }
+        | Instruction::SetIToFontSprite { register }
+        | Instruction::StoreBcd { register } => register == r,
+        Instruction::SkipIfRegistersEqual { register_1, register_2 }
+        | Instruction::SkipIfRegistersNotEqual { register_1, register_2 } => register_1 == r || register_2 == r,
+        Instruction::CopyRegister { src_register, .. } => src_register == r,
+        Instruction::ApplyBitwiseOr {
+            value_register,
+            operand_register,
+        }
+        | Instruction::ApplyBitwiseAnd {
+            value_register,
+            operand_register,
+        }
+        | Instruction::ApplyBitwiseXor {
+            value_register,
+            operand_register,
+        }
+        | Instruction::AddRegisters {
+            value_register,
+            operand_register,
+        }
+        | Instruction::SubtractRegisters {
+            value_register,
+            operand_register,
+        }
+        | Instruction::SubtractRegistersReversed {
+            value_register,
+            operand_register,
+        }
+        | Instruction::ShiftRight {
+            value_register,
+            operand_register,
+        }
+        | Instruction::ShiftLeft {
+            value_register,
+            operand_register,
+        } => value_register == r || operand_register == r,
+        Instruction::DumpRegisters { end_register } => r <= end_register,
+        Instruction::Draw { reg_x, reg_y, .. } => reg_x == r || reg_y == r,
+        Instruction::GotoPlusV0 { addr } => r == 0 || r == (addr >> 8) as u8,
+        _ => false,
+    }
+}
+
+fn writes(inst: &Instruction, r: u8) -> bool {
+    match *inst {
+        Instruction::SetRegister { register, .. }
+        | Instruction::SetRegisterRandomBitwiseAnd { register, .. }
+        | Instruction::AddToRegister { register, .. }
+        | Instruction::GetKey { register }
+        | Instruction::GetDelayTimer { register } => register == r,
+        Instruction::CopyRegister { dst_register, .. } => dst_register == r,
+        // whether these touch VF is the quirk itself
+        Instruction::ApplyBitwiseOr { value_register, .. }
+        | Instruction::ApplyBitwiseAnd { value_register, .. }
+        | Instruction::ApplyBitwiseXor { value_register, .. } => value_register == r,
+        Instruction::AddRegisters { value_register, .. }
+        | Instruction::SubtractRegisters { value_register, .. }
+        | Instruction::SubtractRegistersReversed { value_register, .. }
+        | Instruction::ShiftRight { value_register, .. }
+        | Instruction::ShiftLeft { value_register, .. } => value_register == r || r == 0xf,
+        Instruction::LoadRegisters { end_register } => r <= end_register,
+        Instruction::Draw { .. } => r == 0xf,
+        _ => false,
+    }
+}
+
+// the first instruction after position `index` of the block at `start` that uses what `access` tracks,
+// on any path that doesn't overwrite it first
+fn next_use(
+    analysis: &Analysis,
+    start: usize,
+    index: usize,
+    access: fn(&Instruction) -> Access,
+) -> Option<(usize, Instruction)> {
+    let mut queue = VecDeque::from([(start, index + 1)]);
+    let mut visited = BTreeSet::from([start]);
+    while let Some((start, index)) = queue.pop_front() {
+        let block = &analysis.blocks[&start];
+        let mut killed = false;
+        for (addr, inst) in block.instructions.iter().skip(index) {
+            match access(inst) {
+                Access::Use => return Some((*addr, inst.clone())),
+                Access::Kill => {
+                    killed = true;
+                    break;
+                }
+                Access::Neither => {}
+            }
+        }
+        if killed {
+            continue;
+        }
+        for edge in block.successors.iter() {
+            if visited.len() < SEARCH_DEPTH && analysis.blocks.contains_key(&edge.target) && visited.insert(edge.target)
+            {
+                queue.push_back((edge.target, 0));
+            }
+        }
+    }
+    None
+}
+
+// single operand assemblers encode `shr vx` with y = 0, anything else names a source register on purpose.
+// only asked about shifts with x != y
+fn shift_expectation(y: u8) -> bool {
+    y != 0
+}
+
+// chained loads and stores only make sense if I moves on
+fn load_store_expectation(next: &Instruction) -> Option<bool> {
+    match next {
+        Instruction::DumpRegisters { .. } | Instruction::LoadRegisters { .. } | Instruction::StoreBcd { .. } => {
+            Some(true)
+        }
+        _ => None,
+    }
+}
+
+fn scan(analysis: &Analysis) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for block in analysis.blocks.values() {
+        for (index, (addr, inst)) in block.instructions.iter().enumerate() {
+            let finding = |quirk, expects, note: String| Finding {
+                quirk,
+                addr: *addr,
+                instruction: inst.clone(),
+                expects,
+                dynamic: false,
+                note,
+            };
+            match *inst {
+                Instruction::ShiftRight {
+                    value_register: x,
+                    operand_register: y,
+                }
+                | Instruction::ShiftLeft {
+                    value_register: x,
+                    operand_register: y,
+                } if x != y => {
+                    findings.push(finding(
+                        Quirk::Shift,
+                        Some(shift_expectation(y)),
+                        format!("shift with V{x:X} != V{y:X}"),
+                    ));
+                }
+                Instruction::DumpRegisters { .. } | Instruction::LoadRegisters { .. } => {
+                    if let Some((at, next)) = next_use(analysis, block.start, index, i_access) {
+                        findings.push(finding(
+                            Quirk::LoadStore,
+                            load_store_expectation(&next),
+                            format!("I is used again by {next} at {at:#05x}"),
+                        ));
+                    }
+                }
+                Instruction::GotoPlusV0 { addr: target } if target >> 8 != 0 => {
+                    let x = (target >> 8) as u8;
+                    let before = &block.instructions[..index];
+                    let sets_vx = before.iter().any(|(_, i)| writes(i, x));
+                    let sets_v0 = before.iter().any(|(_, i)| writes(i, 0));
+                    let expects = match (sets_vx, sets_v0) {
+                        (true, false) => Some(true),
+                        (false, true) => Some(false),
+                        _ => None,
+                    };
+                    findings.push(finding(
+                        Quirk::Jump,
+                        expects,
+                        format!("computed jump could be offset by V0 or V{x:X}"),
+                    ));
+                }
+                Instruction::ApplyBitwiseOr { value_register, .. }
+                | Instruction::ApplyBitwiseAnd { value_register, .. }
+                | Instruction::ApplyBitwiseXor { value_register, .. } => {
+                    if value_register == 0xf {
+                        findings.push(finding(
+                            Quirk::VfReset,
+                            Some(false),
+                            "logic result is written to VF".to_string(),
+                        ));
+                    } else if let Some((at, next)) = next_use(analysis, block.start, index, vf_access) {
+                        findings.push(finding(
+                            Quirk::VfReset,
+                            None,
+                            format!("VF is read by {next} at {at:#05x}"),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    findings
+}
+
+// whether a sprite has set pixels beyond the right or bottom edge
+fn crosses_edge(memory: &Memory, i: u16, x: u8, y: u8, height: u8) -> bool {
+    let x = x as usize % DISPLAY_WIDTH;
+    let y = y as usize % DISPLAY_HEIGHT;
+    let overflow = (x + 8).saturating_sub(DISPLAY_WIDTH);
+    (0..height as usize).any(|row| {
+        let b = memory.get_byte(i as usize + row).unwrap_or(0);
+        b != 0 && (y + row >= DISPLAY_HEIGHT || b as u16 & ((1 << overflow) - 1) != 0)
+    })
+}
+
+// runs the rom under one profile, returning the quirk sensitive instructions that were actually hit
+fn trace(rom: &[u8], quirks: Quirks, cycles: usize, verdict: &mut Verdict) -> Vec<Finding> {
+    let mut memory = Memory::new();
+    if memory.load_array(ORIGIN, rom).is_err() {
+        verdict.error = Some("rom does not fit into memory".to_string());
+        return Vec::new();
+    }
+    let mut proc = Processor::new(memory, Arc::new(Mutex::new([0; 2048])));
+    proc.quirks = quirks;
+
+    let mut findings = Vec::new();
+    // the last load/store and logic instruction whose side effect hasn't been observed or overwritten yet
+    let mut load_store: Option<(usize, Instruction)> = None;
+    let mut logic: Option<(usize, Instruction)> = None;
+    let mut executed = 0;
+    while executed < cycles {
+        let pc = proc.pc;
+        // an instruction that can't be fetched or decoded ends the trace, the rest of the program goes unchecked
+        let inst = match proc.memory.get_word(pc) {
+            Ok(bytes) => match Instruction::parse(bytes) {
+                Ok(inst) => inst,
+                Err(e) => {
+                    verdict.error = Some(format!("{e} at {pc:#05x}"));
+                    break;
+                }
+            },
+            Err(e) => {
+                verdict.error = Some(format!("{e} at {pc:#05x}"));
+                break;
+            }
+        };
+        // nobody is there to press a key
+        if let Instruction::GetKey { .. } = inst {
+            break;
+        }
+        let vs = proc.registers.as_array();
+        let mut finding = |quirk, from: &(usize, Instruction), expects, note| {
+            findings.push(Finding {
+                quirk,
+                addr: from.0,
+                instruction: from.1.clone(),
+                expects,
+                dynamic: true,
+                note,
+            })
+        };
+
+        match i_access(&inst) {
+            Access::Use => {
+                if let Some(from) = load_store.take() {
+                    let note = format!("I is used again by {inst} at {pc:#05x}");
+                    finding(Quirk::LoadStore, &from, load_store_expectation(&inst), note);
+                }
+            }
+            Access::Kill => load_store = None,
+            Access::Neither => {}
+        }
+        match vf_access(&inst) {
+            Access::Use => {
+                if let Some(from) = logic.take() {
+                    finding(
+                        Quirk::VfReset,
+                        &from,
+                        None,
+                        format!("VF is read by {inst} at {pc:#05x}"),
+                    );
+                }
+            }
+            Access::Kill => logic = None,
+            Access::Neither => {}
+        }
+        let here = (pc, inst.clone());
+        match inst {
+            Instruction::ShiftRight {
+                value_register: x,
+                operand_register: y,
+            }
+            | Instruction::ShiftLeft {
+                value_register: x,
+                operand_register: y,
+            } if vs[x as usize] != vs[y as usize] => {
+                let note = format!("shift with V{x:X} != V{y:X}");
+                finding(Quirk::Shift, &here, Some(shift_expectation(y)), note);
+            }
+            Instruction::GotoPlusV0 { addr } if vs[(addr >> 8) as usize] != vs[0] => {
+                let note = format!("computed jump could be offset by V0 or V{:X}", addr >> 8);
+                finding(Quirk::Jump, &here, None, note);
+            }
+            Instruction::Draw {
+                reg_x,
+                reg_y,
+                sprite_height,
+            } if crosses_edge(
+                &proc.memory,
+                proc.i,
+                vs[reg_x as usize],
+                vs[reg_y as usize],
+                sprite_height,
+            ) =>
+            {
+                finding(
+                    Quirk::Clip,
+                    &here,
+                    None,
+                    "sprite drawn across the screen edge".to_string(),
+                );
+            }
+            Instruction::DumpRegisters { .. } | Instruction::LoadRegisters { .. } => load_store = Some(here),
+            Instruction::ApplyBitwiseOr { .. }
+            | Instruction::ApplyBitwiseAnd { .. }
+            | Instruction::ApplyBitwiseXor { .. } => logic = Some(here),
+            _ => {}
+        }
+
+        if let Err(e) = proc.execute() {
+            verdict.error = Some(format!("{e} at {pc:#05x}"));
+            break;
+        }
+        executed += 1;
+        if executed % INSTRUCTIONS_PER_FRAME == 0 {
+            proc.tick_timers();
+        }
+    }
+    verdict.executed = Some(executed);
+    findings
+}
+
+pub fn lint(rom: &[u8], cycles: Option<usize>) -> Report {
+    let analysis = flow::analyze(rom);
+    let mut findings: BTreeMap<(Quirk, usize), Finding> = BTreeMap::new();
+    for f in scan(&analysis) {
+        findings.entry((f.quirk, f.addr)).or_insert(f);
+    }
+
+    let mut verdicts: Vec<Verdict> = PROFILES
+        .iter()
+        .map(|(profile, _)| Verdict {
+            profile,
+            conflicts: Vec::new(),
+            ambiguous: BTreeSet::new(),
+            error: None,
+            executed: None,
+        })
+        .collect();
+    if let Some(cycles) = cycles {
+        for ((_, quirks), verdict) in PROFILES.iter().zip(verdicts.iter_mut()) {
+            for f in trace(rom, quirks(), cycles, verdict) {
+                findings
+                    .entry((f.quirk, f.addr))
+                    .and_modify(|known| {
+                        known.dynamic = true;
+                        known.expects = known.expects.or(f.expects);
+                    })
+                    .or_insert(f);
+            }
+        }
+    }
+
+    for ((_, quirks), verdict) in PROFILES.iter().zip(verdicts.iter_mut()) {
+        let quirks = quirks();
+        for f in findings.values() {
+            let enabled = f.quirk.enabled(&quirks);
+            match f.expects {
+                Some(expected) if expected != enabled => verdict.conflicts.push(format!(
+                    "{} at {:#05x} expects {}, but here {}",
+                    f.quirk,
+                    f.addr,
+                    f.quirk.describe(expected),
+                    f.quirk.describe(enabled)
+                )),
+                Some(_) => {}
+                None => {
+                    verdict.ambiguous.insert(f.quirk);
+                }
+            }
+        }
+    }
+
+    // min_by_key keeps the first of equals, so ties go to the older platform
+    let recommended = verdicts.iter().min_by_key(|v| v.rank()).unwrap().profile;
+    Report {
+        findings: findings.into_values().collect(),
+        verdicts,
+        recommended,
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "findings: {}", self.findings.len())?;
+        for finding in self.findings.iter() {
+            let expects = match finding.expects {
+                Some(enabled) => format!(", expects {}", finding.quirk.describe(enabled)),
+                None => String::new(),
+            };
+            writeln!(
+                f,
+                "  {:#05x}  {:<16} {:<10} {:<6} {}{}",
+                finding.addr,
+                finding.instruction.to_string(),
+                finding.quirk.to_string(),
+                if finding.dynamic { "trace" } else { "static" },
+                finding.note,
+                expects
+            )?;
+        }
+        writeln!(f, "profiles:")?;
+        for v in self.verdicts.iter() {
+            let status = if v.error.is_some() || !v.conflicts.is_empty() {
+                "unsafe"
+            } else if !v.ambiguous.is_empty() {
+                "unsure"
+            } else {
+                "safe"
+            };
+            write!(f, "  {:<7} {}", v.profile, status)?;
+            if let Some(executed) = v.executed {
+                write!(f, " ({executed} instructions traced)")?;
+            }
+            writeln!(f)?;
+            if let Some(e) = &v.error {
+                writeln!(f, "    trace failed: {e}")?;
+            }
+            for c in v.conflicts.iter() {
+                writeln!(f, "    {c}")?;
+            }
+            if !v.ambiguous.is_empty() {
+                let quirks: Vec<String> = v.ambiguous.iter().map(|q| q.to_string()).collect();
+                writeln!(f, "    depends on: {}", quirks.join(", "))?;
+            }
+        }
+        writeln!(f, "recommended profile: {}", self.recommended)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint() {
+        let rom = [
+            0x60, 0x05, // LD V0, 0x05
+            0x82, 0x06, // SHR V2, V0 (y = 0, single operand form)
+            0xa3, 0x00, // LD I, 0x300
+            0xf1, 0x55, // LD [I], V1
+            0xf1, 0x55, // LD [I], V1 (chained)
+            0x61, 0x3e, // LD V1, 0x3e
+            0xa2, 0x02, // LD I, 0x202
+            0xd1, 0x01, // DRW V1, V0, 1 (crosses the right edge)
+            0x12, 0x10, // JP 0x210
+        ];
+        let report = lint(&rom, Some(100));
+        let quirks: Vec<Quirk> = report.findings.iter().map(|f| f.quirk).collect();
+        assert_eq!(quirks, vec![Quirk::Shift, Quirk::LoadStore, Quirk::Clip]);
+        assert!(report.findings.iter().all(|f| f.dynamic));
+
+        // in-place shifts and advancing I aren't both offered by any profile
+        assert!(report.verdicts.iter().all(|v| !v.is_safe()));
+        assert_eq!(report.verdicts[1].conflicts.len(), 1);
+        assert_eq!(report.verdicts[0].executed, Some(100));
+
+        // the entry point doesn't decode, there is nothing to lint but the rom can't be called safe either
+        let report = lint(&[0xff, 0xff, 0x12, 0x00], Some(100));
+        assert!(report.findings.is_empty());
+        assert!(report.verdicts.iter().all(|v| !v.is_safe() && v.executed == Some(0)));
+        assert_eq!(
+            report.verdicts[0].error.as_deref(),
+            Some("unknown instruction: 0xffff at 0x200")
+        );
+        assert!(lint(&[], None).findings.is_empty());
+
+        assert_eq!(super::quirks("schip"), Ok(Quirks::schip()));
+        assert!(super::quirks("vip").is_err());
+    }
+}
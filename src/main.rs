@@ -1,25 +1,688 @@
 // use std::{io, path::Path};
 // use getch_rs::{Getch, Key};
+mod asm;
+mod cov;
+mod dbg;
+mod decomp;
+mod flow;
 mod inst;
+mod lint;
 mod mem;
+mod octo;
 mod proc;
+mod prof;
+mod rec;
 mod reg;
+mod shot;
+mod sprite;
+mod trace;
 
 extern crate sdl2;
 
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, io, process};
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 
 mod disp;
+
+static USAGE: &str = "usage: chip_8 [--trace text|jsonl|bin [--trace-out <file>] [--trace-pc <start>..<end>]
+                [--trace-class <digit>,..] [--trace-frames <start>..<end>]] [--ring <instructions>]
+               [asm|octo <source> [-o <rom>] [--sym <symbols>] [--map <source map>]]
+               [decomp <rom> [--octo] [-o <source>]]
+               [flow <rom> [--dot <graph>]]
+               [lint <rom> [--trace <instructions>]]
+               [dbg <rom> [--sym <symbols>] [--quirks chip8|schip|xochip] [--window [--heatmap] [--provenance]
+                [--filter none|phosphor[:<frames>]|blend|erase-free] [--scaling integer|fit] [--fullscreen]
+                [--theme classic|green|amber|lcd|high-contrast|colorblind|<file>] [--record <gif>|<png>]
+                [--sprites <start>..<end> [--layout 8x<n>|16x16] [--columns <n>]] | --gdb <port>]]
+               [dap]
+               [trace <rom> [--cycles <n>] [--quirks chip8|schip|xochip] [--format text|jsonl|bin|ref] [-o <file>]]
+               [tracediff <trace> <trace> [--context <lines>]]
+               [profile <rom> [--cycles <n>] [--quirks chip8|schip|xochip] [--sym <symbols>] [--listing <file>]
+                [--folded <file>]]
+               [coverage <rom> [--cycles <n>] [--quirks chip8|schip|xochip] [--keys <script>] [--sym <symbols>]
+                [-o <report>] [--lcov <file> [--map <source map>]]]
+               [sprites <rom> -o <png> [--range <start>..<end>] [--layout 8x<n>|16x16] [--columns <n>] [--scale <n>]
+                [--cycles <n>] [--quirks chip8|schip|xochip]]
+               [screenshot <rom> -o <file> [--format png|pbm|pgm|svg] [--scale <n>] [--theme <name>|<file>]
+                [--cycles <n>] [--quirks chip8|schip|xochip] [--keys <script>]]
+               [record <rom> -o <gif>|<png> [--frames <n>] [--scale <n>] [--theme <name>|<file>]
+                [--quirks chip8|schip|xochip] [--keys <script>]]
+in a window F1 shows registers, timers, the stack and the code around pc next to the display, F2 switches between
+display filters, F3 between integer scaling and fitting the window, F4 between themes, F11 toggles fullscreen,
+F12 saves a screenshot and F9 starts or stops recording to a gif";
+
+// returns the value following `flag` in args, if present
+fn option<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
+
+fn assemble(args: &[String], octo: bool) -> Result<(), Box<dyn Error>> {
+    let source = args.first().ok_or(USAGE)?;
+    let source = Path::new(source);
+    let rom = option(args, "-o")
+        .map(PathBuf::from)
+        .unwrap_or(source.with_extension("ch8"));
+    let symbols = option(args, "--sym")
+        .map(PathBuf::from)
+        .unwrap_or(rom.with_extension("sym"));
+    let map = option(args, "--map")
+        .map(PathBuf::from)
+        .unwrap_or(rom.with_extension("map"));
+
+    let program = if octo {
+        octo::compile(source)?
+    } else {
+        asm::assemble(source)?
+    };
+    fs::write(&rom, &program.bytes)?;
+    fs::write(&symbols, program.symbols.to_string())?;
+    fs::write(&map, program.lines.to_string())?;
+    println!(
+        "{}: {} bytes, {} symbols",
+        rom.display(),
+        program.bytes.len(),
+        program.symbols.symbols.len()
+    );
+    Ok(())
+}
+
+fn decompile(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let syntax = match args.iter().any(|a| a == "--octo") {
+        true => decomp::Syntax::Octo,
+        false => decomp::Syntax::Asm,
+    };
+    let source = decomp::decompile(&rom, syntax);
+    match option(args, "-o") {
+        Some(p) => fs::write(p, source)?,
+        None => print!("{source}"),
+    }
+    Ok(())
+}
+
+fn analyze(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let analysis = flow::analyze(&rom);
+    print!("{analysis}");
+    if let Some(p) = option(args, "--dot") {
+        fs::write(p, analysis.to_dot())?;
+    }
+    Ok(())
+}
+
+fn lint(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let cycles = match option(args, "--trace") {
+        Some(n) => Some(n.parse::<usize>().map_err(|_| USAGE)?),
+        None => None,
+    };
+    print!("{}", lint::lint(&rom, cycles));
+    Ok(())
+}
+
+// a debugger for running a rom without a window, seeded so runs using random numbers repeat and can be compared
+fn headless(
+    rom: &[u8],
+    args: &[String],
+    display_buffer: &Arc<Mutex<[u8; 2048]>>,
+) -> Result<dbg::Debugger, Box<dyn Error>> {
+    let mut debugger = dbg::Debugger::new(rom, asm::Symbols::default(), Arc::clone(display_buffer))?;
+    if let Some(name) = option(args, "--quirks") {
+        debugger.proc.quirks = lint::quirks(name)?;
+    }
+    debugger.proc.seed(0);
+    Ok(debugger)
+}
+
+// runs a rom without a window for a number of instructions, logging each one
+fn record_trace(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let cycles: u64 = match option(args, "--cycles") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 1000,
+    };
+    let out: Box<dyn io::Write + Send> = match option(args, "-o") {
+        Some(p) => Box::new(io::BufWriter::new(fs::File::create(p)?)),
+        None => Box::new(io::stdout()),
+    };
+    let mut tracer = trace::Tracer::new(32);
+    tracer
+        .sinks
+        .push(trace::sink(option(args, "--format").unwrap_or("ref"), out)?.ok_or(USAGE)?);
+
+    let mut debugger = headless(&rom, args, &Arc::new(Mutex::new([0; 2048])))?;
+    while debugger.cycles < cycles {
+        let (pc, cycle) = (debugger.proc.pc, debugger.cycles);
+        if let Err(e) = debugger.step() {
+            eprintln!("{e} at {pc:#05x}, after:");
+            tracer.dump(&mut io::stderr())?;
+            break;
+        }
+        let frame = cycle / 10;
+        tracer.record(trace::Record::capture(&debugger.proc, pc, cycle, frame))?;
+    }
+    // the sinks write through buffers that flush when dropped
+    drop(tracer);
+    Ok(())
+}
+
+fn diff_traces(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [a, b] = [args.first(), args.get(1)].map(|p| p.ok_or(USAGE));
+    let (a, b) = (a?, b?);
+    let context = match option(args, "--context") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 5,
+    };
+    let (x, y) = (
+        trace::diff::parse(&fs::read_to_string(a)?),
+        trace::diff::parse(&fs::read_to_string(b)?),
+    );
+    match trace::diff::report((a, b), &x, &y, context) {
+        Some(report) => {
+            print!("{report}");
+            Err("traces differ".into())
+        }
+        None => {
+            println!("traces match over {} instructions", x.len());
+            Ok(())
+        }
+    }
+}
+
+// runs a rom without a window and reports where the cycles went
+fn profile(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom_path = Path::new(args.first().ok_or(USAGE)?);
+    let rom = fs::read(rom_path)?;
+    let cycles: u64 = match option(args, "--cycles") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 100000,
+    };
+    let symbols = match option(args, "--sym") {
+        Some(p) => asm::Symbols::load(Path::new(p))?,
+        None if rom_path.with_extension("sym").exists() => asm::Symbols::load(&rom_path.with_extension("sym"))?,
+        None => asm::Symbols::default(),
+    };
+    let mut debugger = headless(&rom, args, &Arc::new(Mutex::new([0; 2048])))?;
+    let mut profile = prof::Profile::default();
+    while debugger.cycles < cycles {
+        profile.record(&debugger.proc);
+        if let Err(e) = debugger.step() {
+            eprintln!("{e} at {:#05x}", debugger.proc.pc);
+            break;
+        }
+    }
+    print!("{}", profile.report(&debugger.proc, &symbols));
+    if let Some(p) = option(args, "--listing") {
+        fs::write(p, profile.listing(&debugger.proc, rom.len(), &symbols))?;
+    }
+    if let Some(p) = option(args, "--folded") {
+        fs::write(p, profile.folded(&symbols))?;
+    }
+    Ok(())
+}
+
+// runs a rom without a window, optionally pressing keys from a script, and reports which bytes were touched
+fn coverage(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom_path = Path::new(args.first().ok_or(USAGE)?);
+    let rom = fs::read(rom_path)?;
+    let cycles: u64 = match option(args, "--cycles") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 100000,
+    };
+    let symbols = match option(args, "--sym") {
+        Some(p) => asm::Symbols::load(Path::new(p))?,
+        None if rom_path.with_extension("sym").exists() => asm::Symbols::load(&rom_path.with_extension("sym"))?,
+        None => asm::Symbols::default(),
+    };
+    let inputs = match option(args, "--keys") {
+        Some(p) => cov::parse_inputs(&fs::read_to_string(p)?)?,
+        None => Vec::new(),
+    };
+    let mut debugger = headless(&rom, args, &Arc::new(Mutex::new([0; 2048])))?;
+    debugger.proc.memory.coverage = Some(Box::new([0; 4096]));
+    let mut inputs = inputs.into_iter().peekable();
+    while debugger.cycles < cycles {
+        while let Some((_, keypad)) = inputs.next_if(|(cycle, _)| *cycle <= debugger.cycles) {
+            debugger.proc.keypad = keypad;
+        }
+        if let Err(e) = debugger.step() {
+            eprintln!("{e} at {:#05x}", debugger.proc.pc);
+            break;
+        }
+    }
+
+    let coverage = cov::Coverage::new(&rom, &debugger.proc.memory.coverage.unwrap()[..]);
+    let report = coverage.text(&symbols);
+    match option(args, "-o") {
+        Some(p) => fs::write(p, report)?,
+        None => print!("{report}"),
+    }
+    if let Some(p) = option(args, "--lcov") {
+        let map = option(args, "--map")
+            .map(|m| asm::SourceMap::load(Path::new(m)))
+            .transpose()?;
+        fs::write(p, coverage.lcov(&rom_path.display().to_string(), map.as_ref()))?;
+    }
+    Ok(())
+}
+
+// the sprite size and sprites per row picked on the command line
+fn sprite_layout(args: &[String]) -> Result<(sprite::Layout, usize), Box<dyn Error>> {
+    let layout = match option(args, "--layout") {
+        Some(l) => sprite::Layout::parse(l).ok_or(USAGE)?,
+        None => sprite::Layout::Chip8 { height: 8 },
+    };
+    let columns = match option(args, "--columns") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 32,
+    };
+    Ok((layout, columns))
+}
+
+// renders memory as sprites into a png, the rom's own bytes by default
+fn export_sprites(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let out = option(args, "-o").ok_or(USAGE)?;
+    let range = match option(args, "--range") {
+        Some(r) => trace::Filter::parse_range(r).ok_or(USAGE)?,
+        None => 512..512 + rom.len() as u64,
+    };
+    let (layout, columns) = sprite_layout(args)?;
+    let scale = match option(args, "--scale") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 4,
+    };
+    let mut debugger = headless(&rom, args, &Arc::new(Mutex::new([0; 2048])))?;
+    // after running for a while, the sprite I points to is highlighted
+    let i = match option(args, "--cycles") {
+        Some(n) => {
+            let cycles: u64 = n.parse().map_err(|_| USAGE)?;
+            while debugger.cycles < cycles && debugger.step().is_ok() {}
+            Some(debugger.proc.i)
+        }
+        None => None,
+    };
+    let range = range.start as usize..range.end as usize;
+    let sheet = sprite::Sheet::new(&debugger.proc.memory.mem, range, layout, columns, i);
+    sheet.write_png(io::BufWriter::new(fs::File::create(out)?), scale)?;
+    Ok(())
+}
+
+// the built-in themes, joined by a theme file given with --theme, and the one to start with
+fn themes(args: &[String]) -> Result<(Vec<disp::theme::Theme>, usize), Box<dyn Error>> {
+    let mut themes = disp::theme::builtin();
+    let theme = match option(args, "--theme") {
+        Some(t) => match themes.iter().position(|theme| theme.name == t) {
+            Some(index) => index,
+            None => {
+                themes.push(disp::theme::Theme::parse(&fs::read_to_string(t)?).map_err(|e| format!("{t}: {e}"))?);
+                themes.len() - 1
+            }
+        },
+        None => 0,
+    };
+    Ok((themes, theme))
+}
+
+// runs a rom headless and saves the display at the end
+fn screenshot(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let out = Path::new(option(args, "-o").ok_or(USAGE)?);
+    let format = match option(args, "--format") {
+        Some(f) => shot::Format::parse(f).ok_or(USAGE)?,
+        None => shot::Format::from_path(out).unwrap_or(shot::Format::Png),
+    };
+    // text formats default to a character per pixel
+    let scale = match option(args, "--scale") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None if format == shot::Format::Png => 8,
+        None => 1,
+    };
+    let cycles: u64 = match option(args, "--cycles") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 100000,
+    };
+    let inputs = match option(args, "--keys") {
+        Some(p) => cov::parse_inputs(&fs::read_to_string(p)?)?,
+        None => Vec::new(),
+    };
+    let (themes, theme) = themes(args)?;
+    let display_buffer = Arc::new(Mutex::new([0; 2048]));
+    let mut debugger = headless(&rom, args, &display_buffer)?;
+    let mut inputs = inputs.into_iter().peekable();
+    while debugger.cycles < cycles {
+        while let Some((_, keypad)) = inputs.next_if(|(cycle, _)| *cycle <= debugger.cycles) {
+            debugger.proc.keypad = keypad;
+        }
+        if let Err(e) = debugger.step() {
+            eprintln!("{e} at {:#05x}", debugger.proc.pc);
+            break;
+        }
+    }
+
+    let pixels = *display_buffer.lock().unwrap();
+    let shot = shot::Screenshot {
+        pixels: &pixels,
+        width: 64,
+        height: 32,
+        theme: &themes[theme],
+    };
+    shot.write(io::BufWriter::new(fs::File::create(out)?), format, scale)?;
+    Ok(())
+}
+
+// runs a rom headless and records the display every frame
+fn record(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.first().ok_or(USAGE)?)?;
+    let out = Path::new(option(args, "-o").ok_or(USAGE)?);
+    let frames: usize = match option(args, "--frames") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 600,
+    };
+    let scale = match option(args, "--scale") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 4,
+    };
+    let inputs = match option(args, "--keys") {
+        Some(p) => cov::parse_inputs(&fs::read_to_string(p)?)?,
+        None => Vec::new(),
+    };
+    let (themes, theme) = themes(args)?;
+    let display_buffer = Arc::new(Mutex::new([0; 2048]));
+    let mut debugger = headless(&rom, args, &display_buffer)?;
+    let mut recorder = rec::Recorder::create(out, 64, 32, scale, &themes[theme])?;
+    let mut inputs = inputs.into_iter().peekable();
+    'frames: while recorder.frames() < frames {
+        for _ in 0..dbg::INSTRUCTIONS_PER_FRAME {
+            while let Some((_, keypad)) = inputs.next_if(|(cycle, _)| *cycle <= debugger.cycles) {
+                debugger.proc.keypad = keypad;
+            }
+            if let Err(e) = debugger.step() {
+                eprintln!("{e} at {:#05x}", debugger.proc.pc);
+                break 'frames;
+            }
+        }
+        recorder.frame(&*display_buffer.lock().unwrap())?;
+    }
+    recorder.finish()?;
+    Ok(())
+}
+
+// F12 in a window saves the display in the working directory
+fn save_screenshot(display: &disp::Display) {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = PathBuf::from(format!("chip8-{millis}.png"));
+    match display.screenshot(&path) {
+        Ok(()) => eprintln!("saved {}", path.display()),
+        Err(e) => eprintln!("screenshot: {e}"),
+    }
+}
+
+// F9 in a window starts recording to the working directory, or stops and saves the recording
+fn toggle_recording(display: &mut disp::Display) {
+    if display.recording() {
+        stop_recording(display);
+        return;
+    }
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = PathBuf::from(format!("chip8-{millis}.gif"));
+    match display.start_recording(&path) {
+        Ok(()) => eprintln!("recording to {}", path.display()),
+        Err(e) => eprintln!("record: {e}"),
+    }
+}
+
+fn stop_recording(display: &mut disp::Display) {
+    match display.stop_recording() {
+        Ok(Some(frames)) => eprintln!("saved {frames} frames"),
+        Ok(None) => {}
+        Err(e) => eprintln!("record: {e}"),
+    }
+}
+
+fn debug(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rom_path = Path::new(args.first().ok_or(USAGE)?);
+    let rom = fs::read(rom_path)?;
+    // symbols written next to the rom by the assembler are picked up automatically
+    let symbols = match option(args, "--sym") {
+        Some(p) => asm::Symbols::load(Path::new(p))?,
+        None if rom_path.with_extension("sym").exists() => asm::Symbols::load(&rom_path.with_extension("sym"))?,
+        None => asm::Symbols::default(),
+    };
+    let display_buffer: Arc<Mutex<[u8; 2048]>> = Arc::new(Mutex::new([0; 2048]));
+    let mut debugger = dbg::Debugger::new(&rom, symbols, Arc::clone(&display_buffer))?;
+    if let Some(name) = option(args, "--quirks") {
+        debugger.proc.quirks = lint::quirks(name)?;
+    }
+    if let Some(port) = option(args, "--gdb") {
+        dbg::gdb::listen(&mut debugger, port.parse().map_err(|_| USAGE)?)?;
+        return Ok(());
+    }
+    let mut repl = dbg::repl::Repl::new(debugger);
+    if !args.iter().any(|a| a == "--window") {
+        repl.run();
+        return Ok(());
+    }
+
+    let keys = Arc::new(Mutex::new([false; 16]));
+    let interrupt = Arc::new(AtomicBool::new(false));
+    repl.attach(Arc::clone(&keys), Arc::clone(&interrupt));
+    let heat = Arc::new(Mutex::new(vec![[0; 3]; 4096]));
+    let heatmap = args.iter().any(|a| a == "--heatmap");
+    if heatmap {
+        repl.attach_heatmap(Arc::clone(&heat));
+    }
+    let memory = Arc::new(Mutex::new((vec![0; 4096], 0)));
+    let sprites = match option(args, "--sprites") {
+        Some(r) => Some(trace::Filter::parse_range(r).ok_or(USAGE)?),
+        None => None,
+    };
+    if sprites.is_some() {
+        repl.attach_sprites(Arc::clone(&memory));
+    }
+    let (layout, columns) = sprite_layout(args)?;
+    let mut filter = match option(args, "--filter") {
+        Some(f) => disp::filter::Filter::parse(f).ok_or(USAGE)?,
+        None => disp::filter::Filter::None,
+    };
+    let mut scaling = match option(args, "--scaling") {
+        Some(s) => disp::scale::Scaling::parse(s).ok_or(USAGE)?,
+        None => disp::scale::Scaling::Integer,
+    };
+    let mut fullscreen = args.iter().any(|a| a == "--fullscreen");
+    let (themes, mut theme) = themes(args)?;
+    let mut shoot = false;
+    let mut record = false;
+    let status = Arc::new(Mutex::new(disp::overlay::Status::default()));
+    repl.attach_overlay(Arc::clone(&status));
+    let provenance = Arc::new(Mutex::new(proc::Provenance::default()));
+    let show_provenance = args.iter().any(|a| a == "--provenance");
+    if show_provenance {
+        repl.attach_provenance(Arc::clone(&provenance));
+    }
+    let console = thread::spawn(move || repl.run());
+
+    let mut display = disp::Display::new(12, display_buffer);
+    display.set_overlay(status);
+    if show_provenance {
+        display.set_provenance(provenance);
+    }
+    if let Some(path) = option(args, "--record") {
+        display.set_theme(&themes[theme]);
+        display.start_recording(Path::new(path))?;
+    }
+    let mut overlay_state = false;
+    let mut heatmap = heatmap.then(|| disp::heat::Heatmap::new(&display.video(), 6, heat));
+    let mut sprites = sprites.map(|r| {
+        let range = r.start as usize..r.end as usize;
+        disp::sprites::SpriteViewer::new(&display.video(), 3, memory, range, layout, columns)
+    });
+    let display_id = display.window_id();
+    let mut hover = None;
+    'running: while !console.is_finished() {
+        for event in display.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                }
+                // with the heatmap open, closing either window doesn't quit by itself
+                | Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => interrupt.store(true, Ordering::Relaxed),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => overlay_state = !overlay_state,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => filter = filter.next(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => scaling = scaling.next(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => fullscreen = !fullscreen,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => theme = (theme + 1) % themes.len(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => shoot = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => record = true,
+                Event::MouseMotion { window_id, x, y, .. } if window_id == display_id => hover = Some((x, y)),
+                Event::MouseButtonDown { window_id, x, y, .. } => {
+                    if let Some(viewer) = sprites.as_mut().filter(|v| v.window_id() == window_id) {
+                        viewer.click(x, y);
+                    }
+                }
+                Event::KeyDown { keycode: Some(k), .. } | Event::KeyUp { keycode: Some(k), .. } => {
+                    if let Some(index) = disp::keypad_index(k) {
+                        keys.lock().unwrap()[index] = matches!(event, Event::KeyDown { .. });
+                    }
+                }
+                _ => {}
+            }
+        }
+        display.set_show_overlay(overlay_state);
+        display.set_filter(filter);
+        display.set_scaling(scaling);
+        display.set_fullscreen(fullscreen);
+        display.set_theme(&themes[theme]);
+        if let Some((x, y)) = hover {
+            display.hover(x, y);
+        }
+        display.update();
+        if shoot {
+            save_screenshot(&display);
+            shoot = false;
+        }
+        if record {
+            toggle_recording(&mut display);
+            record = false;
+        }
+        if let Some(heatmap) = &mut heatmap {
+            heatmap.update();
+        }
+        if let Some(viewer) = &mut sprites {
+            viewer.update();
+        }
+
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    }
+    stop_recording(&mut display);
+    Ok(())
+}
+
 pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("asm") => assemble(&args[1..], false),
+        Some("octo") => assemble(&args[1..], true),
+        Some("decomp") => decompile(&args[1..]),
+        Some("flow") => analyze(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        Some("dbg") => debug(&args[1..]),
+        Some("trace") => record_trace(&args[1..]),
+        Some("tracediff") => diff_traces(&args[1..]),
+        Some("profile") => profile(&args[1..]),
+        Some("coverage") => coverage(&args[1..]),
+        Some("sprites") => export_sprites(&args[1..]),
+        Some("screenshot") => screenshot(&args[1..]),
+        Some("record") => record(&args[1..]),
+        // the program to debug comes with the client's launch request
+        Some("dap") => dbg::dap::serve(io::stdin(), io::stdout()).map_err(|e| e.into()),
+        Some(a) if !a.starts_with("--") => Err(USAGE.into()),
+        _ => tracer(&args).map(run),
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+// the sinks and filters picked on the command line, the ring is dumped when execution fails
+fn tracer(args: &[String]) -> Result<trace::Tracer, Box<dyn Error>> {
+    let capacity = match option(args, "--ring") {
+        Some(n) => n.parse().map_err(|_| USAGE)?,
+        None => 32,
+    };
+    let mut tracer = trace::Tracer::new(capacity);
+    if let Some(format) = option(args, "--trace") {
+        let out: Box<dyn io::Write + Send> = match option(args, "--trace-out") {
+            Some(p) => Box::new(fs::File::create(p)?),
+            None => Box::new(io::stdout()),
+        };
+        tracer.sinks.push(trace::sink(format, out)?.ok_or(USAGE)?);
+    }
+    let range = |flag| option(args, flag).map(|r| trace::Filter::parse_range(r).ok_or(USAGE));
+    tracer.filter.pc = range("--trace-pc").transpose()?.map(|r| r.start as u16..r.end as u16);
+    tracer.filter.frames = range("--trace-frames").transpose()?;
+    tracer.filter.classes = option(args, "--trace-class")
+        .map(|c| trace::Filter::parse_classes(c).ok_or(USAGE))
+        .transpose()?;
+    tracer.check()?;
+    Ok(tracer)
+}
+
+fn run(mut tracer: trace::Tracer) {
     let pause: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let pause_2: Arc<Mutex<bool>> = Arc::clone(&pause);
     let display_buffer: Arc<Mutex<[u8; 2048]>> = Arc::new(Mutex::new([0; 2048]));
     let display_buffer_2 = Arc::clone(&display_buffer);
+    let status = Arc::new(Mutex::new(disp::overlay::Status::default()));
+    let status_2 = Arc::clone(&status);
 
     let _ = thread::spawn(move || {
         let mut memory = mem::Memory::new();
@@ -53,23 +716,35 @@ pub fn main() {
             .unwrap();
 
         let mut proc = proc::Processor::new(memory, display_buffer_2);
+        let timer_period = Duration::new(0, 1_000_000_000u32 / 60);
+        let mut last_tick = Instant::now();
+        let (mut cycle, mut frame) = (0, 0);
         loop {
             if !*pause_2.lock().unwrap() {
-                let r = proc.execute();
-                match r {
-                    Ok(d) => {
-                        println!(
-                            "(PC:{}, SP:{}, I:{}) {:?}, {:?}",
-                            proc.pc, proc.sp, proc.i, proc.current_instruction, d
-                        );
-                        println!("{:?}", proc.registers)
+                while last_tick.elapsed() >= timer_period {
+                    proc.tick_timers();
+                    last_tick += timer_period;
+                    frame += 1;
+                }
+                let pc = proc.pc;
+                match proc.execute() {
+                    Ok(_) => {
+                        if let Err(e) = tracer.record(trace::Record::capture(&proc, pc, cycle, frame)) {
+                            eprintln!("trace: {e}");
+                            tracer.sinks.clear();
+                        }
+                        cycle += 1;
+                        *status_2.lock().unwrap() = disp::overlay::Status::capture(&proc, cycle);
                     }
                     Err(e) => {
-                        println!("{e}");
+                        eprintln!("{e} at {pc:#05x}, after:");
+                        let _ = tracer.dump(&mut io::stderr());
                         break;
                     }
                 };
-                println!();
+            } else {
+                // timers don't run while paused
+                last_tick = Instant::now();
             }
 
             ::std::thread::sleep(Duration::new(0, 500_000_000u32));
@@ -77,7 +752,16 @@ pub fn main() {
     });
 
     let mut display = disp::Display::new(23, display_buffer);
+    display.set_overlay(status);
     let mut grid_state: bool = false;
+    let mut overlay_state: bool = false;
+    let mut filter = disp::filter::Filter::None;
+    let mut scaling = disp::scale::Scaling::Integer;
+    let mut fullscreen: bool = false;
+    let themes = disp::theme::builtin();
+    let mut theme = 0;
+    let mut shoot = false;
+    let mut record = false;
 
     'running: loop {
         for event in display.event_pump.poll_iter() {
@@ -100,13 +784,55 @@ pub fn main() {
                 } => {
                     grid_state = !grid_state;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => overlay_state = !overlay_state,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => filter = filter.next(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => scaling = scaling.next(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => fullscreen = !fullscreen,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => theme = (theme + 1) % themes.len(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => shoot = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => record = true,
                 _ => {}
             }
         }
         display.set_pause(*pause.lock().unwrap());
         display.set_grid(grid_state);
+        display.set_show_overlay(overlay_state);
+        display.set_filter(filter);
+        display.set_scaling(scaling);
+        display.set_fullscreen(fullscreen);
+        display.set_theme(&themes[theme]);
         display.update();
+        if shoot {
+            save_screenshot(&display);
+            shoot = false;
+        }
+        if record {
+            toggle_recording(&mut display);
+            record = false;
+        }
 
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
     }
+    stop_recording(&mut display);
 }
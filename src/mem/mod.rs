@@ -6,6 +6,27 @@ use std::io::{self, ErrorKind, Read};
 use std::path::Path;
 
 static MAX_SIZE: usize = 4096;
+// where the hex digit sprites programs draw with are loaded
+pub static FONT_ADDR: usize = 0x50;
+#[rustfmt::skip]
+pub static FONT: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x20, 0x60, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0x90, 0x90, 0xF0, 0x10, 0x10], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x20, 0x40, 0x40], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xE0, 0x90, 0x90, 0x90, 0xE0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
 
 #[derive(Debug, Clone)]
 pub struct MemoryError(usize);
@@ -20,8 +41,57 @@ impl Error for MemoryError {}
 
 pub struct MemorySlice {}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+impl AccessKind {
+    // its bit in the coverage of an address
+    pub fn bit(self) -> u8 {
+        match self {
+            AccessKind::Read => 1,
+            AccessKind::Write => 2,
+            AccessKind::Execute => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub addr: usize,
+    pub kind: AccessKind,
+}
+
+// how often each address was accessed, and which instruction last wrote it
+#[derive(Clone)]
+pub struct Heat {
+    // indexed by AccessKind
+    pub counts: Vec<[u32; 3]>,
+    pub writers: Vec<Option<u16>>,
+    // where the instruction being executed was fetched from
+    pc: u16,
+}
+
+impl Default for Heat {
+    fn default() -> Self {
+        Heat {
+            counts: vec![[0; 3]; MAX_SIZE],
+            writers: vec![None; MAX_SIZE],
+            pc: 0,
+        }
+    }
+}
+
 pub struct Memory {
     pub mem: [u8; MAX_SIZE],
+    // accesses made through read, write and fetch, only recorded while enabled
+    pub log: Option<Vec<Access>>,
+    // the kinds of access seen at each address as AccessKind bits, while enabled
+    pub coverage: Option<Box<[u8; MAX_SIZE]>>,
+    pub heat: Option<Heat>,
 }
 
 impl fmt::Display for Memory {
@@ -42,29 +112,22 @@ impl fmt::Display for Memory {
 }
 impl Memory {
     pub fn new() -> Self {
-        let mut m = Self { mem: [0; 4096] };
+        let mut m = Self {
+            mem: [0; 4096],
+            log: None,
+            coverage: None,
+            heat: None,
+        };
         m.load_fonts();
 
         m
     }
 
     pub fn load_fonts(&mut self) {
-        self.mem[0x50..0x55].copy_from_slice(&[0xF0, 0x90, 0x90, 0x90, 0xF0]); // 0
-        self.mem[0x55..0x5a].copy_from_slice(&[0x20, 0x60, 0x20, 0x20, 0x70]); // 1
-        self.mem[0x5a..0x5f].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x80, 0xF0]); // 2
-        self.mem[0x5f..0x64].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x10, 0xF0]); // 3
-        self.mem[0x64..0x69].copy_from_slice(&[0x90, 0x90, 0xF0, 0x10, 0x10]); // 4
-        self.mem[0x69..0x6e].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x10, 0xF0]); // 5
-        self.mem[0x6e..0x73].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x90, 0xF0]); // 6
-        self.mem[0x73..0x78].copy_from_slice(&[0xF0, 0x10, 0x20, 0x40, 0x40]); // 7
-        self.mem[0x78..0x7d].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x90, 0xF0]); // 8
-        self.mem[0x7d..0x82].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x10, 0xF0]); // 9
-        self.mem[0x82..0x87].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x90, 0x90]); // A
-        self.mem[0x87..0x8c].copy_from_slice(&[0xE0, 0x90, 0xE0, 0x90, 0xE0]); // B
-        self.mem[0x8c..0x91].copy_from_slice(&[0xF0, 0x80, 0x80, 0x80, 0xF0]); // C
-        self.mem[0x91..0x96].copy_from_slice(&[0xE0, 0x90, 0x90, 0x90, 0xE0]); // D
-        self.mem[0x96..0x9b].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x80, 0xF0]); // E
-        self.mem[0x9b..0xa0].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x80, 0x80]); // F
+        for (n, digit) in FONT.iter().enumerate() {
+            let addr = FONT_ADDR + n * 5;
+            self.mem[addr..addr + 5].copy_from_slice(digit);
+        }
     }
 
     pub fn load_rom(&mut self, p: &Path) -> io::Result<()> {
@@ -124,6 +187,47 @@ impl Memory {
         Ok(self.mem[index])
     }
 
+    fn record(&mut self, addr: usize, kind: AccessKind) {
+        if let Some(log) = &mut self.log {
+            log.push(Access { addr, kind });
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage[addr] |= kind.bit();
+        }
+        if let Some(heat) = &mut self.heat {
+            let count = &mut heat.counts[addr][kind as usize];
+            *count = count.saturating_add(1);
+            if kind == AccessKind::Write {
+                heat.writers[addr] = Some(heat.pc);
+            }
+        }
+    }
+
+    // reads a byte on behalf of the program
+    pub fn read(&mut self, index: usize) -> Result<u8, MemoryError> {
+        let b = self.get_byte(index)?;
+        self.record(index, AccessKind::Read);
+        Ok(b)
+    }
+
+    // writes a byte on behalf of the program
+    pub fn write(&mut self, index: usize, b: u8) -> Result<(), MemoryError> {
+        self.set_byte(index, b)?;
+        self.record(index, AccessKind::Write);
+        Ok(())
+    }
+
+    // fetches an instruction
+    pub fn fetch(&mut self, index: usize) -> Result<[u8; 2], MemoryError> {
+        let bs = self.get_word(index)?;
+        if let Some(heat) = &mut self.heat {
+            heat.pc = index as u16;
+        }
+        self.record(index, AccessKind::Execute);
+        self.record(index + 1, AccessKind::Execute);
+        Ok(bs)
+    }
+
     pub fn get_word(&self, index: usize) -> Result<[u8; 2], MemoryError> {
         Memory::in_bounds(index + 1)?;
         let mut bs: [u8; 2] = [0; 2];
@@ -134,7 +238,7 @@ impl Memory {
 }
 
 mod tests {
-    use super::{Memory, MemoryError, MAX_SIZE};
+    use super::{Heat, Memory, MemoryError, MAX_SIZE};
 
     #[test]
     fn test_get_set() {
@@ -158,4 +262,23 @@ mod tests {
         assert!(matches!(mem.get_word(4095), Err(MemoryError(4096))));
         assert!(matches!(mem.set_word(4095, [0, 1]), Err(MemoryError(4096))));
     }
+
+    #[test]
+    fn test_heat() {
+        let mut mem = Memory::new();
+        mem.heat = Some(Heat::default());
+        mem.fetch(0x200).unwrap();
+        mem.write(0x300, 1).unwrap();
+        mem.write(0x300, 2).unwrap();
+        mem.read(0x300).unwrap();
+        mem.fetch(0x202).unwrap();
+        mem.write(0x301, 3).unwrap();
+
+        let heat = mem.heat.unwrap();
+        assert_eq!(heat.counts[0x300], [1, 2, 0]);
+        assert_eq!(heat.counts[0x201], [0, 0, 1]);
+        assert_eq!(heat.writers[0x300], Some(0x200));
+        assert_eq!(heat.writers[0x301], Some(0x202));
+        assert_eq!(heat.writers[0x302], None);
+    }
 }
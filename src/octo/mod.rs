@@ -0,0 +1,1094 @@
+#![allow(dead_code)]
+use crate::asm::{Program, SourceLine, SourceMap, Symbol, SymbolKind, Symbols};
+use crate::inst::Instruction;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+static ORIGIN: usize = 512;
+static MAX_SIZE: usize = 4096;
+
+// SCHIP and XO-CHIP statements that have no counterpart in inst
+static UNSUPPORTED: [&str; 12] = [
+    "hires",
+    "lores",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "exit",
+    "saveflags",
+    "loadflags",
+    "plane",
+    "audio",
+    "pitch",
+];
+
+#[derive(Debug, Clone)]
+pub struct OctoError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+impl Error for OctoError {}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '#' {
+                break;
+            }
+            if chars[i].is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() {
+                i += 1;
+            }
+            tokens.push(Token {
+                text: chars[start..i].iter().collect(),
+                line: n + 1,
+                column: start + 1,
+            });
+        }
+    }
+    tokens.reverse();
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('v').or(text.strip_prefix('V'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    // low 12 bits of the instruction at the address
+    Address,
+    // the two `vN := nn` instructions emitted by :unpack, carrying the nibble in the high bits
+    Unpack(u8),
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+enum Condition {
+    Equal(u8, Operand),
+    NotEqual(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+    Less(u8, Operand),
+    Greater(u8, Operand),
+    LessEqual(u8, Operand),
+    GreaterEqual(u8, Operand),
+}
+
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Self {
+        match self {
+            Condition::Equal(r, o) => Condition::NotEqual(r, o),
+            Condition::NotEqual(r, o) => Condition::Equal(r, o),
+            Condition::Key(r) => Condition::NotKey(r),
+            Condition::NotKey(r) => Condition::Key(r),
+            Condition::Less(r, o) => Condition::GreaterEqual(r, o),
+            Condition::Greater(r, o) => Condition::LessEqual(r, o),
+            Condition::LessEqual(r, o) => Condition::Greater(r, o),
+            Condition::GreaterEqual(r, o) => Condition::Less(r, o),
+        }
+    }
+}
+
+struct Compiler {
+    file: String,
+    tokens: Vec<Token>,
+    last: Token,
+    rom: Vec<Option<u8>>,
+    here: usize,
+    has_main: bool,
+    labels: HashMap<String, usize>,
+    label_order: Vec<String>,
+    constants: HashMap<String, f64>,
+    constant_order: Vec<String>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    // forward references, resolved when the label gets defined
+    protos: HashMap<String, Vec<(usize, Fixup, Token)>>,
+    loops: Vec<(usize, Token)>,
+    // addresses of `while` exits, None marks the start of each enclosing loop
+    whiles: Vec<Option<usize>>,
+    branches: Vec<(usize, Token, &'static str)>,
+    lines: Vec<SourceLine>,
+}
+
+impl Compiler {
+    fn new(file: &str, source: &str) -> Self {
+        let mut aliases = HashMap::new();
+        aliases.insert("unpack-hi".to_string(), 0x0);
+        aliases.insert("unpack-lo".to_string(), 0x1);
+        Compiler {
+            file: file.to_string(),
+            tokens: tokenize(source),
+            last: Token {
+                text: String::new(),
+                line: 1,
+                column: 1,
+            },
+            rom: Vec::new(),
+            here: ORIGIN,
+            has_main: true,
+            labels: HashMap::new(),
+            label_order: Vec::new(),
+            constants: HashMap::new(),
+            constant_order: Vec::new(),
+            aliases,
+            macros: HashMap::new(),
+            protos: HashMap::new(),
+            loops: Vec::new(),
+            whiles: Vec::new(),
+            branches: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    fn error_at(&self, t: &Token, message: String) -> OctoError {
+        OctoError {
+            file: self.file.clone(),
+            line: t.line,
+            column: t.column,
+            message,
+        }
+    }
+
+    fn error(&self, message: String) -> OctoError {
+        self.error_at(&self.last, message)
+    }
+
+    fn next(&mut self) -> Result<String, OctoError> {
+        match self.tokens.pop() {
+            Some(t) => {
+                self.last = t;
+                Ok(self.last.text.clone())
+            }
+            None => Err(self.error("unexpected end of file".to_string())),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), OctoError> {
+        let t = self.next()?;
+        if t != text {
+            return Err(self.error(format!("expected '{text}', got '{t}'")));
+        }
+        Ok(())
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        parse_register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    fn register(&mut self) -> Result<u8, OctoError> {
+        let t = self.next()?;
+        match self.aliases.get(&t) {
+            Some(r) => Ok(*r),
+            None => parse_register(&t).ok_or_else(|| self.error(format!("expected a register, got '{t}'"))),
+        }
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.aliases.contains_key(name)
+            || self.macros.contains_key(name)
+    }
+
+    fn check_name(&mut self, kind: &str) -> Result<String, OctoError> {
+        let name = self.next()?;
+        if parse_register(&name).is_some() || parse_number(&name).is_some() {
+            return Err(self.error(format!("'{name}' is not a valid {kind} name")));
+        }
+        if self.is_defined(&name) {
+            return Err(self.error(format!("the name '{name}' has already been defined")));
+        }
+        Ok(name)
+    }
+
+    // a number or a constant, used where forward references aren't possible
+    fn constant(&self, text: &str) -> Option<f64> {
+        match parse_number(text) {
+            Some(n) => Some(n as f64),
+            None => self.constants.get(text).copied(),
+        }
+    }
+
+    fn ranged_value(&mut self, min: i64, max: i64, what: &str) -> Result<i64, OctoError> {
+        let t = self.next()?;
+        let value = match self.constant(&t) {
+            Some(v) => v.floor() as i64,
+            None => match self.labels.get(&t) {
+                Some(addr) => *addr as i64,
+                None => return Err(self.error(format!("undefined name '{t}'"))),
+            },
+        };
+        if value < min || value > max {
+            return Err(self.error(format!(
+                "argument {value} does not fit in {what}, it must be in the range [{min}, {max}]"
+            )));
+        }
+        Ok(value)
+    }
+
+    fn short_value(&mut self) -> Result<u8, OctoError> {
+        Ok(self.ranged_value(-128, 255, "a byte")? as u8)
+    }
+
+    fn tiny_value(&mut self) -> Result<u8, OctoError> {
+        Ok(self.ranged_value(0, 15, "a nibble")? as u8)
+    }
+
+    // a 12 bit address, labels that aren't defined yet are patched in once they are
+    fn wide_value(&mut self, fixup: Fixup) -> Result<u16, OctoError> {
+        let t = self.next()?;
+        if let Some(v) = self.constant(&t) {
+            let v = v.floor() as i64;
+            if !(0..=0xfff).contains(&v) {
+                return Err(self.error(format!("value {v} does not fit in 12 bits")));
+            }
+            return Ok(v as u16);
+        }
+        if let Some(addr) = self.labels.get(&t) {
+            return Ok(*addr as u16);
+        }
+        if self.is_register(&t) || parse_number(&t).is_some() {
+            return Err(self.error(format!("expected an address, got '{t}'")));
+        }
+        let token = self.last.clone();
+        self.protos.entry(t).or_default().push((self.here, fixup, token));
+        Ok(0)
+    }
+
+    fn emit_byte(&mut self, b: u8) -> Result<(), OctoError> {
+        if self.here >= MAX_SIZE {
+            return Err(self.error("program is larger than 4096 bytes".to_string()));
+        }
+        let index = self.here - ORIGIN;
+        if self.rom.len() <= index {
+            self.rom.resize(index + 1, None);
+        }
+        if self.rom[index].is_some() {
+            return Err(self.error(format!(
+                "data overlap, address {:#05x} has already been defined",
+                self.here
+            )));
+        }
+        self.rom[index] = Some(b);
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, inst: Instruction) -> Result<(), OctoError> {
+        self.lines.push(SourceLine {
+            addr: self.here as u16,
+            file: self.file.clone(),
+            line: self.last.line,
+        });
+        let bs = inst.encode();
+        self.emit_byte(bs[0])?;
+        self.emit_byte(bs[1])
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) {
+        let bs = Instruction::Goto { addr: target as u16 }.encode();
+        self.rom[at - ORIGIN] = Some(bs[0]);
+        self.rom[at + 1 - ORIGIN] = Some(bs[1]);
+    }
+
+    fn resolve(&mut self, name: &str, addr: usize) {
+        for (at, fixup, _) in self.protos.remove(name).unwrap_or_default() {
+            let index = at - ORIGIN;
+            match fixup {
+                Fixup::Address => {
+                    let hi = self.rom[index].unwrap_or(0) & 0xf0;
+                    self.rom[index] = Some(hi | (addr >> 8) as u8);
+                    self.rom[index + 1] = Some(addr as u8);
+                }
+                Fixup::Unpack(nibble) => {
+                    self.rom[index + 1] = Some((nibble << 4) | (addr >> 8) as u8);
+                    self.rom[index + 3] = Some(addr as u8);
+                }
+            }
+        }
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) {
+        self.resolve(&name, addr);
+        self.labels.insert(name.clone(), addr);
+        self.label_order.push(name);
+    }
+
+    fn define_constant(&mut self, name: String, value: f64) {
+        self.constants.insert(name.clone(), value);
+        self.constant_order.push(name);
+    }
+
+    fn compile(&mut self) -> Result<(), OctoError> {
+        // reserve space for the jump to main
+        self.emit(Instruction::NoOp)?;
+        while !self.tokens.is_empty() {
+            let t = self.next()?;
+            self.statement(&t)?;
+        }
+        if self.has_main {
+            let Some(main) = self.labels.get("main").copied() else {
+                return Err(self.error("this program is missing a 'main' label".to_string()));
+            };
+            self.patch_jump(ORIGIN, main);
+        }
+        // the first use in the source, so the same name is reported every time
+        let first = self
+            .protos
+            .values()
+            .flatten()
+            .map(|(_, _, t)| t)
+            .min_by_key(|t| (t.line, t.column));
+        if let Some(t) = first {
+            return Err(self.error_at(t, format!("undefined name '{}'", t.text)));
+        }
+        if let Some((_, t)) = self.loops.first() {
+            return Err(self.error_at(t, "this 'loop' does not have a matching 'again'".to_string()));
+        }
+        if let Some((_, t, kind)) = self.branches.first() {
+            return Err(self.error_at(t, format!("this '{kind}' does not have a matching 'end'")));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, t: &str) -> Result<(), OctoError> {
+        if UNSUPPORTED.contains(&t) {
+            return Err(self.error(format!("'{t}' is not supported by this instruction set")));
+        }
+        match t {
+            ":" => {
+                let name = self.check_name("label")?;
+                if name == "main" && self.here == ORIGIN + 2 && self.rom.len() == 2 {
+                    // main directly follows the reserved jump, so the jump isn't needed
+                    self.has_main = false;
+                    self.rom.clear();
+                    self.lines.clear();
+                    self.here = ORIGIN;
+                }
+                self.define_label(name, self.here);
+            }
+            ":next" => {
+                let name = self.check_name("label")?;
+                self.define_label(name, self.here + 1);
+                let t = self.next()?;
+                self.statement(&t)?;
+            }
+            ":alias" => {
+                let name = self.check_name("alias")?;
+                let r = self.register()?;
+                self.aliases.insert(name, r);
+            }
+            ":const" => {
+                let name = self.check_name("constant")?;
+                let t = self.next()?;
+                let value = match self.constant(&t) {
+                    Some(v) => v,
+                    None => match self.labels.get(&t) {
+                        Some(addr) => *addr as f64,
+                        None => return Err(self.error(format!("undefined name '{t}'"))),
+                    },
+                };
+                self.define_constant(name, value);
+            }
+            ":calc" => {
+                let name = self.check_name("constant")?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.expect("}")?;
+                self.define_constant(name, value);
+            }
+            ":byte" => {
+                let b = if self.peek() == Some("{") {
+                    self.next()?;
+                    let v = self.calc()?;
+                    self.expect("}")?;
+                    v.floor() as i64 as u8
+                } else {
+                    self.short_value()?
+                };
+                self.emit_byte(b)?;
+            }
+            ":org" => {
+                let t = self.next()?;
+                let addr = self.constant(&t).map(|v| v.floor() as i64);
+                match addr {
+                    Some(a) if (ORIGIN as i64..MAX_SIZE as i64).contains(&a) => self.here = a as usize,
+                    _ => return Err(self.error(format!("invalid :org address '{t}'"))),
+                }
+            }
+            ":unpack" => {
+                let nibble = self.tiny_value()?;
+                let hi = self.aliases["unpack-hi"];
+                let lo = self.aliases["unpack-lo"];
+                let addr = self.wide_value(Fixup::Unpack(nibble))?;
+                self.emit(Instruction::SetRegister {
+                    register: hi,
+                    value: (nibble << 4) | (addr >> 8) as u8,
+                })?;
+                self.emit(Instruction::SetRegister {
+                    register: lo,
+                    value: addr as u8,
+                })?;
+            }
+            ":call" => {
+                let addr = self.wide_value(Fixup::Address)?;
+                self.emit(Instruction::CallSubroutine { addr })?;
+            }
+            ":macro" => self.define_macro()?,
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ";" | "return" => self.emit(Instruction::Return)?,
+            "clear" => self.emit(Instruction::ClearDisplay)?,
+            "bcd" => {
+                let register = self.register()?;
+                self.emit(Instruction::StoreBcd { register })?;
+            }
+            "save" | "load" => {
+                let end_register = self.register()?;
+                if self.peek() == Some("-") {
+                    return Err(self.error(format!("ranged '{t}' is not supported by this instruction set")));
+                }
+                self.emit(match t {
+                    "save" => Instruction::DumpRegisters { end_register },
+                    _ => Instruction::LoadRegisters { end_register },
+                })?;
+            }
+            "sprite" => {
+                let reg_x = self.register()?;
+                let reg_y = self.register()?;
+                let sprite_height = self.tiny_value()?;
+                self.emit(Instruction::Draw {
+                    reg_x,
+                    reg_y,
+                    sprite_height,
+                })?;
+            }
+            "jump" => {
+                let addr = self.wide_value(Fixup::Address)?;
+                self.emit(Instruction::Goto { addr })?;
+            }
+            "jump0" => {
+                let addr = self.wide_value(Fixup::Address)?;
+                self.emit(Instruction::GotoPlusV0 { addr })?;
+            }
+            "native" => {
+                let addr = self.wide_value(Fixup::Address)?;
+                self.emit(Instruction::Call { addr })?;
+            }
+            "if" => {
+                let cond = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit_condition(cond)?,
+                    "begin" => {
+                        self.emit_condition(cond.negate())?;
+                        self.branches.push((self.here, self.last.clone(), "begin"));
+                        self.emit(Instruction::NoOp)?;
+                    }
+                    other => return Err(self.error(format!("expected 'then' or 'begin', got '{other}'"))),
+                }
+            }
+            "else" => {
+                let Some((at, _, "begin")) = self.branches.pop() else {
+                    return Err(self.error("this 'else' does not have a matching 'begin'".to_string()));
+                };
+                self.branches.push((self.here, self.last.clone(), "else"));
+                self.emit(Instruction::NoOp)?;
+                self.patch_jump(at, self.here);
+            }
+            "end" => {
+                let Some((at, _, _)) = self.branches.pop() else {
+                    return Err(self.error("this 'end' does not have a matching 'begin'".to_string()));
+                };
+                self.patch_jump(at, self.here);
+            }
+            "loop" => {
+                self.loops.push((self.here, self.last.clone()));
+                self.whiles.push(None);
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("this 'while' is not within a loop".to_string()));
+                }
+                let cond = self.condition()?;
+                self.emit_condition(cond.negate())?;
+                self.whiles.push(Some(self.here));
+                self.emit(Instruction::NoOp)?;
+            }
+            "again" => {
+                let Some((start, _)) = self.loops.pop() else {
+                    return Err(self.error("this 'again' does not have a matching 'loop'".to_string()));
+                };
+                self.emit(Instruction::Goto { addr: start as u16 })?;
+                while let Some(Some(at)) = self.whiles.pop() {
+                    self.patch_jump(at, self.here);
+                }
+            }
+            "i" => self.i_assignment()?,
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let register = self.register()?;
+                self.emit(match t {
+                    "delay" => Instruction::SetDelayTimer { register },
+                    _ => Instruction::SetSoundTimer { register },
+                })?;
+            }
+            _ if self.is_register(t) => {
+                let r = match self.aliases.get(t) {
+                    Some(r) => *r,
+                    None => parse_register(t).unwrap(),
+                };
+                self.v_assignment(r)?;
+            }
+            _ if self.macros.contains_key(t) => self.expand_macro(t)?,
+            _ if self.constant(t).is_some() => {
+                // bare numbers and constants are data
+                self.tokens.push(self.last.clone());
+                let b = self.short_value()?;
+                self.emit_byte(b)?;
+            }
+            _ if t.starts_with(':') || matches!(t, "then" | "begin" | "{" | "}") => {
+                return Err(self.error(format!("unexpected '{t}'")))
+            }
+            _ => {
+                // anything else names a subroutine to call
+                self.tokens.push(self.last.clone());
+                let addr = self.wide_value(Fixup::Address)?;
+                self.emit(Instruction::CallSubroutine { addr })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn i_assignment(&mut self) -> Result<(), OctoError> {
+        match self.next()?.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let register = self.register()?;
+                    self.emit(Instruction::SetIToFontSprite { register })
+                }
+                Some(t @ ("bighex" | "long")) => {
+                    let t = t.to_string();
+                    self.next()?;
+                    Err(self.error(format!("'i := {t}' is not supported by this instruction set")))
+                }
+                _ => {
+                    let addr = self.wide_value(Fixup::Address)?;
+                    self.emit(Instruction::SetI { addr })
+                }
+            },
+            "+=" => {
+                let register = self.register()?;
+                self.emit(Instruction::AddToI { register })
+            }
+            op => Err(self.error(format!("'{op}' is not an operator that can target the i register"))),
+        }
+    }
+
+    fn v_assignment(&mut self, x: u8) -> Result<(), OctoError> {
+        let op = self.next()?;
+        let operand_is_register = self.peek().map(|t| self.is_register(t)).unwrap_or(false);
+        let inst = match op.as_str() {
+            ":=" if operand_is_register => Instruction::CopyRegister {
+                src_register: self.register()?,
+                dst_register: x,
+            },
+            ":=" => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    Instruction::SetRegisterRandomBitwiseAnd {
+                        register: x,
+                        and_operand: self.short_value()?,
+                    }
+                }
+                Some("key") => {
+                    self.next()?;
+                    Instruction::GetKey { register: x }
+                }
+                Some("delay") => {
+                    self.next()?;
+                    Instruction::GetDelayTimer { register: x }
+                }
+                _ => Instruction::SetRegister {
+                    register: x,
+                    value: self.short_value()?,
+                },
+            },
+            "+=" if !operand_is_register => Instruction::AddToRegister {
+                register: x,
+                value: self.short_value()?,
+            },
+            "-=" if !operand_is_register => Instruction::AddToRegister {
+                register: x,
+                value: (self.short_value()? as i8).wrapping_neg() as u8,
+            },
+            "+=" | "-=" | "=-" | "|=" | "&=" | "^=" | ">>=" | "<<=" => {
+                let value_register = x;
+                let operand_register = self.register()?;
+                match op.as_str() {
+                    "+=" => Instruction::AddRegisters {
+                        value_register,
+                        operand_register,
+                    },
+                    "-=" => Instruction::SubtractRegisters {
+                        value_register,
+                        operand_register,
+                    },
+                    "=-" => Instruction::SubtractRegistersReversed {
+                        value_register,
+                        operand_register,
+                    },
+                    "|=" => Instruction::ApplyBitwiseOr {
+                        value_register,
+                        operand_register,
+                    },
+                    "&=" => Instruction::ApplyBitwiseAnd {
+                        value_register,
+                        operand_register,
+                    },
+                    "^=" => Instruction::ApplyBitwiseXor {
+                        value_register,
+                        operand_register,
+                    },
+                    ">>=" => Instruction::ShiftRight {
+                        value_register,
+                        operand_register,
+                    },
+                    _ => Instruction::ShiftLeft {
+                        value_register,
+                        operand_register,
+                    },
+                }
+            }
+            _ => return Err(self.error(format!("unrecognized operator '{op}'"))),
+        };
+        self.emit(inst)
+    }
+
+    fn condition(&mut self) -> Result<Condition, OctoError> {
+        let r = self.register()?;
+        let op = self.next()?;
+        let mut operand = || -> Result<Operand, OctoError> {
+            if self.peek().map(|t| self.is_register(t)).unwrap_or(false) {
+                Ok(Operand::Register(self.register()?))
+            } else {
+                Ok(Operand::Byte(self.short_value()?))
+            }
+        };
+        Ok(match op.as_str() {
+            "==" => Condition::Equal(r, operand()?),
+            "!=" => Condition::NotEqual(r, operand()?),
+            "key" => Condition::Key(r),
+            "-key" => Condition::NotKey(r),
+            "<" => Condition::Less(r, operand()?),
+            ">" => Condition::Greater(r, operand()?),
+            "<=" => Condition::LessEqual(r, operand()?),
+            ">=" => Condition::GreaterEqual(r, operand()?),
+            _ => return Err(self.error(format!("expected a conditional operator, got '{op}'"))),
+        })
+    }
+
+    // emits a skip so that the following instruction only runs when cond holds
+    fn emit_condition(&mut self, cond: Condition) -> Result<(), OctoError> {
+        let compare = |c: &mut Self, r: u8, o: Operand, reversed: bool, skip_if_zero: bool| {
+            // vf := operand, vf -= r (or vf =- r), then test the no-borrow flag
+            c.emit(match o {
+                Operand::Register(y) => Instruction::CopyRegister {
+                    src_register: y,
+                    dst_register: 0xf,
+                },
+                Operand::Byte(b) => Instruction::SetRegister {
+                    register: 0xf,
+                    value: b,
+                },
+            })?;
+            c.emit(match reversed {
+                false => Instruction::SubtractRegisters {
+                    value_register: 0xf,
+                    operand_register: r,
+                },
+                true => Instruction::SubtractRegistersReversed {
+                    value_register: 0xf,
+                    operand_register: r,
+                },
+            })?;
+            // compared against 1 rather than 0, as Octo does, so the bytes match
+            c.emit(match skip_if_zero {
+                true => Instruction::SkipIfRegisterNotEquals {
+                    register: 0xf,
+                    value: 1,
+                },
+                false => Instruction::SkipIfRegisterEquals {
+                    register: 0xf,
+                    value: 1,
+                },
+            })
+        };
+        match cond {
+            Condition::Equal(r, Operand::Register(y)) => self.emit(Instruction::SkipIfRegistersNotEqual {
+                register_1: r,
+                register_2: y,
+            }),
+            Condition::Equal(r, Operand::Byte(b)) => {
+                self.emit(Instruction::SkipIfRegisterNotEquals { register: r, value: b })
+            }
+            Condition::NotEqual(r, Operand::Register(y)) => self.emit(Instruction::SkipIfRegistersEqual {
+                register_1: r,
+                register_2: y,
+            }),
+            Condition::NotEqual(r, Operand::Byte(b)) => {
+                self.emit(Instruction::SkipIfRegisterEquals { register: r, value: b })
+            }
+            Condition::Key(r) => self.emit(Instruction::SkipIfKeyNotPressed { register: r }),
+            Condition::NotKey(r) => self.emit(Instruction::SkipIfKeyPressed { register: r }),
+            Condition::Greater(r, o) => compare(self, r, o, false, false),
+            Condition::Less(r, o) => compare(self, r, o, true, false),
+            Condition::GreaterEqual(r, o) => compare(self, r, o, true, true),
+            Condition::LessEqual(r, o) => compare(self, r, o, false, true),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name = self.check_name("macro")?;
+        let mut args = Vec::new();
+        while self.peek().is_some() && self.peek() != Some("{") {
+            args.push(self.next()?);
+        }
+        self.expect("{")?;
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            self.next()?;
+            match self.last.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(self.last.clone());
+        }
+        self.macros.insert(name, Macro { args, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), OctoError> {
+        let m = &self.macros[name];
+        let arg_names = m.args.clone();
+        let body = m.body.clone();
+        let mut bindings = HashMap::new();
+        for arg in arg_names {
+            bindings.insert(arg, self.next()?);
+        }
+        for t in body.into_iter().rev() {
+            let text = bindings.get(&t.text).cloned().unwrap_or(t.text);
+            self.tokens.push(Token { text, ..t });
+        }
+        Ok(())
+    }
+
+    // :calc expressions have no precedence and evaluate right to left
+    fn calc(&mut self) -> Result<f64, OctoError> {
+        let lhs = self.calc_terminal()?;
+        if matches!(self.peek(), Some("}") | Some(")") | None) {
+            return Ok(lhs);
+        }
+        let op = self.next()?;
+        let rhs = self.calc()?;
+        let (a, b) = (lhs as i64, rhs as i64);
+        Ok(match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.wrapping_shl(b as u32) as f64,
+            ">>" => a.wrapping_shr(b as u32) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => (lhs < rhs) as i64 as f64,
+            "<=" => (lhs <= rhs) as i64 as f64,
+            "==" => (lhs == rhs) as i64 as f64,
+            "!=" => (lhs != rhs) as i64 as f64,
+            ">=" => (lhs >= rhs) as i64 as f64,
+            ">" => (lhs > rhs) as i64 as f64,
+            _ => return Err(self.error(format!("unknown operator '{op}' in :calc"))),
+        })
+    }
+
+    fn calc_terminal(&mut self) -> Result<f64, OctoError> {
+        let t = self.next()?;
+        let unary = |c: &mut Self, f: fn(f64) -> f64| c.calc_terminal().map(f);
+        match t.as_str() {
+            "(" => {
+                let v = self.calc()?;
+                self.expect(")")?;
+                Ok(v)
+            }
+            "-" => unary(self, |v| -v),
+            "~" => unary(self, |v| !(v as i64) as f64),
+            "!" => unary(self, |v| (v == 0.0) as i64 as f64),
+            "abs" => unary(self, f64::abs),
+            "sqrt" => unary(self, f64::sqrt),
+            "sin" => unary(self, f64::sin),
+            "cos" => unary(self, f64::cos),
+            "tan" => unary(self, f64::tan),
+            "exp" => unary(self, f64::exp),
+            "log" => unary(self, f64::ln),
+            "sign" => unary(self, |v| if v == 0.0 { 0.0 } else { v.signum() }),
+            "ceil" => unary(self, f64::ceil),
+            "floor" => unary(self, f64::floor),
+            "@" => {
+                let addr = self.calc_terminal()? as i64 as usize;
+                let b = addr
+                    .checked_sub(ORIGIN)
+                    .and_then(|i| self.rom.get(i).copied().flatten())
+                    .unwrap_or(0);
+                Ok(b as f64)
+            }
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            "HERE" => Ok(self.here as f64),
+            _ => match self.constant(&t) {
+                Some(v) => Ok(v),
+                None => match self.labels.get(&t) {
+                    Some(addr) => Ok(*addr as f64),
+                    None => Err(self.error(format!("undefined name '{t}' in :calc"))),
+                },
+            },
+        }
+    }
+
+    fn program(&self) -> Program {
+        let mut symbols: Vec<Symbol> = Vec::new();
+        for name in self.label_order.iter() {
+            symbols.push(Symbol {
+                name: name.clone(),
+                value: self.labels[name] as u16,
+                kind: SymbolKind::Label,
+            });
+        }
+        for name in self.constant_order.iter() {
+            symbols.push(Symbol {
+                name: name.clone(),
+                value: self.constants[name].floor() as i64 as u16,
+                kind: SymbolKind::Constant,
+            });
+        }
+        Program {
+            origin: ORIGIN,
+            bytes: self.rom.iter().map(|b| b.unwrap_or(0)).collect(),
+            symbols: Symbols { symbols },
+            lines: SourceMap {
+                lines: self.lines.clone(),
+            },
+        }
+    }
+}
+
+pub fn compile_source(name: &str, source: &str) -> Result<Program, OctoError> {
+    let mut compiler = Compiler::new(name, source);
+    compiler.compile()?;
+    Ok(compiler.program())
+}
+
+pub fn compile(p: &Path) -> Result<Program, Box<dyn Error>> {
+    let source = fs::read_to_string(p)?;
+    Ok(compile_source(&p.display().to_string(), &source)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let source = "
+            :const SPEED 2
+            :alias x v1
+            :calc HALF { ( 64 / 2 ) - 4 }
+            : main
+                clear
+                i := sprite
+                x := HALF
+                loop
+                    x += SPEED
+                    if x == 60 then x := 0
+                    sprite x v2 3
+                    while x != 30
+                    draw-more
+                again
+                if v3 > 5 begin
+                    v4 := random 0xf
+                else
+                    v4 -= 1
+                end
+            : draw-more
+                v2 <<= v2
+                return
+            : sprite 0xff 0x81 0xff
+        ";
+        let program = compile_source("test.8o", source).unwrap();
+        assert_eq!(
+            program.bytes,
+            vec![
+                0x00, 0xe0, // clear
+                0xa2, 0x28, // i := sprite
+                0x61, 0x1c, // x := HALF
+                0x71, 0x02, // x += SPEED
+                0x41, 0x3c, // if x == 60 then
+                0x61, 0x00, // x := 0
+                0xd1, 0x23, // sprite x v2 3
+                0x41, 0x1e, // while x != 30
+                0x12, 0x16, // (exit loop)
+                0x22, 0x24, // draw-more
+                0x12, 0x06, // again
+                0x6f, 0x05, 0x8f, 0x35, 0x4f, 0x01, // if v3 > 5 begin
+                0x12, 0x22, // (to else)
+                0xc4, 0x0f, // v4 := random 0xf
+                0x12, 0x24, // else
+                0x74, 0xff, // v4 -= 1
+                0x82, 0x2e, // v2 <<= v2
+                0x00, 0xee, // return
+                0xff, 0x81, 0xff, // sprite
+            ]
+        );
+        assert_eq!(program.symbols.lookup("draw-more").unwrap().value, 0x224);
+        // main comes first, so 0x200 is the clear rather than a jump to it
+        assert_eq!(program.lines.lookup(0x200).unwrap().line, 6);
+        assert_eq!(program.lines.lines.iter().filter(|l| l.addr == 0x200).count(), 1);
+        assert_eq!(program.lines.lookup(0x224).unwrap().line, 22);
+    }
+
+    #[test]
+    fn test_main_jump_and_macros() {
+        let source = "
+            :macro twice op { op op }
+            : helper twice return
+            :org 0x210
+            : main jump helper
+            :unpack 0xa helper
+        ";
+        let program = compile_source("test.8o", source).unwrap();
+        let mut expected = vec![0x12, 0x10, 0x00, 0xee, 0x00, 0xee];
+        expected.resize(16, 0);
+        expected.extend_from_slice(&[0x12, 0x02, 0x60, 0xa2, 0x61, 0x02]);
+        assert_eq!(program.bytes, expected);
+
+        let e = compile_source("test.8o", ": main\n  jump nowhere").unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.message.as_str()),
+            (2, 8, "undefined name 'nowhere'")
+        );
+        // the first of several, whatever order they are kept in
+        let e = compile_source("test.8o", ": main\n  jump c\n  jump b\n  jump a\n  jump b").unwrap_err();
+        assert_eq!((e.line, e.message.as_str()), (2, "undefined name 'c'"));
+        let e = compile_source("test.8o", ": main hires").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "test.8o:1:8: 'hires' is not supported by this instruction set"
+        );
+        assert!(compile_source("test.8o", ": start clear").is_err());
+    }
+
+    #[test]
+    fn test_comparisons_run() {
+        use crate::mem::Memory;
+        use crate::proc::Processor;
+        use std::sync::{Arc, Mutex};
+
+        for (op, holds) in [
+            ("<", (|a, b| a < b) as fn(u8, u8) -> bool),
+            (">", |a, b| a > b),
+            ("<=", |a, b| a <= b),
+            (">=", |a, b| a >= b),
+        ] {
+            for a in [4, 5, 6] {
+                // against a constant and against a register
+                for rhs in ["5", "v2"] {
+                    let source = format!(": main v0 := {a} v2 := 5 if v0 {op} {rhs} then v1 := 1 loop again");
+                    let program = compile_source("test.8o", &source).unwrap();
+                    let mut mem = Memory::new();
+                    mem.load_array(program.origin, &program.bytes).unwrap();
+                    let mut proc = Processor::new(mem, Arc::new(Mutex::new([0; 2048])));
+                    for _ in 0..10 {
+                        proc.execute().unwrap();
+                    }
+                    assert_eq!(proc.registers.v1 == 1, holds(a, 5), "{source}");
+                }
+            }
+        }
+    }
+}
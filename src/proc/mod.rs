@@ -7,6 +7,8 @@ use std::sync::{Arc, Mutex};
 use std::{error::Error, time::Duration, time::Instant};
 
 static RESET_VECTOR: usize = 512;
+static DISPLAY_WIDTH: usize = 64;
+static DISPLAY_HEIGHT: usize = 32;
 
 #[derive(Debug, Clone)]
 pub enum ErrorKind {
@@ -15,6 +17,8 @@ pub enum ErrorKind {
     InstructionInvalid([u8; 2]),
     InvalidMemoryAccess(usize),
     KeyboardError,
+    StackOverflow,
+    StackUnderflow,
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +34,107 @@ impl fmt::Display for ProcError {
 
 impl Error for ProcError {}
 
+// behaviours that differ between CHIP-8 interpreters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65 leave I pointing past the last register
+    pub load_store_increments_i: bool,
+    // Bnnn jumps to nnn + Vx (x being the high nibble of nnn) instead of nnn + V0
+    pub jump_uses_vx: bool,
+    // sprites are cut off at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub logic_resets_vf: bool,
+}
+
+// where Fx0A takes its key from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyInput {
+    // blocks reading the terminal
+    Terminal,
+    // waits, by executing Fx0A again, until a key in `keypad` is down
+    Keypad,
+}
+
+impl Quirks {
+    // the original COSMAC VIP interpreter
+    pub fn chip8() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            logic_resets_vf: true,
+        }
+    }
+
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            logic_resets_vf: false,
+        }
+    }
+
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+        }
+    }
+}
+
+// the Dxyn that last changed a pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Origin {
+    pub pc: u16,
+    pub i: u16,
+    // the sprite byte the pixel came from
+    pub row: u16,
+}
+
+// where each pixel of the display was drawn from, and which pixels the latest draw turned off
+#[derive(Clone)]
+pub struct Provenance {
+    pub pixels: Vec<Option<Origin>>,
+    pub collisions: Vec<usize>,
+}
+
+impl Default for Provenance {
+    fn default() -> Self {
+        Provenance {
+            pixels: vec![None; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            collisions: Vec::new(),
+        }
+    }
+}
+
+// everything execution depends on, so running on from a restored snapshot gives the same results
+#[derive(Clone)]
+pub struct Snapshot {
+    memory: [u8; 4096],
+    registers: [u8; 16],
+    pc: usize,
+    sp: usize,
+    i: u16,
+    stack: [u16; 128],
+    current_instruction: inst::Instruction,
+    delay_timer: u8,
+    sound_timer: u8,
+    keypad: [bool; 16],
+    rng: StdRng,
+    display: [u8; 2048],
+    provenance: Option<Provenance>,
+    heat: Option<mem::Heat>,
+}
+
 pub struct Processor {
     pub memory: mem::Memory,
     pub registers: reg::Registers,
@@ -37,9 +142,17 @@ pub struct Processor {
     pub sp: usize,
     pub i: u16,
     pub current_instruction: inst::Instruction,
+    pub quirks: Quirks,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keypad: [bool; 16],
+    pub key_input: KeyInput,
+    // recorded by Dxyn and 00E0 while enabled
+    pub provenance: Option<Provenance>,
     display: Arc<Mutex<[u8; 2048]>>,
-    keys: Getch,
-    rng: ThreadRng,
+    // only created once a program waits for a key, as it puts the terminal into raw mode
+    keys: Option<Getch>,
+    rng: StdRng,
     stack: [u16; 128],
 }
 
@@ -52,15 +165,21 @@ impl Processor {
             sp: 0,
             i: 0,
             current_instruction: inst::Instruction::NoOp,
+            quirks: Quirks::chip8(),
+            delay_timer: 0,
+            sound_timer: 0,
+            keypad: [false; 16],
+            key_input: KeyInput::Terminal,
+            provenance: None,
             display: display,
-            keys: Getch::new(),
-            rng: rand::rng(),
+            keys: None,
+            rng: StdRng::from_os_rng(),
             stack: [0; 128],
         }
     }
 
     fn fetch(&mut self) -> Result<[u8; 2], ProcError> {
-        let bs = self.memory.get_word(self.pc).map_err(|_| ProcError {
+        let bs = self.memory.fetch(self.pc).map_err(|_| ProcError {
             kind: ErrorKind::InvalidMemoryAccess(self.pc),
         })?;
         self.pc += 2;
@@ -68,14 +187,30 @@ impl Processor {
         Ok(bs)
     }
 
-    fn push_stack(&mut self, val: u16) {
+    fn push_stack(&mut self, val: u16) -> Result<(), ProcError> {
+        if self.sp == self.stack.len() {
+            return Err(ProcError {
+                kind: ErrorKind::StackOverflow,
+            });
+        }
         self.stack[self.sp] = val;
         self.sp += 1;
+        Ok(())
     }
-    fn pop_stack(&mut self) -> u16 {
+    fn pop_stack(&mut self) -> Result<u16, ProcError> {
+        if self.sp == 0 {
+            return Err(ProcError {
+                kind: ErrorKind::StackUnderflow,
+            });
+        }
         self.sp -= 1;
 
-        self.stack[self.sp]
+        Ok(self.stack[self.sp])
+    }
+
+    // return addresses of the active subroutines, innermost last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
     }
 
     pub fn reset(&mut self) {
@@ -83,6 +218,61 @@ impl Processor {
         self.sp = 0;
     }
 
+    // makes CXNN produce the same numbers on every run
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.mem,
+            registers: self.registers.as_array(),
+            pc: self.pc,
+            sp: self.sp,
+            i: self.i,
+            stack: self.stack,
+            current_instruction: self.current_instruction.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keypad: self.keypad,
+            rng: self.rng.clone(),
+            display: *self.display.lock().unwrap(),
+            provenance: self.provenance.clone(),
+            heat: self.memory.heat.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory.mem = snapshot.memory;
+        if let Some(log) = &mut self.memory.log {
+            log.clear();
+        }
+        self.registers.from_array(&snapshot.registers);
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.i = snapshot.i;
+        self.stack = snapshot.stack;
+        self.current_instruction = snapshot.current_instruction.clone();
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.keypad = snapshot.keypad;
+        self.rng = snapshot.rng.clone();
+        *self.display.lock().unwrap() = snapshot.display;
+        // recording stays on or off, if it was off when the snapshot was taken what came before is unknown
+        if self.provenance.is_some() {
+            self.provenance = Some(snapshot.provenance.clone().unwrap_or_default());
+        }
+        if self.memory.heat.is_some() {
+            self.memory.heat = Some(snapshot.heat.clone().unwrap_or_default());
+        }
+    }
+
+    // counts both timers down, to be called at 60Hz
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
     fn get_register(&self, index: u8) -> Result<u8, ProcError> {
         self.registers.get(index).map_err(|_| ProcError {
             kind: ErrorKind::InvalidRegister(index),
@@ -102,9 +292,10 @@ impl Processor {
         })
     }
 
-    fn read_key(&self) -> Result<u8, ProcError> {
+    fn read_key(&mut self) -> Result<u8, ProcError> {
+        let keys = self.keys.get_or_insert_with(Getch::new);
         loop {
-            match self.keys.getch() {
+            match keys.getch() {
                 Ok(Key::Char(c)) => {
                     // let ord: u8 = c as u8 - 49; // '1' -> 0, '2' ->1
                     match c {
@@ -162,6 +353,9 @@ impl Processor {
             inst::Instruction::ClearDisplay => {
                 let mut display = self.display.lock().unwrap();
                 display.fill(0);
+                if let Some(provenance) = &mut self.provenance {
+                    *provenance = Provenance::default();
+                }
                 Ok(start.elapsed())
             }
             inst::Instruction::Draw {
@@ -169,20 +363,32 @@ impl Processor {
                 reg_y,
                 sprite_height,
             } => {
-                let x = self.get_register(reg_x)?;
-                let y = self.get_register(reg_y)?;
+                // the starting position wraps, the quirk decides what happens to pixels past the edge
+                let x = self.get_register(reg_x)? as usize % DISPLAY_WIDTH;
+                let y = self.get_register(reg_y)? as usize % DISPLAY_HEIGHT;
                 let mut display = self.display.lock().unwrap();
 
                 // reset VF to 0
                 self.registers.vf = 0;
+                if let Some(provenance) = &mut self.provenance {
+                    provenance.collisions.clear();
+                }
 
-                for y_offset in 0..sprite_height {
-                    let row_addr = (self.i + (y_offset as u16)) as usize;
-                    let row = self.memory.get_byte(row_addr).map_err(|_| ProcError {
+                for y_offset in 0..sprite_height as usize {
+                    let row_addr = self.i as usize + y_offset;
+                    let row = self.memory.read(row_addr).map_err(|_| ProcError {
                         kind: ErrorKind::InvalidMemoryAccess(row_addr),
                     })?;
+                    let pixel_y = y + y_offset;
+                    if pixel_y >= DISPLAY_HEIGHT && self.quirks.clip_sprites {
+                        break;
+                    }
                     for x_offset in 0..8 {
-                        let pixel_addr = (x + x_offset) as usize + (((y + y_offset) as usize) * 64);
+                        let pixel_x = x + x_offset;
+                        if pixel_x >= DISPLAY_WIDTH && self.quirks.clip_sprites {
+                            break;
+                        }
+                        let pixel_addr = (pixel_x % DISPLAY_WIDTH) + (pixel_y % DISPLAY_HEIGHT) * DISPLAY_WIDTH;
                         let shift = 7 - x_offset;
 
                         // value for pixel in sprite to draw
@@ -193,6 +399,16 @@ impl Processor {
                         if display[pixel_addr] == 1 && pixel_xor == 0 {
                             // pixel was flipped from set to unset
                             self.registers.vf = 1;
+                            if let Some(provenance) = &mut self.provenance {
+                                provenance.collisions.push(pixel_addr);
+                            }
+                        }
+                        if let (Some(provenance), 1) = (&mut self.provenance, pixel_draw) {
+                            provenance.pixels[pixel_addr] = Some(Origin {
+                                pc: self.pc as u16 - 2,
+                                i: self.i,
+                                row: row_addr as u16,
+                            });
                         }
 
                         display[pixel_addr] = pixel_xor;
@@ -202,11 +418,11 @@ impl Processor {
                 Ok(start.elapsed())
             }
             inst::Instruction::Return => {
-                self.pc = self.pop_stack() as usize;
+                self.pc = self.pop_stack()? as usize;
                 Ok(start.elapsed())
             }
             inst::Instruction::CallSubroutine { addr } => {
-                self.push_stack(self.pc as u16);
+                self.push_stack(self.pc as u16)?;
                 self.pc = addr as usize;
 
                 Ok(start.elapsed())
@@ -216,8 +432,12 @@ impl Processor {
                 Ok(start.elapsed())
             }
             inst::Instruction::GotoPlusV0 { addr } => {
-                let v0 = self.get_register(reg::V0)? as u16;
-                self.pc = (addr + v0) as usize;
+                let offset_register = match self.quirks.jump_uses_vx {
+                    true => (addr >> 8) as u8,
+                    false => reg::V0,
+                };
+                let offset = self.get_register(offset_register)? as u16;
+                self.pc = (addr + offset) as usize;
                 Ok(start.elapsed())
             }
             inst::Instruction::Call { addr: _ } => Ok(start.elapsed()),
@@ -232,7 +452,7 @@ impl Processor {
             }
             inst::Instruction::AddToRegister { register, value } => {
                 let r_v = self.get_register(register)?;
-                self.set_register(register, r_v + value)?;
+                self.set_register(register, r_v.wrapping_add(value))?;
                 Ok(start.elapsed())
             }
             inst::Instruction::CopyRegister {
@@ -251,6 +471,9 @@ impl Processor {
                 let b = self.get_register(operand_register)?;
 
                 self.set_register(value_register, a | b)?;
+                if self.quirks.logic_resets_vf {
+                    self.registers.vf = 0;
+                }
                 Ok(start.elapsed())
             }
             inst::Instruction::ApplyBitwiseAnd {
@@ -261,6 +484,9 @@ impl Processor {
                 let b = self.get_register(operand_register)?;
 
                 self.set_register(value_register, a & b)?;
+                if self.quirks.logic_resets_vf {
+                    self.registers.vf = 0;
+                }
                 Ok(start.elapsed())
             }
             inst::Instruction::ApplyBitwiseXor {
@@ -271,6 +497,9 @@ impl Processor {
                 let b = self.get_register(operand_register)?;
 
                 self.set_register(value_register, a ^ b)?;
+                if self.quirks.logic_resets_vf {
+                    self.registers.vf = 0;
+                }
                 Ok(start.elapsed())
             }
             inst::Instruction::AddRegisters {
@@ -280,10 +509,10 @@ impl Processor {
                 let a = self.get_register(value_register)?;
                 let b = self.get_register(operand_register)?;
 
-                let (v, of) = a.overflowing_add(b);
-                self.registers.vf = if of { 1 } else { 0 };
-
+                // the flag is written last, so it wins when x is VF
+                let (v, carry) = a.overflowing_add(b);
                 self.set_register(value_register, v)?;
+                self.registers.vf = if carry { 1 } else { 0 };
                 Ok(start.elapsed())
             }
             inst::Instruction::SubtractRegisters {
@@ -293,21 +522,94 @@ impl Processor {
                 let a = self.get_register(value_register)?;
                 let b = self.get_register(operand_register)?;
 
-                let (v, of) = a.overflowing_sub(b);
-                self.registers.vf = if of { 1 } else { 0 };
+                // VF is 1 when there was no borrow, written last like the carry of 8xy4
+                let (v, borrow) = a.overflowing_sub(b);
                 self.set_register(value_register, v)?;
+                self.registers.vf = if borrow { 0 } else { 1 };
+                Ok(start.elapsed())
+            }
+            inst::Instruction::SubtractRegistersReversed {
+                value_register,
+                operand_register,
+            } => {
+                let a = self.get_register(value_register)?;
+                let b = self.get_register(operand_register)?;
+
+                let (v, borrow) = b.overflowing_sub(a);
+                self.set_register(value_register, v)?;
+                self.registers.vf = if borrow { 0 } else { 1 };
+                Ok(start.elapsed())
+            }
+            inst::Instruction::ShiftRight {
+                value_register,
+                operand_register,
+            }
+            | inst::Instruction::ShiftLeft {
+                value_register,
+                operand_register,
+            } => {
+                let source = match self.quirks.shift_uses_vy {
+                    true => operand_register,
+                    false => value_register,
+                };
+                let v = self.get_register(source)?;
+                let (shifted, flag) = match self.current_instruction {
+                    inst::Instruction::ShiftRight { .. } => (v >> 1, v & 1),
+                    _ => (v << 1, v >> 7),
+                };
+                self.set_register(value_register, shifted)?;
+                self.registers.vf = flag;
                 Ok(start.elapsed())
             }
             inst::Instruction::SetI { addr } => {
                 self.i = addr;
                 Ok(start.elapsed())
             }
+            inst::Instruction::AddToI { register } => {
+                let v = self.get_register(register)? as u16;
+                self.i = self.i.wrapping_add(v);
+                Ok(start.elapsed())
+            }
+            inst::Instruction::SetIToFontSprite { register } => {
+                let v = self.get_register(register)? as u16;
+                self.i = mem::FONT_ADDR as u16 + (v & 0xf) * 5;
+                Ok(start.elapsed())
+            }
+            inst::Instruction::StoreBcd { register } => {
+                let v = self.get_register(register)?;
+                for (offset, digit) in [v / 100, (v / 10) % 10, v % 10].iter().enumerate() {
+                    let addr = self.i as usize + offset;
+                    self.memory.write(addr, *digit).map_err(|_| ProcError {
+                        kind: ErrorKind::InvalidMemoryAccess(addr),
+                    })?;
+                }
+                Ok(start.elapsed())
+            }
             inst::Instruction::DumpRegisters { end_register } => {
-                for r_v in self.registers.as_array()[0..(end_register + 1) as usize].iter() {
-                    self.memory.set_byte(self.i as usize, *r_v).map_err(|_| ProcError {
-                        kind: ErrorKind::InvalidMemoryAccess(self.i as usize),
+                for (offset, r_v) in self.registers.as_array()[0..(end_register + 1) as usize]
+                    .iter()
+                    .enumerate()
+                {
+                    let addr = self.i as usize + offset;
+                    self.memory.write(addr, *r_v).map_err(|_| ProcError {
+                        kind: ErrorKind::InvalidMemoryAccess(addr),
+                    })?;
+                }
+                if self.quirks.load_store_increments_i {
+                    self.i += end_register as u16 + 1;
+                }
+                Ok(start.elapsed())
+            }
+            inst::Instruction::LoadRegisters { end_register } => {
+                for r in 0..=end_register {
+                    let addr = self.i as usize + r as usize;
+                    let v = self.memory.read(addr).map_err(|_| ProcError {
+                        kind: ErrorKind::InvalidMemoryAccess(addr),
                     })?;
-                    self.i += 1;
+                    self.set_register(r, v)?;
+                }
+                if self.quirks.load_store_increments_i {
+                    self.i += end_register as u16 + 1;
                 }
                 Ok(start.elapsed())
             }
@@ -333,12 +635,50 @@ impl Processor {
                 }
                 Ok(start.elapsed())
             }
+            inst::Instruction::SkipIfRegistersNotEqual { register_1, register_2 } => {
+                let r1_value = self.get_register(register_1)?;
+                let r2_value = self.get_register(register_2)?;
+                if r1_value != r2_value {
+                    self.pc += 2;
+                }
+                Ok(start.elapsed())
+            }
+            inst::Instruction::SkipIfKeyPressed { register } | inst::Instruction::SkipIfKeyNotPressed { register } => {
+                let k = self.get_register(register)?;
+                let pressed = self.keypad[(k & 0xf) as usize];
+                let skip_if_pressed = matches!(self.current_instruction, inst::Instruction::SkipIfKeyPressed { .. });
+                if pressed == skip_if_pressed {
+                    self.pc += 2;
+                }
+                Ok(start.elapsed())
+            }
             inst::Instruction::GetKey { register: reg } => {
-                let k = self.read_key()?;
+                let k = match self.key_input {
+                    KeyInput::Terminal => self.read_key()?,
+                    KeyInput::Keypad => match self.keypad.iter().position(|k| *k) {
+                        Some(k) => k as u8,
+                        None => {
+                            self.pc -= 2;
+                            return Ok(start.elapsed());
+                        }
+                    },
+                };
                 self.set_register(reg, k)?;
 
                 Ok(start.elapsed())
             }
+            inst::Instruction::GetDelayTimer { register } => {
+                self.set_register(register, self.delay_timer)?;
+                Ok(start.elapsed())
+            }
+            inst::Instruction::SetDelayTimer { register } => {
+                self.delay_timer = self.get_register(register)?;
+                Ok(start.elapsed())
+            }
+            inst::Instruction::SetSoundTimer { register } => {
+                self.sound_timer = self.get_register(register)?;
+                Ok(start.elapsed())
+            }
             _ => Err(ProcError {
                 kind: ErrorKind::InstructionNotImplemented(self.current_instruction.clone()),
             }),
@@ -346,8 +686,10 @@ impl Processor {
     }
 }
 
+#[cfg(test)]
 mod tests {
     use crate::mem::Memory;
+    use std::sync::{Arc, Mutex};
 
     // use super::*;
 
@@ -384,7 +726,7 @@ mod tests {
         let mut mem = Memory::new();
         load_test_program(&mut mem);
 
-        let mut proc = super::Processor::new(mem);
+        let mut proc = super::Processor::new(mem, Arc::new(Mutex::new([0; 2048])));
         proc.registers
             .from_array(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
 
@@ -451,13 +793,13 @@ mod tests {
         let r = proc.execute();
         assert!(matches!(r, Ok(_)));
         assert_eq!(proc.registers.v8, 8);
-        assert_eq!(proc.registers.vf, 0);
+        assert_eq!(proc.registers.vf, 1);
 
         // V8 -= V9 (again, overflows)
         let r = proc.execute();
         assert!(matches!(r, Ok(_)));
         assert_eq!(proc.registers.v8, 222);
-        assert_eq!(proc.registers.vf, 1);
+        assert_eq!(proc.registers.vf, 0);
 
         // return from subroutine
         let r = proc.execute();
@@ -471,4 +813,91 @@ mod tests {
         assert!(matches!(r, Ok(_)));
         assert_eq!(proc.pc, pre_skip_pc + 4);
     }
+
+    // runs each instruction of `program`, loaded at 0x200
+    fn run(program: &[u8], quirks: super::Quirks) -> super::Processor {
+        let mut mem = Memory::new();
+        mem.load_array(0x200, program).unwrap();
+        let mut proc = super::Processor::new(mem, Arc::new(Mutex::new([0; 2048])));
+        proc.quirks = quirks;
+        for _ in 0..program.len() / 2 {
+            proc.execute().unwrap();
+        }
+        proc
+    }
+
+    #[test]
+    fn test_quirks() {
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x7b, // V0 = 123
+            0xa3, 0x00, // I = 0x300
+            0xf0, 0x33, // BCD of V0 at I
+            0xf1, 0x65, // V0, V1 = 1, 2
+            0x62, 0x81, // V2 = 0x81
+            0x83, 0x26, // V3 = V2 >> 1, or V3 >> 1
+            0x84, 0x21, // V4 |= V2
+            0xb3, 0x00, // jump to 0x300 + V0, or + V3
+        ];
+        let chip8 = run(&program, super::Quirks::chip8());
+        assert_eq!(chip8.memory.get_byte(0x302).unwrap(), 3);
+        assert_eq!((chip8.registers.v0, chip8.registers.v1), (1, 2));
+        assert_eq!(chip8.i, 0x302);
+        assert_eq!(chip8.registers.v3, 0x40);
+        assert_eq!(chip8.registers.v4, 0x81);
+        // or reset VF after the shift set it
+        assert_eq!(chip8.registers.vf, 0);
+        assert_eq!(chip8.pc, 0x301);
+
+        let schip = run(&program, super::Quirks::schip());
+        assert_eq!(schip.i, 0x300);
+        assert_eq!(schip.registers.v3, 0);
+        assert_eq!(schip.registers.vf, 0);
+        assert_eq!(schip.pc, 0x300);
+
+        // a sprite drawn over the right edge
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x3e, // V0 = 62
+            0x61, 0x01, // V1 = 1
+            0xf1, 0x29, // I = the font sprite of 1
+            0xd0, 0x11, // draw its first row, 0x20, at 62,1
+        ];
+        let clipped = run(&program, super::Quirks::chip8());
+        assert_eq!(clipped.i, 0x55);
+        assert!(clipped.display.lock().unwrap().iter().all(|p| *p == 0));
+        let wrapped = run(&program, super::Quirks::xochip());
+        assert_eq!(wrapped.display.lock().unwrap()[64], 1);
+    }
+
+    #[test]
+    fn test_timers_and_keys() {
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x02, // V0 = 2
+            0xf0, 0x15, // delay = V0
+            0xf0, 0x18, // sound = V0
+        ];
+        let mut proc = run(&program, super::Quirks::chip8());
+        proc.tick_timers();
+        assert_eq!((proc.delay_timer, proc.sound_timer), (1, 1));
+        proc.tick_timers();
+        proc.tick_timers();
+        assert_eq!((proc.delay_timer, proc.sound_timer), (0, 0));
+
+        #[rustfmt::skip]
+        proc.memory.load_array(0x206, &[
+            0xf1, 0x07, // V1 = delay
+            0x62, 0x0a, // V2 = 0xa
+            0xe2, 0x9e, // skip if key A is down
+            0x00, 0xe0, // skipped
+            0xe2, 0xa1, // skip if key A is up
+        ]).unwrap();
+        proc.keypad[0xa] = true;
+        for _ in 0..4 {
+            proc.execute().unwrap();
+        }
+        assert_eq!(proc.registers.v1, 0);
+        assert_eq!(proc.pc, 0x210);
+    }
 }
@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+use crate::asm::Symbols;
+use crate::decomp;
+use crate::inst::Instruction;
+use crate::proc::Processor;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+static ORIGIN: u16 = 512;
+static HOT_ADDRESSES: usize = 20;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subroutine {
+    pub calls: u64,
+    // cycles spent in it and everything it called
+    pub inclusive: u64,
+    // cycles spent in its own instructions
+    pub exclusive: u64,
+}
+
+// where the cycles of a run went, one cycle per instruction
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub cycles: u64,
+    pub addresses: BTreeMap<u16, u64>,
+    // by Instruction variant
+    pub instructions: BTreeMap<String, u64>,
+    // by entry address, the code outside any call counts as a subroutine at the origin
+    pub subroutines: BTreeMap<u16, Subroutine>,
+    // entry addresses from the outermost frame in, with the cycles spent in the innermost
+    pub stacks: HashMap<Vec<u16>, u64>,
+}
+
+// the Instruction variant without its fields
+fn variant(inst: &Instruction) -> String {
+    let name = format!("{inst:?}");
+    match name.split_once([' ', '{']) {
+        Some((name, _)) => name.to_string(),
+        None => name,
+    }
+}
+
+impl Profile {
+    // called before executing the instruction at pc
+    pub fn record(&mut self, proc: &Processor) {
+        let pc = proc.pc;
+        let Some(inst) = instruction_at(proc, pc) else {
+            return;
+        };
+        self.cycles += 1;
+        *self.addresses.entry(pc as u16).or_default() += 1;
+        *self.instructions.entry(variant(&inst)).or_default() += 1;
+
+        // each return address follows the call that made the frame, which tells its entry
+        let mut stack = vec![ORIGIN];
+        stack.extend(
+            proc.stack()
+                .iter()
+                .map(|ret| match instruction_at(proc, *ret as usize - 2) {
+                    Some(Instruction::CallSubroutine { addr }) => addr,
+                    _ => *ret - 2,
+                }),
+        );
+        let mut seen = Vec::with_capacity(stack.len());
+        for entry in stack.iter() {
+            // recursion counts once towards inclusive time
+            if !seen.contains(entry) {
+                seen.push(*entry);
+                self.subroutines.entry(*entry).or_default().inclusive += 1;
+            }
+        }
+        self.subroutines.entry(*stack.last().unwrap()).or_default().exclusive += 1;
+        if let Instruction::CallSubroutine { addr } = inst {
+            self.subroutines.entry(addr).or_default().calls += 1;
+        }
+        *self.stacks.entry(stack).or_default() += 1;
+    }
+
+    fn percent(&self, n: u64) -> f64 {
+        n as f64 * 100.0 / self.cycles.max(1) as f64
+    }
+
+    pub fn report(&self, proc: &Processor, symbols: &Symbols) -> String {
+        let mut out = String::new();
+        writeln!(out, "cycles: {}", self.cycles).unwrap();
+
+        writeln!(out, "hot addresses:").unwrap();
+        let mut addresses: Vec<(&u16, &u64)> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (addr, n) in addresses.into_iter().take(HOT_ADDRESSES) {
+            let inst = instruction_at(proc, *addr as usize).map(|i| i.to_string());
+            writeln!(
+                out,
+                "  {:>10} {:>6.2}%  {:<24} {}",
+                n,
+                self.percent(*n),
+                describe(symbols, *addr),
+                inst.unwrap_or_default()
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "instructions:").unwrap();
+        let mut instructions: Vec<(&String, &u64)> = self.instructions.iter().collect();
+        instructions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (name, n) in instructions {
+            writeln!(out, "  {:>10} {:>6.2}%  {name}", n, self.percent(*n)).unwrap();
+        }
+
+        writeln!(out, "subroutines:        calls  inclusive          exclusive").unwrap();
+        let mut subroutines: Vec<(&u16, &Subroutine)> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        for (entry, s) in subroutines {
+            writeln!(
+                out,
+                "  {:<16} {:>7} {:>10} {:>6.2}% {:>10} {:>6.2}%",
+                name(symbols, *entry),
+                s.calls,
+                s.inclusive,
+                self.percent(s.inclusive),
+                s.exclusive,
+                self.percent(s.exclusive)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // the code in memory, each instruction with the cycles spent on it
+    pub fn listing(&self, proc: &Processor, rom_len: usize, symbols: &Symbols) -> String {
+        let start = ORIGIN as usize;
+        let rom = &proc.memory.mem[start..start + rom_len];
+        let code = decomp::trace(rom);
+        let mut addrs: Vec<u16> = (start..start + rom_len)
+            .filter(|a| code[*a])
+            .map(|a| a as u16)
+            .collect();
+        addrs.extend(self.addresses.keys());
+        addrs.sort();
+        addrs.dedup();
+
+        let mut out = String::new();
+        for addr in addrs {
+            if let Some(label) = symbols.label_at(addr) {
+                writeln!(out, "{label}:").unwrap();
+            }
+            let Some(inst) = instruction_at(proc, addr as usize) else {
+                continue;
+            };
+            let bytes = inst.encode();
+            match self.addresses.get(&addr) {
+                Some(n) => write!(out, "{:>10} {:>6.2}%", n, self.percent(*n)).unwrap(),
+                None => write!(out, "{:>18}", "").unwrap(),
+            }
+            writeln!(out, "  {addr:#05x}: {:02x} {:02x}  {inst}", bytes[0], bytes[1]).unwrap();
+        }
+        out
+    }
+
+    // the format of flame graph tools: frames separated by semicolons, then the count
+    pub fn folded(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, n)| {
+                let frames: Vec<String> = stack.iter().map(|e| name(symbols, *e)).collect();
+                format!("{} {n}", frames.join(";"))
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|l| format!("{l}\n")).collect()
+    }
+}
+
+fn instruction_at(proc: &Processor, addr: usize) -> Option<Instruction> {
+    Instruction::parse(proc.memory.get_word(addr).ok()?).ok()
+}
+
+fn name(symbols: &Symbols, entry: u16) -> String {
+    match symbols.label_at(entry) {
+        Some(label) => label.to_string(),
+        None if entry == ORIGIN => "start".to_string(),
+        None => format!("sub_{entry:03x}"),
+    }
+}
+
+fn describe(symbols: &Symbols, addr: u16) -> String {
+    match symbols.label_before(addr) {
+        Some((label, 0)) => format!("{addr:#05x} <{label}>"),
+        Some((label, offset)) => format!("{addr:#05x} <{label}+{offset}>"),
+        None => format!("{addr:#05x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Memory;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_profile() {
+        let mut memory = Memory::new();
+        #[rustfmt::skip]
+        memory.load_array(512, &[
+            0x22, 0x06, // call 0x206
+            0x12, 0x00, // jp 0x200
+            0x00, 0x00,
+            0x70, 0x01, // add v0, 1
+            0x22, 0x0e, // call 0x20e
+            0x00, 0xee, // ret
+            0x00, 0x00,
+            0x71, 0x01, // add v1, 1
+            0x00, 0xee, // ret
+        ]).unwrap();
+        let mut proc = Processor::new(memory, Arc::new(Mutex::new([0; 2048])));
+        let mut profile = Profile::default();
+        // two rounds of the loop, 7 instructions each
+        for _ in 0..14 {
+            profile.record(&proc);
+            proc.execute().unwrap();
+        }
+
+        assert_eq!(profile.cycles, 14);
+        assert_eq!(profile.addresses[&0x206], 2);
+        assert_eq!(profile.instructions["CallSubroutine"], 4);
+        assert_eq!(profile.instructions["AddToRegister"], 4);
+        let sub = |calls, inclusive, exclusive| Subroutine {
+            calls,
+            inclusive,
+            exclusive,
+        };
+        assert_eq!(profile.subroutines[&0x200], sub(0, 14, 4));
+        assert_eq!(profile.subroutines[&0x206], sub(2, 10, 6));
+        assert_eq!(profile.subroutines[&0x20e], sub(2, 4, 4));
+
+        let symbols = Symbols::parse("test.sym", "label 0x20e inner").unwrap();
+        assert_eq!(
+            profile.folded(&symbols),
+            "start 4\nstart;sub_206 6\nstart;sub_206;inner 4\n"
+        );
+        let report = profile.report(&proc, &symbols);
+        assert!(report.contains("  sub_206                2         10  71.43%          6  42.86%\n"));
+        let listing = profile.listing(&proc, 18, &symbols);
+        assert!(listing.contains("inner:\n         2  14.29%  0x20e: 71 01  ADD V1, 0x01\n"));
+    }
+}
@@ -0,0 +1,201 @@
+#![allow(dead_code)]
+use crate::disp::theme::Theme;
+use crate::shot::{Format, Screenshot};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+// frames are recorded at the rate the emulated display runs
+static FRAME_RATE: usize = 60;
+
+enum Sink {
+    Gif(gif::Encoder<BufWriter<File>>),
+    // numbered images next to this path
+    Png(PathBuf),
+}
+
+// records frames of the display to an animated gif, or to a png per frame
+pub struct Recorder {
+    sink: Sink,
+    theme: Theme,
+    width: usize,
+    height: usize,
+    scale: usize,
+    frames: usize,
+    // the last gif frame and the frame it was first shown at, written once it changes and its delay is known
+    pending: Option<(Vec<u8>, usize)>,
+}
+
+// gif delays are in hundredths of a second, so frames are rounded to the closest to keep in time
+fn centiseconds(frame: usize) -> usize {
+    (frame * 100 + FRAME_RATE / 2) / FRAME_RATE
+}
+
+impl Recorder {
+    // a .gif path records an animation, any other a png sequence: clip.png becomes clip-00000.png, clip-00001.png..
+    pub fn create(path: &Path, width: usize, height: usize, scale: usize, theme: &Theme) -> io::Result<Recorder> {
+        let scale = scale.max(1);
+        let sink = match Format::from_path(path) {
+            None if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("gif")) => {
+                // gif sizes are 16 bit
+                let size = |n: usize| n.checked_mul(scale).and_then(|n| u16::try_from(n).ok());
+                let (Some(gif_width), Some(gif_height)) = (size(width), size(height)) else {
+                    let most = u16::MAX as usize / width.max(height).max(1);
+                    return Err(io::Error::other(format!("the scale of a gif can be at most {most}")));
+                };
+                let palette: Vec<u8> = theme.palette.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+                let out = BufWriter::new(File::create(path)?);
+                let mut encoder = gif::Encoder::new(out, gif_width, gif_height, &palette).map_err(io::Error::other)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+                Sink::Gif(encoder)
+            }
+            Some(Format::Png) => Sink::Png(path.to_path_buf()),
+            _ => return Err(io::Error::other("recordings are .gif or .png")),
+        };
+        Ok(Recorder {
+            sink,
+            theme: theme.clone(),
+            width,
+            height,
+            scale,
+            frames: 0,
+            pending: None,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // `pixels` holds a value per pixel, as in the display buffer
+    pub fn frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        if pixels.len() != self.width * self.height {
+            return Err(io::Error::other("the display changed size while recording"));
+        }
+        match &self.sink {
+            Sink::Gif(_) => match &self.pending {
+                // shown for longer
+                Some((last, _)) if last == pixels => {}
+                _ => {
+                    self.flush()?;
+                    self.pending = Some((pixels.to_vec(), self.frames));
+                }
+            },
+            Sink::Png(path) => {
+                let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+                let path = path.with_file_name(format!("{stem}-{:05}.png", self.frames));
+                let shot = Screenshot {
+                    pixels,
+                    width: self.width,
+                    height: self.height,
+                    theme: &self.theme,
+                };
+                shot.write(BufWriter::new(File::create(path)?), Format::Png, self.scale)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // writes the pending gif frame, shown until now
+    fn flush(&mut self) -> io::Result<()> {
+        let (Sink::Gif(encoder), Some((pixels, start))) = (&mut self.sink, self.pending.take()) else {
+            return Ok(());
+        };
+        let (width, height) = (self.width * self.scale, self.height * self.scale);
+        let mut indices = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let value = pixels[y / self.scale * self.width + x / self.scale];
+                indices.push(value.min(self.theme.palette.len() as u8 - 1));
+            }
+        }
+        // a delay only goes up to u16::MAX hundredths, so a frame shown for longer is repeated
+        let mut delay = centiseconds(self.frames) - centiseconds(start);
+        loop {
+            let frame = gif::Frame {
+                width: width as u16,
+                height: height as u16,
+                delay: delay.min(u16::MAX as usize) as u16,
+                buffer: Cow::Borrowed(&indices),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+            delay = delay.saturating_sub(u16::MAX as usize);
+            if delay == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        if let Sink::Gif(encoder) = self.sink {
+            encoder.into_inner()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disp::theme;
+
+    #[test]
+    fn test_recording() {
+        let dir = std::env::temp_dir().join(format!("chip8-rec-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let theme = theme::builtin().remove(0);
+        let (a, b) = ([0, 1, 1, 0], [1, 0, 0, 1]);
+
+        let path = dir.join("clip.gif");
+        let mut recorder = Recorder::create(&path, 2, 2, 3, &theme).unwrap();
+        for frame in [a, a, b] {
+            recorder.frame(&frame).unwrap();
+        }
+        recorder.finish().unwrap();
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (6, 6));
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[..6].to_vec()));
+        }
+        // the repeated frame is shown for 2/60 of a second and the last for 1/60, about 3 and 2 hundredths
+        assert_eq!(frames, [(3, vec![0, 0, 0, 1, 1, 1]), (2, vec![1, 1, 1, 0, 0, 0])]);
+
+        // 40000 frames are 66667 hundredths, longer than a gif delay goes, so the frame is split
+        let mut recorder = Recorder::create(&path, 2, 2, 1, &theme).unwrap();
+        for _ in 0..40_000 {
+            recorder.frame(&a).unwrap();
+        }
+        recorder.frame(&b).unwrap();
+        recorder.finish().unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, [u16::MAX, 1132, 1]);
+
+        let mut recorder = Recorder::create(&dir.join("frame.png"), 2, 2, 1, &theme).unwrap();
+        for frame in [a, b] {
+            recorder.frame(&frame).unwrap();
+        }
+        assert_eq!(recorder.frames(), 2);
+        assert!(dir.join("frame-00001.png").exists());
+        assert!(recorder.frame(&[0; 8]).is_err());
+        recorder.finish().unwrap();
+        assert!(Recorder::create(&dir.join("clip.bmp"), 2, 2, 1, &theme).is_err());
+        let err = Recorder::create(&dir.join("big.gif"), 64, 32, 1024, &theme)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "the scale of a gif can be at most 1023");
+        assert!(!dir.join("big.gif").exists());
+        assert!(Recorder::create(&dir.join("big.gif"), 64, 32, usize::MAX, &theme).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+#![allow(dead_code)]
+use crate::disp::theme::Theme;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    // plain text netpbm, lit pixels are 1
+    Pbm,
+    // plain text netpbm, in the gray of each pixel's color
+    Pgm,
+    // a rect per run of pixels of a color
+    Svg,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "png" => Some(Format::Png),
+            "pbm" => Some(Format::Pbm),
+            "pgm" => Some(Format::Pgm),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+
+    // from the file extension
+    pub fn from_path(path: &Path) -> Option<Format> {
+        Format::parse(&path.extension()?.to_str()?.to_lowercase())
+    }
+}
+
+// a copy of the display, `pixels` holding a value per pixel as in the display buffer
+pub struct Screenshot<'a> {
+    pub pixels: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    pub theme: &'a Theme,
+}
+
+impl Screenshot<'_> {
+    // each display pixel becomes scale x scale pixels of the image
+    pub fn write(&self, mut out: impl Write, format: Format, scale: usize) -> io::Result<()> {
+        let scale = scale.max(1);
+        let (width, height) = (self.width * scale, self.height * scale);
+        let value = |x: usize, y: usize| self.pixels[y / scale * self.width + x / scale];
+        match format {
+            Format::Png => {
+                let mut data = Vec::with_capacity(width * height * 3);
+                for y in 0..height {
+                    for x in 0..width {
+                        let color = self.theme.color(value(x, y));
+                        data.extend([color.r, color.g, color.b]);
+                    }
+                }
+                let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder.write_header().map_err(io::Error::other)?;
+                writer.write_image_data(&data).map_err(io::Error::other)
+            }
+            Format::Pbm | Format::Pgm => {
+                match format {
+                    Format::Pbm => writeln!(out, "P1\n{width} {height}")?,
+                    _ => writeln!(out, "P2\n{width} {height}\n255")?,
+                }
+                for y in 0..height {
+                    let row: Vec<String> = (0..width)
+                        .map(|x| match format {
+                            Format::Pbm => ((value(x, y) != 0) as u8).to_string(),
+                            _ => {
+                                let c = self.theme.color(value(x, y));
+                                // the luma of Rec. 601
+                                let gray = (c.r as u32 * 299 + c.g as u32 * 587 + c.b as u32 * 114) / 1000;
+                                gray.to_string()
+                            }
+                        })
+                        .collect();
+                    writeln!(out, "{}", row.join(" "))?;
+                }
+                Ok(())
+            }
+            Format::Svg => {
+                writeln!(
+                    out,
+                    "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+                     viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">",
+                    self.width, self.height
+                )?;
+                let hex = |value: u8| {
+                    let c = self.theme.color(value);
+                    format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+                };
+                writeln!(
+                    out,
+                    "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    self.width,
+                    self.height,
+                    hex(0)
+                )?;
+                for (y, row) in self.pixels.chunks(self.width).enumerate() {
+                    let mut x = 0;
+                    while x < row.len() {
+                        let run = row[x..].iter().take_while(|p| **p == row[x]).count();
+                        if row[x] != 0 {
+                            writeln!(
+                                out,
+                                "<rect x=\"{x}\" y=\"{y}\" width=\"{run}\" height=\"1\" fill=\"{}\"/>",
+                                hex(row[x])
+                            )?;
+                        }
+                        x += run;
+                    }
+                }
+                writeln!(out, "</svg>")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disp::theme;
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(Format::from_path(Path::new("shot.PNG")), Some(Format::Png));
+        assert_eq!(Format::from_path(Path::new("shot")), None);
+
+        let theme = theme::builtin().remove(0);
+        #[rustfmt::skip]
+        let pixels = [
+            0, 1, 1, 0,
+            0, 0, 0, 1,
+        ];
+        let shot = Screenshot {
+            pixels: &pixels,
+            width: 4,
+            height: 2,
+            theme: &theme,
+        };
+        let text = |format, scale| {
+            let mut out = Vec::new();
+            shot.write(&mut out, format, scale).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(text(Format::Pbm, 1), "P1\n4 2\n0 1 1 0\n0 0 0 1\n");
+        assert_eq!(text(Format::Pgm, 1), "P2\n4 2\n255\n0 255 255 0\n0 0 0 255\n");
+        assert!(text(Format::Pbm, 2).starts_with("P1\n8 4\n0 0 1 1 1 1 0 0\n0 0 1 1 1 1 0 0\n"));
+        let svg = text(Format::Svg, 10);
+        assert!(svg.contains("width=\"40\" height=\"20\" viewBox=\"0 0 4 2\""));
+        assert!(svg.contains("<rect x=\"1\" y=\"0\" width=\"2\" height=\"1\" fill=\"#ffffff\"/>\n"));
+        assert_eq!(svg.matches("<rect").count(), 3);
+
+        let mut png = Vec::new();
+        shot.write(&mut png, Format::Png, 3).unwrap();
+        let decoder = png::Decoder::new(&png[..]);
+        let info = decoder.read_info().unwrap();
+        assert_eq!((info.info().width, info.info().height), (12, 6));
+    }
+}
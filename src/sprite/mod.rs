@@ -0,0 +1,198 @@
+#![allow(dead_code)]
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+// pixels between sprites in a sheet
+static GAP: usize = 1;
+
+static OFF: [u8; 3] = [0, 0, 0];
+static ON: [u8; 3] = [255, 255, 255];
+static GAP_COLOR: [u8; 3] = [40, 40, 40];
+// the sprite I points to
+static HIGHLIGHT_OFF: [u8; 3] = [80, 0, 80];
+static HIGHLIGHT_ON: [u8; 3] = [255, 0, 255];
+
+// memory and I, as copied out of a running program for a viewer
+pub type SharedMemory = Arc<Mutex<(Vec<u8>, u16)>>;
+
+// how memory is cut into sprites: 8 pixels wide with a byte per row, or SCHIP's 16x16 with two bytes per row
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Chip8 { height: usize },
+    Schip,
+}
+
+impl Layout {
+    // "8x5", "16x16"
+    pub fn parse(s: &str) -> Option<Layout> {
+        match s.split_once('x')? {
+            ("16", "16") => Some(Layout::Schip),
+            ("8", height) => match height.parse() {
+                Ok(height @ 1..=15) => Some(Layout::Chip8 { height }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        match self {
+            Layout::Chip8 { .. } => 8,
+            Layout::Schip => 16,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self {
+            Layout::Chip8 { height } => *height,
+            Layout::Schip => 16,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.width() / 8 * self.height()
+    }
+}
+
+// a range of memory drawn as a grid of sprites
+pub struct Sheet {
+    pub layout: Layout,
+    pub start: usize,
+    pub bytes: Vec<u8>,
+    pub columns: usize,
+    // the bytes a sprite drawn now would be read from
+    pub highlight: Option<Range<usize>>,
+}
+
+impl Sheet {
+    pub fn new(memory: &[u8], range: Range<usize>, layout: Layout, columns: usize, i: Option<u16>) -> Self {
+        let end = range.end.min(memory.len());
+        let start = range.start.min(end);
+        Sheet {
+            layout,
+            start,
+            bytes: memory[start..end].to_vec(),
+            columns: columns.max(1),
+            highlight: i.map(|i| i as usize..i as usize + layout.bytes()),
+        }
+    }
+
+    pub fn sprites(&self) -> usize {
+        self.bytes.len().div_ceil(self.layout.bytes())
+    }
+
+    // in pixels, with a gap around every sprite
+    pub fn size(&self) -> (usize, usize) {
+        let rows = self.sprites().div_ceil(self.columns).max(1);
+        let columns = self.columns.min(self.sprites()).max(1);
+        (
+            columns * (self.layout.width() + GAP) + GAP,
+            rows * (self.layout.height() + GAP) + GAP,
+        )
+    }
+
+    // the sprite and the byte of it that a pixel shows, None in gaps and past the end
+    pub fn address_at(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (w, h) = (self.layout.width() + GAP, self.layout.height() + GAP);
+        let (column, px) = (x.checked_sub(GAP)? / w, x.checked_sub(GAP)? % w);
+        let (row, py) = (y.checked_sub(GAP)? / h, y.checked_sub(GAP)? % h);
+        if column >= self.columns || px >= self.layout.width() || py >= self.layout.height() {
+            return None;
+        }
+        let sprite = self.start + (row * self.columns + column) * self.layout.bytes();
+        let byte = sprite + py * self.layout.width() / 8 + px / 8;
+        (byte < self.start + self.bytes.len()).then_some((sprite, byte))
+    }
+
+    pub fn rgb(&self) -> Vec<[u8; 3]> {
+        let (width, height) = self.size();
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let color = match self.address_at(x, y) {
+                    None => GAP_COLOR,
+                    Some((_, byte)) => {
+                        let px = (x - GAP) % (self.layout.width() + GAP);
+                        let on = self.bytes[byte - self.start] & (0x80 >> (px % 8)) != 0;
+                        let highlighted = self.highlight.as_ref().is_some_and(|h| h.contains(&byte));
+                        match (highlighted, on) {
+                            (false, false) => OFF,
+                            (false, true) => ON,
+                            (true, false) => HIGHLIGHT_OFF,
+                            (true, true) => HIGHLIGHT_ON,
+                        }
+                    }
+                };
+                pixels.push(color);
+            }
+        }
+        pixels
+    }
+
+    // each sprite pixel becomes scale x scale pixels of the image
+    pub fn write_png(&self, out: impl Write, scale: usize) -> io::Result<()> {
+        let (width, height) = self.size();
+        let scale = scale.max(1);
+        let rgb = self.rgb();
+        let mut data = Vec::with_capacity(width * height * scale * scale * 3);
+        for y in 0..height * scale {
+            for x in 0..width * scale {
+                data.extend(rgb[y / scale * width + x / scale]);
+            }
+        }
+        let mut encoder = png::Encoder::new(out, (width * scale) as u32, (height * scale) as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&data).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Memory;
+
+    #[test]
+    fn test_sheet() {
+        assert_eq!(Layout::parse("8x5"), Some(Layout::Chip8 { height: 5 }));
+        assert_eq!(Layout::parse("16x16"), Some(Layout::Schip));
+        assert_eq!(Layout::parse("8x16"), None);
+        assert_eq!(Layout::Schip.bytes(), 32);
+
+        // the font digits 0 to 3, with I at 2
+        let memory = Memory::new();
+        let sheet = Sheet::new(&memory.mem, 0x50..0x64, Layout::Chip8 { height: 5 }, 2, Some(0x5a));
+        assert_eq!(sheet.sprites(), 4);
+        assert_eq!(sheet.size(), (19, 13));
+        assert_eq!(sheet.address_at(0, 0), None);
+        assert_eq!(sheet.address_at(1, 1), Some((0x50, 0x50)));
+        assert_eq!(sheet.address_at(12, 11), Some((0x5f, 0x63)));
+        assert_eq!(sheet.address_at(9, 1), None);
+
+        let rgb = sheet.rgb();
+        let row = |y: usize| -> String {
+            rgb[y * 19..(y + 1) * 19]
+                .iter()
+                .map(|c| match *c {
+                    c if c == ON => '#',
+                    c if c == OFF => '.',
+                    c if c == HIGHLIGHT_ON => '@',
+                    c if c == HIGHLIGHT_OFF => ',',
+                    _ => ' ',
+                })
+                .collect()
+        };
+        assert_eq!(row(1), " ####.... ..#..... ");
+        assert_eq!(row(7), " @@@@,,,, ####.... ");
+        assert_eq!(row(8), " ,,,@,,,, ...#.... ");
+
+        let mut png = Vec::new();
+        sheet.write_png(&mut png, 2).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let decoder = png::Decoder::new(&png[..]);
+        let info = decoder.read_info().unwrap();
+        assert_eq!((info.info().width, info.info().height), (38, 26));
+    }
+}
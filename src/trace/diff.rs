@@ -0,0 +1,181 @@
+use std::fmt::Write;
+
+// a logged instruction: the columns of a line like "PC:0200 OP:6005 V0:00 I:0000", in hex
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub number: usize,
+    pub text: String,
+    pub columns: Vec<(String, u16)>,
+}
+
+impl Line {
+    pub fn get(&self, column: &str) -> Option<u16> {
+        self.columns.iter().find(|(c, _)| c == column).map(|(_, v)| *v)
+    }
+}
+
+// emulators name the same things differently
+fn column_name(name: &str) -> String {
+    match name.to_ascii_uppercase().as_str() {
+        "OPCODE" => "OP".to_string(),
+        "INDEX" => "I".to_string(),
+        n => n.to_string(),
+    }
+}
+
+// keeps the lines with a PC column, so headers and other output in a log are skipped
+pub fn parse(text: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    for (n, text) in text.lines().enumerate() {
+        let columns: Vec<(String, u16)> = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter_map(|field| {
+                let (name, value) = field.split_once([':', '='])?;
+                let value = value.trim_start_matches("0x").trim_start_matches('$');
+                Some((column_name(name), u16::from_str_radix(value, 16).ok()?))
+            })
+            .collect();
+        if columns.iter().any(|(c, _)| c == "PC") {
+            lines.push(Line {
+                number: n + 1,
+                text: text.to_string(),
+                columns,
+            });
+        }
+    }
+    lines
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    // the instruction the traces disagree on, counted from 0
+    pub index: usize,
+    // the columns that differ, with the value in each trace; empty when one trace ended early
+    pub columns: Vec<(String, u16, u16)>,
+}
+
+// compares the columns both traces have, instruction by instruction
+pub fn diff(a: &[Line], b: &[Line]) -> Option<Divergence> {
+    for index in 0..a.len().max(b.len()) {
+        let (Some(x), Some(y)) = (a.get(index), b.get(index)) else {
+            return Some(Divergence {
+                index,
+                columns: Vec::new(),
+            });
+        };
+        let columns: Vec<(String, u16, u16)> = x
+            .columns
+            .iter()
+            .filter_map(|(name, value)| {
+                let other = y.get(name)?;
+                (other != *value).then(|| (name.clone(), *value, other))
+            })
+            .collect();
+        if !columns.is_empty() {
+            return Some(Divergence { index, columns });
+        }
+    }
+    None
+}
+
+// the first divergence, with the `context` instructions before it from both traces
+pub fn report(names: (&str, &str), a: &[Line], b: &[Line], context: usize) -> Option<String> {
+    let divergence = diff(a, b)?;
+    let index = divergence.index;
+    let mut out = String::new();
+    match (a.get(index), b.get(index)) {
+        (Some(x), Some(y)) => {
+            let columns: Vec<String> = divergence
+                .columns
+                .iter()
+                .map(|(name, x, y)| format!("{name} {x:02X} != {y:02X}"))
+                .collect();
+            writeln!(
+                out,
+                "traces diverge at instruction {index} ({}:{}, {}:{}): {}",
+                names.0,
+                x.number,
+                names.1,
+                y.number,
+                columns.join(", ")
+            )
+            .unwrap();
+        }
+        (Some(_), None) => writeln!(out, "{} ends after {index} instructions", names.1).unwrap(),
+        _ => writeln!(out, "{} ends after {index} instructions", names.0).unwrap(),
+    }
+    for n in index.saturating_sub(context)..=index {
+        let marker = if n == index { '>' } else { ' ' };
+        for (name, trace) in [(names.0, a), (names.1, b)] {
+            if let Some(line) = trace.get(n) {
+                writeln!(out, "{marker} {name}:{:<6} {}", line.number, line.text).unwrap();
+            }
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Record, Reference, Sink};
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let mut ours = Vec::new();
+        let mut sink = Reference::new(&mut ours);
+        let mut record = Record {
+            cycle: 0,
+            frame: 0,
+            pc: 0x200,
+            opcode: 0x6005,
+            v: [0; 16],
+            i: 0,
+            sp: 0,
+            dt: 0,
+            st: 0,
+        };
+        record.v[0] = 5;
+        sink.write(&record).unwrap();
+        record.pc = 0x202;
+        record.opcode = 0xa300;
+        record.i = 0x300;
+        sink.write(&record).unwrap();
+        record.pc = 0x204;
+        record.opcode = 0x7001;
+        record.v[0] = 6;
+        sink.write(&record).unwrap();
+        let ours = String::from_utf8(ours).unwrap();
+        assert!(ours.starts_with("PC:0200 OP:6005 V0:00 V1:00"));
+        assert!(ours.lines().nth(2).unwrap().ends_with("VF:00 I:0300 SP:00"));
+
+        // another emulator's log, with a header, other names and fewer columns
+        let theirs = "\
+pc      opcode  v0   i
+PC=0x200, OPCODE=0x6005, V0=0x00, INDEX=0x000
+PC=0x202, OPCODE=0xa300, V0=0x05, INDEX=0x000
+PC=0x204, OPCODE=0x7001, V0=0x05, INDEX=0x301
+";
+        let (a, b) = (parse(&ours), parse(theirs));
+        assert_eq!(b[0].number, 2);
+        assert_eq!(b[2].get("I"), Some(0x301));
+        assert_eq!(
+            diff(&a, &b),
+            Some(Divergence {
+                index: 2,
+                columns: vec![("I".to_string(), 0x300, 0x301)]
+            })
+        );
+        assert_eq!(diff(&a[..2], &b[..2]), None);
+        assert_eq!(diff(&a[..2], &b).map(|d| d.index), Some(2));
+
+        let report = report(("ours", "theirs"), &a, &b, 1).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(
+            lines[0],
+            "traces diverge at instruction 2 (ours:3, theirs:4): I 300 != 301"
+        );
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].starts_with("> ours:3      PC:0204"));
+    }
+}
@@ -0,0 +1,363 @@
+#![allow(dead_code)]
+use crate::dbg::parse_number;
+use crate::inst::Instruction;
+use crate::proc::Processor;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+pub mod diff;
+
+static MAGIC: &[u8; 5] = b"C8TR\x01";
+static RECORD_SIZE: usize = 41;
+
+// the state after executing one instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub cycle: u64,
+    // timer ticks so far
+    pub frame: u64,
+    // where the instruction was fetched from
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl Record {
+    pub fn capture(proc: &Processor, pc: usize, cycle: u64, frame: u64) -> Record {
+        Record {
+            cycle,
+            frame,
+            pc: pc as u16,
+            opcode: u16::from_be_bytes(proc.current_instruction.encode()),
+            v: proc.registers.as_array(),
+            i: proc.i,
+            sp: proc.sp as u8,
+            dt: proc.delay_timer,
+            st: proc.sound_timer,
+        }
+    }
+
+    pub fn instruction(&self) -> Option<Instruction> {
+        Instruction::parse(self.opcode.to_be_bytes()).ok()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(RECORD_SIZE);
+        out.extend(self.cycle.to_le_bytes());
+        out.extend(self.frame.to_le_bytes());
+        out.extend(self.pc.to_le_bytes());
+        out.extend(self.opcode.to_le_bytes());
+        out.extend(self.v);
+        out.extend(self.i.to_le_bytes());
+        out.extend([self.sp, self.dt, self.st]);
+        out
+    }
+
+    fn decode(bs: &[u8]) -> Record {
+        let u16_at = |n: usize| u16::from_le_bytes([bs[n], bs[n + 1]]);
+        let u64_at = |n: usize| u64::from_le_bytes(bs[n..n + 8].try_into().unwrap());
+        Record {
+            cycle: u64_at(0),
+            frame: u64_at(8),
+            pc: u16_at(16),
+            opcode: u16_at(18),
+            v: bs[20..36].try_into().unwrap(),
+            i: u16_at(36),
+            sp: bs[38],
+            dt: bs[39],
+            st: bs[40],
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instruction = match self.instruction() {
+            Some(inst) => inst.to_string(),
+            None => "???".to_string(),
+        };
+        write!(
+            f,
+            "{:>8} {:#05x}: {:04x}  {instruction:<16}",
+            self.cycle, self.pc, self.opcode
+        )?;
+        for v in self.v {
+            write!(f, " {v:02x}")?;
+        }
+        write!(
+            f,
+            "  I {:#05x}  SP {}  DT {:02x}  ST {:02x}",
+            self.i, self.sp, self.dt, self.st
+        )
+    }
+}
+
+pub trait Sink: Send {
+    fn write(&mut self, record: &Record) -> io::Result<()>;
+
+    // set by sinks that work out the state before an instruction from the record of the one before it
+    fn needs_every_record(&self) -> bool {
+        false
+    }
+}
+
+// one line per instruction, for people
+pub struct Text<W: Write + Send>(pub W);
+
+impl<W: Write + Send> Sink for Text<W> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        writeln!(self.0, "{record}")
+    }
+}
+
+// one JSON object per line, for scripts
+pub struct JsonLines<W: Write + Send>(pub W);
+
+impl<W: Write + Send> Sink for JsonLines<W> {
+    fn write(&mut self, r: &Record) -> io::Result<()> {
+        let line = json!({
+            "cycle": r.cycle,
+            "frame": r.frame,
+            "pc": r.pc,
+            "opcode": r.opcode,
+            "instruction": r.instruction().map(|i| i.to_string()),
+            "v": r.v,
+            "i": r.i,
+            "sp": r.sp,
+            "dt": r.dt,
+            "st": r.st,
+        });
+        writeln!(self.0, "{line}")
+    }
+}
+
+// the columns reference emulators log, with the state before each instruction: PC:0200 OP:6005 V0:00 .. I:0000 SP:00
+pub struct Reference<W: Write + Send> {
+    out: W,
+    // registers, I and SP after the previous instruction, as they were when this one started
+    before: ([u8; 16], u16, u8),
+}
+
+impl<W: Write + Send> Reference<W> {
+    pub fn new(out: W) -> Self {
+        Reference {
+            out,
+            before: ([0; 16], 0, 0),
+        }
+    }
+}
+
+impl<W: Write + Send> Sink for Reference<W> {
+    fn write(&mut self, r: &Record) -> io::Result<()> {
+        let (v, i, sp) = self.before;
+        write!(self.out, "PC:{:04X} OP:{:04X}", r.pc, r.opcode)?;
+        for (n, v) in v.iter().enumerate() {
+            write!(self.out, " V{n:X}:{v:02X}")?;
+        }
+        writeln!(self.out, " I:{i:04X} SP:{sp:02X}")?;
+        self.before = (r.v, r.i, r.sp);
+        Ok(())
+    }
+
+    fn needs_every_record(&self) -> bool {
+        true
+    }
+}
+
+// fixed size little endian records after a magic number, for long runs
+pub struct Binary<W: Write + Send>(W);
+
+impl<W: Write + Send> Binary<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Binary(out))
+    }
+}
+
+impl<W: Write + Send> Sink for Binary<W> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        self.0.write_all(&record.encode())
+    }
+}
+
+pub fn read_binary(mut input: impl Read) -> io::Result<Vec<Record>> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let records = bytes
+        .strip_prefix(&MAGIC[..])
+        .filter(|r| r.len().is_multiple_of(RECORD_SIZE))
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "not a binary trace"))?;
+    Ok(records.chunks(RECORD_SIZE).map(Record::decode).collect())
+}
+
+// creates a sink by the name of its format
+pub fn sink(format: &str, out: Box<dyn Write + Send>) -> io::Result<Option<Box<dyn Sink>>> {
+    Ok(match format {
+        "text" => Some(Box::new(Text(out))),
+        "jsonl" => Some(Box::new(JsonLines(out))),
+        "bin" => Some(Box::new(Binary::new(out)?)),
+        "ref" => Some(Box::new(Reference::new(out))),
+        _ => None,
+    })
+}
+
+// which records reach the sinks, everything by default
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub pc: Option<Range<u16>>,
+    // a bit for each leading opcode nibble, 1 << 0xD for draws
+    pub classes: Option<u16>,
+    pub frames: Option<Range<u64>>,
+}
+
+impl Filter {
+    pub fn is_set(&self) -> bool {
+        self.pc.is_some() || self.classes.is_some() || self.frames.is_some()
+    }
+
+    pub fn matches(&self, r: &Record) -> bool {
+        self.pc.as_ref().is_none_or(|pc| pc.contains(&r.pc))
+            && self.classes.is_none_or(|c| c & (1 << (r.opcode >> 12)) != 0)
+            && self.frames.as_ref().is_none_or(|f| f.contains(&r.frame))
+    }
+
+    // "0..0x300", the end is exclusive
+    pub fn parse_range(s: &str) -> Option<Range<u64>> {
+        let (start, end) = s.split_once("..")?;
+        Some(parse_number(start)? as u64..parse_number(end)? as u64)
+    }
+
+    // leading opcode digits, like "8,d,f"
+    pub fn parse_classes(s: &str) -> Option<u16> {
+        s.split(',')
+            .map(|c| u8::from_str_radix(c.trim(), 16).ok().filter(|c| *c < 16))
+            .try_fold(0, |classes, c| Some(classes | 1 << c?))
+    }
+}
+
+pub struct Tracer {
+    pub sinks: Vec<Box<dyn Sink>>,
+    pub filter: Filter,
+    // the last instructions, filtered or not, to show what led up to an error
+    ring: VecDeque<Record>,
+    capacity: usize,
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Self {
+        Tracer {
+            sinks: Vec::new(),
+            filter: Filter::default(),
+            ring: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // filtering would leave gaps in what the ref format shows as the state before each instruction
+    pub fn check(&self) -> Result<(), &'static str> {
+        match self.filter.is_set() && self.sinks.iter().any(|s| s.needs_every_record()) {
+            true => Err("the ref trace format shows every instruction and can't be filtered"),
+            false => Ok(()),
+        }
+    }
+
+    pub fn record(&mut self, record: Record) -> io::Result<()> {
+        if self.filter.matches(&record) {
+            for sink in self.sinks.iter_mut() {
+                sink.write(&record)?;
+            }
+        }
+        if self.capacity > 0 {
+            if self.ring.len() == self.capacity {
+                self.ring.pop_front();
+            }
+            self.ring.push_back(record);
+        }
+        Ok(())
+    }
+
+    pub fn recent(&self) -> impl Iterator<Item = &Record> {
+        self.ring.iter()
+    }
+
+    pub fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        for record in self.ring.iter() {
+            writeln!(out, "{record}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Memory;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tracer() {
+        let mut memory = Memory::new();
+        #[rustfmt::skip]
+        memory.load_array(512, &[
+            0x60, 0x05, // ld v0, 5
+            0x81, 0x04, // add v1, v0
+            0xa3, 0x00, // ld i, 0x300
+            0x12, 0x02, // jp 0x202
+        ]).unwrap();
+        let mut proc = Processor::new(memory, Arc::new(Mutex::new([0; 2048])));
+
+        let (text, binary) = (Shared::default(), Shared::default());
+        let mut tracer = Tracer::new(3);
+        tracer.sinks.push(Box::new(Text(text.clone())));
+        tracer.sinks.push(Box::new(Binary::new(binary.clone()).unwrap()));
+        tracer.filter.classes = Filter::parse_classes("8,a");
+        tracer.filter.pc = Filter::parse_range("0..0x204").map(|r| r.start as u16..r.end as u16);
+        for cycle in 0..8 {
+            let pc = proc.pc;
+            proc.execute().unwrap();
+            tracer.record(Record::capture(&proc, pc, cycle, cycle / 3)).unwrap();
+        }
+
+        // add v1, v0 runs three times, ld i only once and is filtered by address
+        let records = read_binary(&binary.0.lock().unwrap()[..]).unwrap();
+        let adds: Vec<u8> = records.iter().map(|r| r.v[1]).collect();
+        assert_eq!(adds, [5, 10, 15]);
+        assert!(records.iter().all(|r| r.opcode == 0x8104));
+        let text = String::from_utf8(text.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(
+            text.starts_with("       1 0x202: 8104  ADD V1, V0       05 05 00"),
+            "{text}"
+        );
+
+        let recent: Vec<u64> = tracer.recent().map(|r| r.cycle).collect();
+        assert_eq!(recent, [5, 6, 7]);
+        tracer.filter.frames = Filter::parse_range("1..2");
+        assert!(!tracer.filter.matches(&records[2]));
+        assert!(tracer.check().is_ok());
+        tracer.sinks.push(Box::new(Reference::new(Shared::default())));
+        assert!(tracer.check().is_err());
+        tracer.filter = Filter::default();
+        assert!(tracer.check().is_ok());
+    }
+}